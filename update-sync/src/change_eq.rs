@@ -0,0 +1,120 @@
+use std::{
+    collections::{BTreeMap, HashMap},
+    hash::{BuildHasher, Hash},
+    marker::PhantomData,
+    num::Wrapping,
+};
+
+/// Decides whether a value has changed, for the purposes of syncronisation
+///
+/// This is kept separate from `PartialEq` so that types can ignore fields which should not count as
+/// edits (such as caches or timestamps), or take part in syncronisation without implementing
+/// `PartialEq` at all. It can be derived with `#[derive(ChangeEq)]` when the `derive` feature is
+/// enabled.
+pub trait ChangeEq {
+    /// Returns true if `self` and `other` should be considered the same value
+    fn change_eq(&self, other: &Self) -> bool;
+}
+
+impl<T: ChangeEq + ?Sized> ChangeEq for &T {
+    fn change_eq(&self, other: &Self) -> bool {
+        T::change_eq(self, other)
+    }
+}
+
+impl<T: ChangeEq + ?Sized> ChangeEq for Box<T> {
+    fn change_eq(&self, other: &Self) -> bool {
+        T::change_eq(self, other)
+    }
+}
+
+impl<T: ChangeEq> ChangeEq for [T] {
+    fn change_eq(&self, other: &Self) -> bool {
+        self.len() == other.len() && self.iter().zip(other).all(|(a, b)| a.change_eq(b))
+    }
+}
+
+impl<T: ChangeEq> ChangeEq for Vec<T> {
+    fn change_eq(&self, other: &Self) -> bool {
+        self[..].change_eq(&other[..])
+    }
+}
+
+impl<T: ChangeEq> ChangeEq for Option<T> {
+    fn change_eq(&self, other: &Self) -> bool {
+        match (self, other) {
+            (Some(a), Some(b)) => a.change_eq(b),
+            (None, None) => true,
+            _ => false,
+        }
+    }
+}
+
+impl<T: ChangeEq, E: ChangeEq> ChangeEq for Result<T, E> {
+    fn change_eq(&self, other: &Self) -> bool {
+        match (self, other) {
+            (Ok(a), Ok(b)) => a.change_eq(b),
+            (Err(a), Err(b)) => a.change_eq(b),
+            _ => false,
+        }
+    }
+}
+
+impl<T: ChangeEq> ChangeEq for Wrapping<T> {
+    fn change_eq(&self, other: &Self) -> bool {
+        self.0.change_eq(&other.0)
+    }
+}
+
+impl<T: ?Sized> ChangeEq for PhantomData<T> {
+    fn change_eq(&self, _: &Self) -> bool {
+        true
+    }
+}
+
+macro_rules! tuple_impl_change_eq {
+    ($($t:ident : $i:tt),+) => {
+        impl<$($t),+> ChangeEq for ($($t,)+)
+        where
+        $(
+            $t: ChangeEq,
+        )*
+        {
+            fn change_eq(&self, other: &Self) -> bool {
+                $(
+                    self.$i.change_eq(&other.$i)
+                )&&*
+            }
+        }
+    }
+}
+
+tuple_impl_change_eq!(T1: 0);
+tuple_impl_change_eq!(T1: 0, T2: 1);
+tuple_impl_change_eq!(T1: 0, T2: 1, T3: 2);
+tuple_impl_change_eq!(T1: 0, T2: 1, T3: 2, T4: 3);
+tuple_impl_change_eq!(T1: 0, T2: 1, T3: 2, T4: 3, T5: 4);
+tuple_impl_change_eq!(T1: 0, T2: 1, T3: 2, T4: 3, T5: 4, T6: 5);
+tuple_impl_change_eq!(T1: 0, T2: 1, T3: 2, T4: 3, T5: 4, T6: 5, T7: 6);
+tuple_impl_change_eq!(T1: 0, T2: 1, T3: 2, T4: 3, T5: 4, T6: 5, T7: 6, T8: 7);
+tuple_impl_change_eq!(T1: 0, T2: 1, T3: 2, T4: 3, T5: 4, T6: 5, T7: 6, T8: 7, T9: 8);
+tuple_impl_change_eq!(T1: 0, T2: 1, T3: 2, T4: 3, T5: 4, T6: 5, T7: 6, T8: 7, T9: 8, T10 : 9);
+
+impl<K: Ord, V: ChangeEq> ChangeEq for BTreeMap<K, V> {
+    fn change_eq(&self, other: &Self) -> bool {
+        self.len() == other.len()
+            && self
+                .iter()
+                .zip(other)
+                .all(|((ak, av), (bk, bv))| ak == bk && av.change_eq(bv))
+    }
+}
+
+impl<K: Hash + Eq, V: ChangeEq, S: BuildHasher> ChangeEq for HashMap<K, V, S> {
+    fn change_eq(&self, other: &Self) -> bool {
+        self.len() == other.len()
+            && self
+                .iter()
+                .all(|(k, av)| other.get(k).is_some_and(|bv| av.change_eq(bv)))
+    }
+}
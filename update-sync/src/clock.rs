@@ -0,0 +1,157 @@
+//! Hybrid logical clocks
+//!
+//! A hybrid logical clock produces timestamps which stay close to wall clock time, but which never
+//! go backwards and always order an event after any event it has observed, even when replicas'
+//! wall clocks disagree. The wall clock is read through a [`TimeSource`] so it can be controlled in
+//! tests.
+
+use std::{
+    sync::{
+        atomic::{AtomicU64, Ordering},
+        Arc,
+    },
+    time::{SystemTime, UNIX_EPOCH},
+};
+
+use crate::{ChangeEq, UpdateSync};
+
+/// Identifies a replica, such as a client or server, which makes changes
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq, PartialOrd, Ord, Hash)]
+pub struct ReplicaId(pub u64);
+
+/// A point in hybrid logical time
+///
+/// Timestamps are ordered by their wall clock time, then by their logical counter.
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq, PartialOrd, Ord, Hash)]
+pub struct Timestamp {
+    /// Milliseconds since the unix epoch
+    pub wall: u64,
+    /// Orders events which happened within the same millisecond
+    pub logical: u32,
+}
+
+impl Timestamp {
+    /// The timestamp straight after this one, moving on to the next millisecond once the logical
+    /// counter runs out
+    fn tick(self) -> Timestamp {
+        match self.logical.checked_add(1) {
+            Some(logical) => Timestamp {
+                wall: self.wall,
+                logical,
+            },
+            None => Timestamp {
+                wall: self.wall + 1,
+                logical: 0,
+            },
+        }
+    }
+}
+
+default_impl_update_sync![ReplicaId, Timestamp];
+
+/// Provides the current wall clock time
+pub trait TimeSource {
+    /// Milliseconds since the unix epoch
+    fn now_millis(&self) -> u64;
+}
+
+/// Reads the system clock
+#[derive(Clone, Copy, Debug, Default)]
+pub struct SystemTimeSource;
+
+impl TimeSource for SystemTimeSource {
+    fn now_millis(&self) -> u64 {
+        SystemTime::now()
+            .duration_since(UNIX_EPOCH)
+            .map_or(0, |since| since.as_millis() as u64)
+    }
+}
+
+/// A time source which only moves when told to, clones share the same time
+#[derive(Clone, Debug, Default)]
+pub struct ManualTimeSource(Arc<AtomicU64>);
+
+impl ManualTimeSource {
+    pub fn new(millis: u64) -> Self {
+        ManualTimeSource(Arc::new(AtomicU64::new(millis)))
+    }
+
+    pub fn set(&self, millis: u64) {
+        self.0.store(millis, Ordering::SeqCst);
+    }
+
+    pub fn advance(&self, millis: u64) {
+        self.0.fetch_add(millis, Ordering::SeqCst);
+    }
+}
+
+impl TimeSource for ManualTimeSource {
+    fn now_millis(&self) -> u64 {
+        self.0.load(Ordering::SeqCst)
+    }
+}
+
+/// A hybrid logical clock belonging to a single replica
+#[derive(Clone, Debug)]
+pub struct Clock<S = SystemTimeSource> {
+    source: S,
+    replica: ReplicaId,
+    last: Timestamp,
+}
+
+impl Clock {
+    /// A clock reading the system time
+    pub fn new(replica: ReplicaId) -> Self {
+        Clock::with_source(replica, SystemTimeSource)
+    }
+}
+
+impl<S: TimeSource> Clock<S> {
+    pub fn with_source(replica: ReplicaId, source: S) -> Self {
+        Clock {
+            source,
+            replica,
+            last: Timestamp::default(),
+        }
+    }
+
+    pub fn replica(&self) -> ReplicaId {
+        self.replica
+    }
+
+    /// The most recent timestamp produced or observed
+    pub fn last(&self) -> Timestamp {
+        self.last
+    }
+
+    /// Produces a timestamp for a local event, later than every timestamp before it
+    pub fn now(&mut self) -> Timestamp {
+        let wall = self.source.now_millis();
+        self.last = if wall > self.last.wall {
+            Timestamp { wall, logical: 0 }
+        } else {
+            self.last.tick()
+        };
+        self.last
+    }
+
+    /// Observes a timestamp from another replica, so that every later timestamp from this clock
+    /// is ordered after it, and returns the timestamp of receiving it
+    pub fn observe(&mut self, remote: Timestamp) -> Timestamp {
+        let wall = self
+            .source
+            .now_millis()
+            .max(self.last.wall)
+            .max(remote.wall);
+        self.last = if wall == self.last.wall && wall == remote.wall {
+            self.last.max(remote).tick()
+        } else if wall == self.last.wall {
+            self.last.tick()
+        } else if wall == remote.wall {
+            remote.tick()
+        } else {
+            Timestamp { wall, logical: 0 }
+        };
+        self.last
+    }
+}
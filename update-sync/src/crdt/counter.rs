@@ -0,0 +1,74 @@
+use std::collections::{btree_map::Entry, BTreeMap};
+
+use super::Join;
+use crate::clock::ReplicaId;
+
+/// A counter which can only grow
+#[derive(Clone, Debug, Default, PartialEq, Eq, Hash)]
+pub struct GCounter(BTreeMap<ReplicaId, u64>);
+
+impl GCounter {
+    pub fn new() -> Self {
+        GCounter::default()
+    }
+
+    pub fn increment(&mut self, replica: ReplicaId, by: u64) {
+        *self.0.entry(replica).or_insert(0) += by;
+    }
+
+    pub fn value(&self) -> u64 {
+        self.0.values().sum()
+    }
+}
+
+impl Join for GCounter {
+    fn join(&mut self, other: Self) {
+        for (replica, count) in other.0 {
+            match self.0.entry(replica) {
+                Entry::Vacant(entry) => {
+                    entry.insert(count);
+                }
+                Entry::Occupied(mut entry) => {
+                    let existing = entry.get_mut();
+                    *existing = (*existing).max(count);
+                }
+            }
+        }
+    }
+}
+
+join_impl_update_sync!(impl<> for GCounter);
+
+/// A counter which can grow and shrink
+#[derive(Clone, Debug, Default, PartialEq, Eq, Hash)]
+pub struct PnCounter {
+    increments: GCounter,
+    decrements: GCounter,
+}
+
+impl PnCounter {
+    pub fn new() -> Self {
+        PnCounter::default()
+    }
+
+    pub fn increment(&mut self, replica: ReplicaId, by: u64) {
+        self.increments.increment(replica, by);
+    }
+
+    pub fn decrement(&mut self, replica: ReplicaId, by: u64) {
+        self.decrements.increment(replica, by);
+    }
+
+    pub fn value(&self) -> i128 {
+        self.increments.value() as i128 - self.decrements.value() as i128
+    }
+}
+
+impl Join for PnCounter {
+    fn join(&mut self, other: Self) {
+        self.increments.join(other.increments);
+        self.decrements.join(other.decrements);
+    }
+}
+
+join_impl_update_sync!(impl<> for PnCounter);
@@ -0,0 +1,77 @@
+use std::collections::{btree_map::Entry, BTreeMap};
+
+use super::Join;
+use crate::{
+    clock::{Clock, TimeSource},
+    lww::Lww,
+};
+
+/// A map where each key holds the most recently written value
+///
+/// Removed keys are kept as timestamped tombstones, so that a removal can win over an older
+/// insert.
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct LwwMap<K: Ord, V> {
+    entries: BTreeMap<K, Lww<Option<V>>>,
+}
+
+impl<K: Ord, V> Default for LwwMap<K, V> {
+    fn default() -> Self {
+        LwwMap {
+            entries: BTreeMap::new(),
+        }
+    }
+}
+
+impl<K: Ord, V> LwwMap<K, V> {
+    pub fn new() -> Self {
+        LwwMap::default()
+    }
+
+    pub fn insert<S: TimeSource>(&mut self, key: K, value: V, clock: &mut Clock<S>) {
+        self.write(key, Some(value), clock);
+    }
+
+    pub fn remove<S: TimeSource>(&mut self, key: K, clock: &mut Clock<S>) {
+        self.write(key, None, clock);
+    }
+
+    fn write<S: TimeSource>(&mut self, key: K, value: Option<V>, clock: &mut Clock<S>) {
+        match self.entries.entry(key) {
+            Entry::Vacant(entry) => {
+                entry.insert(Lww::new(value, clock));
+            }
+            Entry::Occupied(mut entry) => entry.get_mut().set(value, clock),
+        }
+    }
+
+    pub fn get(&self, key: &K) -> Option<&V> {
+        self.entries.get(key).and_then(|entry| entry.get().as_ref())
+    }
+
+    pub fn contains_key(&self, key: &K) -> bool {
+        self.get(key).is_some()
+    }
+
+    /// Iterates over the keys which are present, skipping tombstones
+    pub fn iter(&self) -> impl Iterator<Item = (&K, &V)> {
+        self.entries
+            .iter()
+            .filter_map(|(key, entry)| entry.get().as_ref().map(|value| (key, value)))
+    }
+}
+
+impl<K: Ord, V> Join for LwwMap<K, V> {
+    fn join(&mut self, other: Self) {
+        for (key, theirs) in other.entries {
+            match self.entries.entry(key) {
+                Entry::Vacant(entry) => {
+                    entry.insert(theirs);
+                }
+                Entry::Occupied(mut entry) => entry.get_mut().join(theirs),
+            }
+        }
+    }
+}
+
+join_impl_update_sync!(impl<K, V> for LwwMap<K, V> where K: Ord, V: PartialEq);
@@ -0,0 +1,60 @@
+//! State based CRDTs
+//!
+//! These types don't need a shared `last_base` to sync, any two replicas can be combined with
+//! [`Join::join`], which is commutative, associative and idempotent, so peers can exchange states
+//! in any order, any number of times, and still converge.
+//!
+//! Each type also implements `UpdateSync` by joining `new_base` with `set`, ignoring `last_base`,
+//! so they can be used as fields of `#[derive(UpdateSync)]` structs.
+
+use crate::{clock::ReplicaId, lww::Lww, version::VersionVector};
+
+mod counter;
+mod lww_map;
+mod mv_register;
+mod or_set;
+mod sequence;
+
+pub use counter::{GCounter, PnCounter};
+pub use lww_map::LwwMap;
+pub use mv_register::MvRegister;
+pub use or_set::OrSet;
+pub use sequence::{ElementId, Sequence, Text};
+
+/// Merges the state of another replica into this one
+pub trait Join {
+    /// Must be commutative, associative and idempotent
+    fn join(&mut self, other: Self);
+}
+
+/// A unique event, the `counter`th made by `replica`
+#[derive(Clone, Copy, Debug, PartialEq, Eq, PartialOrd, Ord, Hash)]
+pub struct Dot {
+    pub replica: ReplicaId,
+    pub counter: u64,
+}
+
+impl Dot {
+    /// Creates the next event by `replica`, recording it in `version`
+    pub fn next(version: &mut VersionVector, replica: ReplicaId) -> Self {
+        Dot {
+            replica,
+            counter: version.increment(replica),
+        }
+    }
+}
+
+impl Join for VersionVector {
+    fn join(&mut self, other: Self) {
+        VersionVector::join(self, &other)
+    }
+}
+
+impl<T> Join for Lww<T> {
+    fn join(&mut self, other: Self) {
+        use crate::lww::Stamped;
+        if other.stamp() > self.stamp() {
+            *self = other;
+        }
+    }
+}
@@ -0,0 +1,80 @@
+use super::Join;
+use crate::{
+    clock::ReplicaId,
+    version::{Causality, VersionVector},
+};
+
+/// A multi-value register, which keeps every concurrently written value
+///
+/// Writes which have seen another write replace it, while concurrent writes are all kept until a
+/// later write resolves them.
+#[derive(Clone, Debug, PartialEq)]
+pub struct MvRegister<T> {
+    values: Vec<(VersionVector, T)>,
+}
+
+impl<T> Default for MvRegister<T> {
+    fn default() -> Self {
+        MvRegister { values: Vec::new() }
+    }
+}
+
+impl<T> MvRegister<T> {
+    pub fn new() -> Self {
+        MvRegister::default()
+    }
+
+    /// Replaces every value this replica has seen
+    pub fn set(&mut self, replica: ReplicaId, value: T) {
+        let mut version = VersionVector::new();
+        for (seen, _) in &self.values {
+            version.join(seen);
+        }
+        version.increment(replica);
+        self.values = vec![(version, value)];
+    }
+
+    /// The concurrently written values, empty if the register has never been written
+    ///
+    /// Values are ordered by the writes they have seen, which is the same on every replica.
+    pub fn values(&self) -> impl Iterator<Item = &T> {
+        self.values.iter().map(|(_, value)| value)
+    }
+
+    /// Returns true if concurrent writes are waiting to be resolved
+    pub fn is_conflicted(&self) -> bool {
+        self.values.len() > 1
+    }
+}
+
+impl<T> Join for MvRegister<T> {
+    fn join(&mut self, other: Self) {
+        let mut values = std::mem::take(&mut self.values);
+        values.extend(other.values);
+        // Drop anything which a later write has seen, and duplicates of the same write
+        let superseded: Vec<bool> = values
+            .iter()
+            .enumerate()
+            .map(|(i, (version, _))| {
+                values
+                    .iter()
+                    .enumerate()
+                    .any(|(j, (other, _))| match version.compare(other) {
+                        Causality::Before => true,
+                        Causality::Equal => j < i,
+                        _ => false,
+                    })
+            })
+            .collect();
+        self.values = values
+            .into_iter()
+            .zip(superseded)
+            .filter_map(|(value, superseded)| if superseded { None } else { Some(value) })
+            .collect();
+        // Keep a consistent order, so replicas which have joined the same writes are equal
+        self.values
+            .sort_by_cached_key(|(version, _)| version.iter().collect::<Vec<_>>());
+    }
+}
+
+join_impl_update_sync!(impl<T> for MvRegister<T> where T: PartialEq);
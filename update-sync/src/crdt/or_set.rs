@@ -0,0 +1,79 @@
+use std::collections::{BTreeMap, BTreeSet};
+
+use super::{Dot, Join};
+use crate::{clock::ReplicaId, version::VersionVector};
+
+/// An observed-remove set, where an add concurrent with a remove wins
+///
+/// Every add is tagged with a unique [`Dot`], and a remove only removes the adds it has observed.
+/// Removes keep no tombstones: an add missing from a replica whose version includes its dot must
+/// have been removed there, so the set only grows with the values it holds.
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct OrSet<T: Ord> {
+    version: VersionVector,
+    adds: BTreeMap<T, BTreeSet<Dot>>,
+}
+
+impl<T: Ord> Default for OrSet<T> {
+    fn default() -> Self {
+        OrSet {
+            version: VersionVector::new(),
+            adds: BTreeMap::new(),
+        }
+    }
+}
+
+impl<T: Ord> OrSet<T> {
+    pub fn new() -> Self {
+        OrSet::default()
+    }
+
+    pub fn insert(&mut self, replica: ReplicaId, value: T) {
+        let dot = Dot::next(&mut self.version, replica);
+        self.adds.entry(value).or_default().insert(dot);
+    }
+
+    /// Removes every add of `value` this replica has seen, returning true if there were any
+    pub fn remove(&mut self, value: &T) -> bool {
+        self.adds.remove(value).is_some()
+    }
+
+    pub fn contains(&self, value: &T) -> bool {
+        self.adds.contains_key(value)
+    }
+
+    pub fn iter(&self) -> impl Iterator<Item = &T> {
+        self.adds.keys()
+    }
+
+    pub fn len(&self) -> usize {
+        self.adds.len()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.adds.is_empty()
+    }
+}
+
+impl<T: Ord> Join for OrSet<T> {
+    fn join(&mut self, mut other: Self) {
+        // Keeps the adds both replicas hold, and those only one holds which the other hasn't seen
+        let unseen = |version: &VersionVector, dot: &Dot| dot.counter > version.get(dot.replica);
+        let version = &self.version;
+        self.adds.retain(|value, dots| {
+            let theirs = other.adds.remove(value).unwrap_or_default();
+            dots.retain(|dot| theirs.contains(dot) || unseen(&other.version, dot));
+            dots.extend(theirs.into_iter().filter(|dot| unseen(version, dot)));
+            !dots.is_empty()
+        });
+        for (value, mut dots) in other.adds {
+            dots.retain(|dot| unseen(&self.version, dot));
+            if !dots.is_empty() {
+                self.adds.insert(value, dots);
+            }
+        }
+        self.version.join(&other.version);
+    }
+}
+
+join_impl_update_sync!(impl<T> for OrSet<T> where T: Ord);
@@ -0,0 +1,229 @@
+use std::{collections::BTreeSet, fmt};
+
+use super::Join;
+use crate::clock::ReplicaId;
+
+/// Uniquely identifies an element of a [`Sequence`]
+///
+/// The counter is a Lamport clock, so an element's id is always greater than the id of every
+/// element which existed when it was inserted.
+#[derive(Clone, Copy, Debug, PartialEq, Eq, PartialOrd, Ord, Hash)]
+pub struct ElementId {
+    pub counter: u64,
+    pub replica: ReplicaId,
+}
+
+#[derive(Clone, Debug, PartialEq)]
+struct Element<T> {
+    id: ElementId,
+    /// The element this was inserted after, or `None` for the start of the sequence
+    origin: Option<ElementId>,
+    value: T,
+    deleted: bool,
+}
+
+/// A list which can be edited concurrently by many replicas, using the RGA algorithm
+///
+/// Every element has a stable id, and deleted elements are kept as tombstones so that concurrent
+/// inserts next to them still have somewhere to go. Concurrent inserts at the same position are
+/// ordered by their ids, so every replica which has seen the same edits holds the same list.
+///
+/// A `Sequence<char>`, aliased as [`Text`], can be used for collaboratively edited strings.
+#[derive(Clone, Debug, PartialEq)]
+pub struct Sequence<T> {
+    clock: u64,
+    elements: Vec<Element<T>>,
+}
+
+/// A collaboratively edited string
+pub type Text = Sequence<char>;
+
+impl<T> Default for Sequence<T> {
+    fn default() -> Self {
+        Sequence {
+            clock: 0,
+            elements: Vec::new(),
+        }
+    }
+}
+
+impl<T> Sequence<T> {
+    pub fn new() -> Self {
+        Sequence::default()
+    }
+
+    /// Creates a sequence holding `values`, inserted by `replica`
+    ///
+    /// Replicas should share a single sequence created this way, rather than each creating their
+    /// own from the same values, as the elements of each would have different ids.
+    pub fn from_vec(replica: ReplicaId, values: Vec<T>) -> Self {
+        let mut sequence = Sequence::new();
+        for value in values {
+            sequence.push(replica, value);
+        }
+        sequence
+    }
+
+    /// The number of elements, not counting tombstones
+    pub fn len(&self) -> usize {
+        self.elements.iter().filter(|e| !e.deleted).count()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.len() == 0
+    }
+
+    pub fn iter(&self) -> impl Iterator<Item = &T> {
+        self.elements
+            .iter()
+            .filter(|e| !e.deleted)
+            .map(|e| &e.value)
+    }
+
+    pub fn get(&self, index: usize) -> Option<&T> {
+        self.iter().nth(index)
+    }
+
+    /// The position in `elements` of the `index`th element which isn't a tombstone
+    fn raw_index(&self, index: usize) -> Option<usize> {
+        self.elements
+            .iter()
+            .enumerate()
+            .filter(|(_, e)| !e.deleted)
+            .map(|(raw, _)| raw)
+            .nth(index)
+    }
+
+    fn position(&self, id: ElementId) -> Option<usize> {
+        self.elements.iter().position(|e| e.id == id)
+    }
+
+    /// Inserts `value` so that it becomes the `index`th element
+    ///
+    /// # Panics
+    ///
+    /// Panics if `index` is greater than the length of the sequence.
+    pub fn insert(&mut self, replica: ReplicaId, index: usize, value: T) {
+        let origin = match index {
+            0 => None,
+            _ => {
+                let raw = self
+                    .raw_index(index - 1)
+                    .expect("insertion index out of bounds");
+                Some(self.elements[raw].id)
+            }
+        };
+        self.clock += 1;
+        self.integrate(Element {
+            id: ElementId {
+                counter: self.clock,
+                replica,
+            },
+            origin,
+            value,
+            deleted: false,
+        });
+    }
+
+    pub fn push(&mut self, replica: ReplicaId, value: T) {
+        self.insert(replica, self.len(), value);
+    }
+
+    /// Deletes the `index`th element, returning a reference to its value
+    pub fn remove(&mut self, index: usize) -> Option<&T> {
+        let raw = self.raw_index(index)?;
+        let element = &mut self.elements[raw];
+        element.deleted = true;
+        Some(&element.value)
+    }
+
+    /// Places an element which isn't yet in the sequence after its origin, skipping past any
+    /// elements inserted there concurrently with a greater id
+    fn integrate(&mut self, element: Element<T>) {
+        self.clock = self.clock.max(element.id.counter);
+        let mut raw = match element.origin {
+            None => 0,
+            Some(origin) => {
+                self.position(origin)
+                    .expect("elements are integrated after their origin")
+                    + 1
+            }
+        };
+        while raw < self.elements.len() && self.elements[raw].id > element.id {
+            raw += 1;
+        }
+        self.elements.insert(raw, element);
+    }
+}
+
+impl<T: Clone> Sequence<T> {
+    pub fn to_vec(&self) -> Vec<T> {
+        self.iter().cloned().collect()
+    }
+}
+
+impl<T: PartialEq> Sequence<T> {
+    /// Edits the sequence to hold `values`, as a single deletion and insertion between the
+    /// longest common prefix and suffix
+    ///
+    /// This lets a sequence be edited by replacing its whole contents, while keeping the ids of
+    /// the elements which didn't change.
+    pub fn set_from(&mut self, replica: ReplicaId, values: impl IntoIterator<Item = T>) {
+        let values: Vec<T> = values.into_iter().collect();
+        let current: Vec<&T> = self.iter().collect();
+        let len = current.len();
+        let prefix = current
+            .iter()
+            .zip(&values)
+            .take_while(|(a, b)| **a == *b)
+            .count();
+        let suffix = current
+            .iter()
+            .rev()
+            .zip(values.iter().rev())
+            .take(len.min(values.len()) - prefix)
+            .take_while(|(a, b)| **a == *b)
+            .count();
+        for _ in prefix..len - suffix {
+            self.remove(prefix);
+        }
+        let inserted = values.len() - suffix;
+        for (offset, value) in values.into_iter().take(inserted).skip(prefix).enumerate() {
+            self.insert(replica, prefix + offset, value);
+        }
+    }
+}
+
+impl Sequence<char> {
+    pub fn from_text(replica: ReplicaId, text: &str) -> Self {
+        Sequence::from_vec(replica, text.chars().collect())
+    }
+}
+
+impl fmt::Display for Sequence<char> {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        self.iter().try_for_each(|c| fmt::Write::write_char(f, *c))
+    }
+}
+
+impl<T> Join for Sequence<T> {
+    fn join(&mut self, other: Self) {
+        let deleted: BTreeSet<ElementId> = other
+            .elements
+            .iter()
+            .filter(|e| e.deleted)
+            .map(|e| e.id)
+            .collect();
+        // An element always comes after its origin, so integrating in order finds every origin
+        for element in other.elements {
+            if self.position(element.id).is_none() {
+                self.integrate(element);
+            }
+        }
+        for element in &mut self.elements {
+            element.deleted |= deleted.contains(&element.id);
+        }
+    }
+}
+
+join_impl_update_sync!(impl<T> for Sequence<T> where T: PartialEq);
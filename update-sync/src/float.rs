@@ -0,0 +1,86 @@
+//! Change detection for floating point values
+//!
+//! `f32` and `f64` treat any two `NaN`s as the same value, so an unchanged `NaN` does not
+//! overwrite `new_base`. Fields which pass through lossy encodings can opt into a tolerance with
+//! [`update_sync_epsilon`] or [`update_sync_ulps`], which are also available through the
+//! `#[update_sync(epsilon = ...)]` and `#[update_sync(ulps = ...)]` derive attributes.
+
+use crate::{ChangeEq, UpdateSync};
+
+mod sealed {
+    pub trait Sealed {}
+    impl Sealed for f32 {}
+    impl Sealed for f64 {}
+}
+
+/// The floating point types supported by the tolerance aware update functions
+pub trait Float: Copy + PartialOrd + sealed::Sealed {
+    /// Returns true if the two values should be considered the same, treating `NaN` as equal to
+    /// itself
+    fn same(self, other: Self) -> bool;
+
+    /// Returns true if the two values are within `epsilon` of each other
+    fn within_epsilon(self, other: Self, epsilon: Self) -> bool;
+
+    /// Returns the number of representable values between `self` and `other`
+    fn ulps_between(self, other: Self) -> u64;
+}
+
+macro_rules! float_impl {
+    ($f:ty, $i:ty) => {
+        impl Float for $f {
+            fn same(self, other: Self) -> bool {
+                self == other || (self.is_nan() && other.is_nan())
+            }
+
+            fn within_epsilon(self, other: Self, epsilon: Self) -> bool {
+                self.same(other) || (self - other).abs() <= epsilon
+            }
+
+            fn ulps_between(self, other: Self) -> u64 {
+                // Maps the bit patterns onto a single number line, so that -0.0 and 0.0 meet
+                fn ordered(f: $f) -> i64 {
+                    let bits = f.to_bits() as $i;
+                    if bits < 0 {
+                        <$i>::MIN.wrapping_sub(bits) as i64
+                    } else {
+                        bits as i64
+                    }
+                }
+                if self.is_nan() || other.is_nan() {
+                    return if self.same(other) { 0 } else { u64::MAX };
+                }
+                (ordered(self) as i128 - ordered(other) as i128).unsigned_abs() as u64
+            }
+        }
+
+        impl ChangeEq for $f {
+            fn change_eq(&self, other: &Self) -> bool {
+                self.same(*other)
+            }
+        }
+        change_eq_impl_update_sync!($f);
+    };
+}
+float_impl!(f32, i32);
+float_impl!(f64, i64);
+
+/// Updates a float, only considering `set` changed if it differs from `last_base` by more than
+/// `epsilon`
+pub fn update_sync_epsilon<F: Float>(last_base: F, new_base: F, set: F, epsilon: F) -> F {
+    if !last_base.within_epsilon(set, epsilon) {
+        set
+    } else {
+        new_base
+    }
+}
+
+/// Updates a float, only considering `set` changed if it is more than `ulps` representable values
+/// away from `last_base`
+pub fn update_sync_ulps<F: Float>(last_base: F, new_base: F, set: F, ulps: u64) -> F {
+    if last_base.ulps_between(set) > ulps {
+        set
+    } else {
+        new_base
+    }
+}
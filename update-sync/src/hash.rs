@@ -0,0 +1,506 @@
+//! Structural hashes for cheap change detection
+//!
+//! [`SyncHash`] gives every value a hash which is stable between runs, platforms and processes,
+//! so it can be stored, or sent between a client and a server. Structs, enums, lists and maps also
+//! have a [`HashTree`], holding the hash of every subtree, and the hash of each value is computed
+//! from the hashes of its children.
+//!
+//! Comparing the hashes of two values tells whether anything in them differs. Comparing their
+//! hash trees, with [`HashTree::changed`], tells where, so a client and server can exchange hash
+//! trees, or the top few levels of them with [`HashTree::truncated`], to find the changed subtrees
+//! before sending any data.
+//!
+//! `SyncHash` can be derived alongside `UpdateSync` with `#[derive(SyncHash)]`.
+
+use std::{
+    borrow::Cow,
+    collections::{BTreeMap, HashMap},
+    ffi::OsString,
+    fmt::Debug,
+    marker::PhantomData,
+    net::{IpAddr, Ipv4Addr, Ipv6Addr, SocketAddr, SocketAddrV4, SocketAddrV6},
+    num::{
+        NonZeroI128, NonZeroI16, NonZeroI32, NonZeroI64, NonZeroI8, NonZeroIsize, NonZeroU128,
+        NonZeroU16, NonZeroU32, NonZeroU64, NonZeroU8, NonZeroUsize, Wrapping,
+    },
+    path::PathBuf,
+    time::{Duration, SystemTime, UNIX_EPOCH},
+};
+
+use crate::{
+    path::{Path, PathSegment},
+    visit::Segment,
+    UpdateSync,
+};
+
+/// A hash of a value's structure and contents which is stable between runs and platforms
+pub trait SyncHash {
+    fn sync_hash(&self) -> u64;
+
+    /// The hashes of every subtree of the value, a single leaf by default
+    ///
+    /// Its hash must be the same as `sync_hash`.
+    fn hash_tree(&self) -> HashTree {
+        HashTree::leaf(self.sync_hash())
+    }
+}
+
+/// A 64 bit FNV-1a hasher, which gives the same hash for the same bytes everywhere
+#[derive(Clone, Debug)]
+pub struct SyncHasher(u64);
+
+impl Default for SyncHasher {
+    fn default() -> Self {
+        SyncHasher(0xcbf2_9ce4_8422_2325)
+    }
+}
+
+impl SyncHasher {
+    pub fn new() -> Self {
+        SyncHasher::default()
+    }
+
+    pub fn write(&mut self, bytes: &[u8]) {
+        for byte in bytes {
+            self.0 ^= u64::from(*byte);
+            self.0 = self.0.wrapping_mul(0x0100_0000_01b3);
+        }
+    }
+
+    pub fn write_u64(&mut self, value: u64) {
+        self.write(&value.to_le_bytes());
+    }
+
+    pub fn finish(&self) -> u64 {
+        self.0
+    }
+}
+
+/// Hashes a value with children from the hashes of those children and where they are
+pub fn node_hash(children: &[(PathSegment, u64)]) -> u64 {
+    let mut hasher = SyncHasher::new();
+    hasher.write(b"node");
+    hasher.write_u64(children.len() as u64);
+    for (segment, hash) in children {
+        let token = segment.token();
+        hasher.write_u64(token.len() as u64);
+        hasher.write(token.as_bytes());
+        hasher.write_u64(*hash);
+    }
+    hasher.finish()
+}
+
+/// The hash of a value, along with the hash trees of its children
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct HashTree {
+    hash: u64,
+    children: Vec<(PathSegment, HashTree)>,
+}
+
+impl HashTree {
+    /// The tree of a value with no children
+    pub fn leaf(hash: u64) -> Self {
+        HashTree {
+            hash,
+            children: Vec::new(),
+        }
+    }
+
+    /// The tree of a value with the given children, hashed with [`node_hash`]
+    pub fn node(children: Vec<(PathSegment, HashTree)>) -> Self {
+        let hashes: Vec<_> = children
+            .iter()
+            .map(|(segment, tree)| (segment.clone(), tree.hash))
+            .collect();
+        HashTree {
+            hash: node_hash(&hashes),
+            children,
+        }
+    }
+
+    pub fn hash(&self) -> u64 {
+        self.hash
+    }
+
+    pub fn children(&self) -> &[(PathSegment, HashTree)] {
+        &self.children
+    }
+
+    /// The tree of the value at `path` inside this one, if this tree reaches it
+    pub fn get(&self, path: &Path) -> Option<&HashTree> {
+        path.segments().iter().try_fold(self, |tree, segment| {
+            tree.children
+                .iter()
+                .find(|(child, _)| child == segment)
+                .map(|(_, tree)| tree)
+        })
+    }
+
+    /// This tree without anything more than `depth` levels below it, for sending a few levels of
+    /// a large tree at a time
+    pub fn truncated(&self, depth: usize) -> HashTree {
+        HashTree {
+            hash: self.hash,
+            children: match depth {
+                0 => Vec::new(),
+                depth => self
+                    .children
+                    .iter()
+                    .map(|(segment, tree)| (segment.clone(), tree.truncated(depth - 1)))
+                    .collect(),
+            },
+        }
+    }
+
+    /// The paths of the smallest subtrees which differ between the two trees
+    ///
+    /// Where either tree stops, such as at a truncated subtree, or a child only one of them has,
+    /// the whole subtree is reported.
+    pub fn changed(&self, other: &HashTree) -> Vec<Path> {
+        let mut changed = Vec::new();
+        self.changed_into(other, &mut Path::new(), &mut changed);
+        changed
+    }
+
+    fn changed_into(&self, other: &HashTree, path: &mut Path, changed: &mut Vec<Path>) {
+        if self.hash == other.hash {
+            return;
+        }
+        if self.children.is_empty() || other.children.is_empty() {
+            return changed.push(path.clone());
+        }
+        for (segment, tree) in &self.children {
+            path.push(segment.clone());
+            match other.children.iter().find(|(other, _)| other == segment) {
+                Some((_, other)) => tree.changed_into(other, path, changed),
+                None => changed.push(path.clone()),
+            }
+            path.pop();
+        }
+        for (segment, _) in &other.children {
+            if !self.children.iter().any(|(own, _)| own == segment) {
+                changed.push(path.join(segment.clone()));
+            }
+        }
+    }
+}
+
+/// Updates as `update_sync` would, skipping the update where either side is unchanged
+///
+/// This is worthwhile where hashes are cheaper than comparisons, such as for types whose hashes
+/// are cached, or which compare many leaves.
+///
+/// A side whose hash matches `last_base` is taken to be unchanged without comparing them. The
+/// hashes are 64 bit FNV-1a, which isn't collision resistant: a changed value has about a one in
+/// 2^64 chance of hashing the same by accident, but values can be crafted to collide, and the
+/// change is then lost. Don't use it where `set` comes from someone who could gain from that.
+pub fn update_sync_hashed<T: UpdateSync + SyncHash>(last_base: T, new_base: T, set: T) -> T {
+    let last_base_hash = last_base.sync_hash();
+    if set.sync_hash() == last_base_hash {
+        new_base
+    } else if new_base.sync_hash() == last_base_hash {
+        set
+    } else {
+        T::update_sync(last_base, new_base, set)
+    }
+}
+
+/// Floats which are `ChangeEq` hash the same, so every `NaN` is hashed as one, as are both zeros
+fn canonical_f32(value: f32) -> f32 {
+    if value.is_nan() {
+        f32::NAN
+    } else if value == 0.0 {
+        0.0
+    } else {
+        value
+    }
+}
+
+fn canonical_f64(value: f64) -> f64 {
+    if value.is_nan() {
+        f64::NAN
+    } else if value == 0.0 {
+        0.0
+    } else {
+        value
+    }
+}
+
+/// A duration as its whole seconds, then its nanoseconds
+fn duration_bytes(duration: &Duration) -> Vec<u8> {
+    [
+        &duration.as_secs().to_le_bytes()[..],
+        &duration.subsec_nanos().to_le_bytes(),
+    ]
+    .concat()
+}
+
+/// A time as its distance from the unix epoch, and which side of it it is on
+fn system_time_bytes(time: &SystemTime) -> Vec<u8> {
+    match time.duration_since(UNIX_EPOCH) {
+        Ok(after) => [&[1][..], &duration_bytes(&after)].concat(),
+        Err(before) => [&[0][..], &duration_bytes(&before.duration())].concat(),
+    }
+}
+
+fn ip_bytes(ip: &IpAddr) -> Vec<u8> {
+    match ip {
+        IpAddr::V4(ip) => [&[4][..], &ip.octets()].concat(),
+        IpAddr::V6(ip) => [&[6][..], &ip.octets()].concat(),
+    }
+}
+
+fn socket_v4_bytes(socket: &SocketAddrV4) -> Vec<u8> {
+    [&socket.ip().octets()[..], &socket.port().to_le_bytes()].concat()
+}
+
+fn socket_v6_bytes(socket: &SocketAddrV6) -> Vec<u8> {
+    [
+        &socket.ip().octets()[..],
+        &socket.port().to_le_bytes(),
+        &socket.flowinfo().to_le_bytes(),
+        &socket.scope_id().to_le_bytes(),
+    ]
+    .concat()
+}
+
+fn socket_bytes(socket: &SocketAddr) -> Vec<u8> {
+    match socket {
+        SocketAddr::V4(socket) => [&[4][..], &socket_v4_bytes(socket)].concat(),
+        SocketAddr::V6(socket) => [&[6][..], &socket_v6_bytes(socket)].concat(),
+    }
+}
+
+macro_rules! bytes_impl_sync_hash {
+    ($($t:ty => |$value:ident| $bytes:expr),* $(,)?) => {
+        $(
+            impl SyncHash for $t {
+                fn sync_hash(&self) -> u64 {
+                    let $value = self;
+                    let mut hasher = SyncHasher::new();
+                    hasher.write(&$bytes);
+                    hasher.finish()
+                }
+            }
+        )*
+    };
+}
+
+macro_rules! int_impl_sync_hash {
+    ($($t:ty),*) => {
+        bytes_impl_sync_hash!($($t => |value| value.to_le_bytes()),*);
+    };
+}
+
+int_impl_sync_hash!(u8, u16, u32, u64, u128, i8, i16, i32, i64, i128);
+
+bytes_impl_sync_hash!(
+    usize => |value| (*value as u64).to_le_bytes(),
+    isize => |value| (*value as i64).to_le_bytes(),
+    f32 => |value| canonical_f32(*value).to_bits().to_le_bytes(),
+    f64 => |value| canonical_f64(*value).to_bits().to_le_bytes(),
+    bool => |value| [*value as u8],
+    char => |value| u32::from(*value).to_le_bytes(),
+    () => |_value| [],
+    str => |value| *value.as_bytes(),
+    String => |value| *value.as_bytes(),
+);
+
+// Non-zero integers hash the same as the integers they hold
+bytes_impl_sync_hash!(
+    NonZeroU8 => |value| value.get().to_le_bytes(),
+    NonZeroU16 => |value| value.get().to_le_bytes(),
+    NonZeroU32 => |value| value.get().to_le_bytes(),
+    NonZeroU64 => |value| value.get().to_le_bytes(),
+    NonZeroU128 => |value| value.get().to_le_bytes(),
+    NonZeroUsize => |value| (value.get() as u64).to_le_bytes(),
+    NonZeroI8 => |value| value.get().to_le_bytes(),
+    NonZeroI16 => |value| value.get().to_le_bytes(),
+    NonZeroI32 => |value| value.get().to_le_bytes(),
+    NonZeroI64 => |value| value.get().to_le_bytes(),
+    NonZeroI128 => |value| value.get().to_le_bytes(),
+    NonZeroIsize => |value| (value.get() as i64).to_le_bytes(),
+);
+
+bytes_impl_sync_hash!(
+    Duration => |value| duration_bytes(value),
+    SystemTime => |value| system_time_bytes(value),
+    IpAddr => |value| ip_bytes(value),
+    Ipv4Addr => |value| value.octets(),
+    Ipv6Addr => |value| value.octets(),
+    SocketAddr => |value| socket_bytes(value),
+    SocketAddrV4 => |value| socket_v4_bytes(value),
+    SocketAddrV6 => |value| socket_v6_bytes(value),
+);
+
+// Paths are hashed as text so they hash the same on every platform, with any invalid unicode
+// replaced, so paths which differ only there hash alike
+bytes_impl_sync_hash!(
+    PathBuf => |value| *value.to_string_lossy().as_bytes(),
+    OsString => |value| *value.to_string_lossy().as_bytes(),
+);
+
+impl<T: ?Sized> SyncHash for PhantomData<T> {
+    fn sync_hash(&self) -> u64 {
+        ().sync_hash()
+    }
+}
+
+impl<T: SyncHash> SyncHash for Wrapping<T> {
+    fn sync_hash(&self) -> u64 {
+        self.0.sync_hash()
+    }
+}
+
+impl<T: SyncHash + ?Sized> SyncHash for &T {
+    fn sync_hash(&self) -> u64 {
+        T::sync_hash(self)
+    }
+
+    fn hash_tree(&self) -> HashTree {
+        T::hash_tree(self)
+    }
+}
+
+impl<T: SyncHash + ?Sized> SyncHash for Box<T> {
+    fn sync_hash(&self) -> u64 {
+        T::sync_hash(self)
+    }
+
+    fn hash_tree(&self) -> HashTree {
+        T::hash_tree(self)
+    }
+}
+
+impl<T: SyncHash> SyncHash for Option<T> {
+    fn sync_hash(&self) -> u64 {
+        match self {
+            Some(value) => node_hash(&[(variant("Some"), value.sync_hash())]),
+            None => node_hash(&[]),
+        }
+    }
+
+    fn hash_tree(&self) -> HashTree {
+        match self {
+            Some(value) => HashTree::node(vec![(variant("Some"), value.hash_tree())]),
+            None => HashTree::node(Vec::new()),
+        }
+    }
+}
+
+impl<T: SyncHash, E: SyncHash> SyncHash for Result<T, E> {
+    fn sync_hash(&self) -> u64 {
+        match self {
+            Ok(value) => node_hash(&[(variant("Ok"), value.sync_hash())]),
+            Err(error) => node_hash(&[(variant("Err"), error.sync_hash())]),
+        }
+    }
+
+    fn hash_tree(&self) -> HashTree {
+        match self {
+            Ok(value) => HashTree::node(vec![(variant("Ok"), value.hash_tree())]),
+            Err(error) => HashTree::node(vec![(variant("Err"), error.hash_tree())]),
+        }
+    }
+}
+
+macro_rules! tuple_impl_sync_hash {
+    ($($t:ident : $i:tt),+) => {
+        impl<$($t: SyncHash),+> SyncHash for ($($t,)+) {
+            fn sync_hash(&self) -> u64 {
+                node_hash(&[$((PathSegment::Index($i), self.$i.sync_hash())),+])
+            }
+
+            fn hash_tree(&self) -> HashTree {
+                HashTree::node(vec![$((PathSegment::Index($i), self.$i.hash_tree())),+])
+            }
+        }
+    };
+}
+
+tuple_impl_sync_hash!(T1: 0);
+tuple_impl_sync_hash!(T1: 0, T2: 1);
+tuple_impl_sync_hash!(T1: 0, T2: 1, T3: 2);
+tuple_impl_sync_hash!(T1: 0, T2: 1, T3: 2, T4: 3);
+tuple_impl_sync_hash!(T1: 0, T2: 1, T3: 2, T4: 3, T5: 4);
+tuple_impl_sync_hash!(T1: 0, T2: 1, T3: 2, T4: 3, T5: 4, T6: 5);
+tuple_impl_sync_hash!(T1: 0, T2: 1, T3: 2, T4: 3, T5: 4, T6: 5, T7: 6);
+tuple_impl_sync_hash!(T1: 0, T2: 1, T3: 2, T4: 3, T5: 4, T6: 5, T7: 6, T8: 7);
+tuple_impl_sync_hash!(T1: 0, T2: 1, T3: 2, T4: 3, T5: 4, T6: 5, T7: 6, T8: 7, T9: 8);
+tuple_impl_sync_hash!(T1: 0, T2: 1, T3: 2, T4: 3, T5: 4, T6: 5, T7: 6, T8: 7, T9: 8, T10: 9);
+
+impl<T: SyncHash> SyncHash for [T] {
+    fn sync_hash(&self) -> u64 {
+        let hashes: Vec<_> = self
+            .iter()
+            .enumerate()
+            .map(|(i, value)| (PathSegment::Index(i), value.sync_hash()))
+            .collect();
+        node_hash(&hashes)
+    }
+
+    fn hash_tree(&self) -> HashTree {
+        HashTree::node(
+            self.iter()
+                .enumerate()
+                .map(|(i, value)| (PathSegment::Index(i), value.hash_tree()))
+                .collect(),
+        )
+    }
+}
+
+impl<T: SyncHash> SyncHash for Vec<T> {
+    fn sync_hash(&self) -> u64 {
+        self.as_slice().sync_hash()
+    }
+
+    fn hash_tree(&self) -> HashTree {
+        self.as_slice().hash_tree()
+    }
+}
+
+/// Hashes the entries of a map, ordered by their path segments so that iteration order doesn't
+/// matter
+fn map_hash_tree<'a, K: Debug + 'a, V: SyncHash + 'a>(
+    entries: impl Iterator<Item = (&'a K, &'a V)>,
+) -> HashTree {
+    let mut children: Vec<_> = entries
+        .map(|(key, value)| (PathSegment::from(Segment::Key(key)), value.hash_tree()))
+        .collect();
+    children.sort_by(|(a, _), (b, _)| a.cmp(b));
+    HashTree::node(children)
+}
+
+fn map_sync_hash<'a, K: Debug + 'a, V: SyncHash + 'a>(
+    entries: impl Iterator<Item = (&'a K, &'a V)>,
+) -> u64 {
+    let mut hashes: Vec<_> = entries
+        .map(|(key, value)| (PathSegment::from(Segment::Key(key)), value.sync_hash()))
+        .collect();
+    hashes.sort_by(|(a, _), (b, _)| a.cmp(b));
+    node_hash(&hashes)
+}
+
+impl<K: Debug, V: SyncHash> SyncHash for BTreeMap<K, V> {
+    fn sync_hash(&self) -> u64 {
+        map_sync_hash(self.iter())
+    }
+
+    fn hash_tree(&self) -> HashTree {
+        map_hash_tree(self.iter())
+    }
+}
+
+impl<K: Debug, V: SyncHash, S> SyncHash for HashMap<K, V, S> {
+    fn sync_hash(&self) -> u64 {
+        map_sync_hash(self.iter())
+    }
+
+    fn hash_tree(&self) -> HashTree {
+        map_hash_tree(self.iter())
+    }
+}
+
+fn variant(name: &'static str) -> PathSegment {
+    PathSegment::Variant(Cow::Borrowed(name))
+}
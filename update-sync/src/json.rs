@@ -0,0 +1,309 @@
+//! Updating schemaless JSON
+//!
+//! Enabled with the `serde_json` feature, this implements `UpdateSync` for `serde_json::Value`.
+//! Objects are updated key by key, with the same semantics as maps: keys added by either side
+//! are kept, keys removed by `new_base` are removed, and keys removed by `set` are left as they
+//! are in `new_base`. Values under keys in all three objects are updated recursively. Scalars, and
+//! values whose type changed, are updated as a whole.
+//!
+//! Arrays are replaced as a whole by default, [`update_sync_json`] takes [`JsonOptions`] choosing
+//! another [`ArrayStrategy`]. [`merge`] reports conflicts as JSON Pointers.
+
+use std::{
+    collections::HashMap,
+    fmt::{self, Debug},
+};
+
+use serde_json::{Map, Value};
+
+use crate::{
+    keyed::update_sync_keyed_visit,
+    map_entry_resolution,
+    visit::{visit_change_eq, Leaf, Recorder, Resolution, Segment, Visitor},
+    ChangeEq, UpdateSync, UpdateSyncVisit,
+};
+
+/// How arrays are updated
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub enum ArrayStrategy {
+    /// Arrays are updated as a single value
+    Atomic,
+    /// Arrays with the same length in all three inputs are updated element by element, others
+    /// are updated as a single value
+    ByIndex,
+    /// Arrays are matched up element by element using the given field of each element, as by
+    /// [`keyed`](crate::keyed), and elements without it are matched by their whole value
+    ///
+    /// Elements sharing a key are matched up in the order they appear, so none are lost. Conflicts
+    /// inside an element are located by its key, such as `/items/a/qty` for an element whose key
+    /// field is `"a"`.
+    Keyed(String),
+}
+
+/// Options for updating JSON values
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct JsonOptions {
+    pub arrays: ArrayStrategy,
+}
+
+impl Default for JsonOptions {
+    fn default() -> Self {
+        JsonOptions {
+            arrays: ArrayStrategy::Atomic,
+        }
+    }
+}
+
+impl ChangeEq for Value {
+    fn change_eq(&self, other: &Self) -> bool {
+        self == other
+    }
+}
+
+/// Updates with the default [`JsonOptions`]
+impl UpdateSync for Value {
+    fn update_sync(last_base: Self, new_base: Self, set: Self) -> Self {
+        Self::update_sync_visit(last_base, new_base, set, &mut ())
+    }
+}
+
+impl UpdateSyncVisit for Value {
+    fn update_sync_visit(
+        last_base: Self,
+        new_base: Self,
+        set: Self,
+        visitor: &mut dyn Visitor,
+    ) -> Self {
+        update_sync_json_visit(last_base, new_base, set, &JsonOptions::default(), visitor)
+    }
+}
+
+/// Updates a JSON value using `options`
+pub fn update_sync_json(
+    last_base: Value,
+    new_base: Value,
+    set: Value,
+    options: &JsonOptions,
+) -> Value {
+    update_sync_json_visit(last_base, new_base, set, options, &mut ())
+}
+
+/// Performs the same update as `update_sync_json`, reporting it to `visitor`
+pub fn update_sync_json_visit(
+    last_base: Value,
+    new_base: Value,
+    set: Value,
+    options: &JsonOptions,
+    visitor: &mut dyn Visitor,
+) -> Value {
+    match (last_base, new_base, set) {
+        (Value::Object(l), Value::Object(n), Value::Object(s)) => {
+            Value::Object(update_object(l, n, s, options, visitor))
+        }
+        (Value::Array(l), Value::Array(n), Value::Array(s)) => {
+            update_array(l, n, s, options, visitor)
+        }
+        (l, n, s) => visit_change_eq(l, n, s, visitor),
+    }
+}
+
+/// The result of [`merge`]
+#[derive(Clone, Debug, PartialEq)]
+pub struct JsonMerge {
+    pub merged: Value,
+    /// JSON Pointers to every value both sides changed differently, where `set`'s change was kept
+    pub conflicts: Vec<String>,
+}
+
+/// Updates a JSON value using `options`, reporting every conflict
+pub fn merge(last_base: Value, new_base: Value, set: Value, options: &JsonOptions) -> JsonMerge {
+    let mut recorder = Recorder::default();
+    let merged = update_sync_json_visit(last_base, new_base, set, options, &mut recorder);
+    JsonMerge {
+        merged,
+        conflicts: recorder
+            .leaves
+            .into_iter()
+            .filter(|(_, resolution)| *resolution == Resolution::Conflict)
+            .map(|(path, _)| path.to_pointer())
+            .collect(),
+    }
+}
+
+fn update_object(
+    last_base: Map<String, Value>,
+    mut new_base: Map<String, Value>,
+    mut set: Map<String, Value>,
+    options: &JsonOptions,
+    visitor: &mut dyn Visitor,
+) -> Map<String, Value> {
+    let mut merged = Map::new();
+    let mut update_entry = |key: String, l: Option<Value>, n: Option<Value>, s: Option<Value>| {
+        visitor.enter(Segment::Key(&key));
+        let value = match (l, n, s) {
+            (Some(l), Some(n), Some(s)) => Some(update_sync_json_visit(l, n, s, options, visitor)),
+            (l, n, s) => {
+                let resolution = map_entry_resolution(l.as_ref(), n.as_ref(), s.as_ref());
+                visitor.leaf(Leaf {
+                    resolution,
+                    last_base: l.as_ref().map(|l| l as &dyn Debug),
+                    new_base: n.as_ref().map(|n| n as &dyn Debug),
+                    set: s.as_ref().map(|s| s as &dyn Debug),
+                });
+                if resolution.took_set() {
+                    s
+                } else {
+                    n
+                }
+            }
+        };
+        visitor.leave();
+        if let Some(value) = value {
+            merged.insert(key, value);
+        }
+    };
+    for (key, l) in last_base {
+        let (n, s) = (new_base.remove(&key), set.remove(&key));
+        update_entry(key, Some(l), n, s);
+    }
+    for (key, n) in new_base {
+        let s = set.remove(&key);
+        update_entry(key, None, Some(n), s);
+    }
+    for (key, s) in set {
+        update_entry(key, None, None, Some(s));
+    }
+    merged
+}
+
+fn update_array(
+    last_base: Vec<Value>,
+    new_base: Vec<Value>,
+    set: Vec<Value>,
+    options: &JsonOptions,
+    visitor: &mut dyn Visitor,
+) -> Value {
+    match &options.arrays {
+        ArrayStrategy::ByIndex
+            if last_base.len() == new_base.len() && new_base.len() == set.len() =>
+        {
+            let elements = last_base.into_iter().zip(new_base).zip(set);
+            Value::Array(
+                elements
+                    .enumerate()
+                    .map(|(i, ((l, n), s))| {
+                        visitor.enter(Segment::Index(i));
+                        let value = update_sync_json_visit(l, n, s, options, visitor);
+                        visitor.leave();
+                        value
+                    })
+                    .collect(),
+            )
+        }
+        ArrayStrategy::Keyed(field) => {
+            // Keys are numbered by occurrence, so elements sharing a key are kept apart
+            let with_options = |values: Vec<Value>| -> Vec<WithOptions<'_>> {
+                let mut occurrences = HashMap::new();
+                values
+                    .into_iter()
+                    .map(|value| {
+                        let (key, token) = match value.get(field) {
+                            Some(Value::String(key)) => (value[field].to_string(), key.clone()),
+                            Some(key) => (key.to_string(), key.to_string()),
+                            None => (value.to_string(), value.to_string()),
+                        };
+                        let occurrence = occurrences.entry(key.clone()).or_insert(0);
+                        *occurrence += 1;
+                        WithOptions {
+                            key: ElementKey {
+                                key,
+                                occurrence: *occurrence,
+                                token,
+                            },
+                            value,
+                            options,
+                        }
+                    })
+                    .collect()
+            };
+            let merged = update_sync_keyed_visit(
+                with_options(last_base),
+                with_options(new_base),
+                with_options(set),
+                |element| element.key.clone(),
+                visitor,
+            );
+            Value::Array(merged.into_iter().map(|element| element.value).collect())
+        }
+        _ => visit_change_eq(
+            Value::Array(last_base),
+            Value::Array(new_base),
+            Value::Array(set),
+            visitor,
+        ),
+    }
+}
+
+/// The key of an element of a keyed array, which is found at its key field's value in paths
+///
+/// Elements sharing a key are told apart by their occurrence, but share a path.
+#[derive(Clone, PartialEq, Eq, PartialOrd, Ord)]
+struct ElementKey {
+    key: String,
+    occurrence: usize,
+    token: String,
+}
+
+impl Debug for ElementKey {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        // Shown as a string, so that JSON Pointers hold the key without its quotes
+        self.token.fmt(f)
+    }
+}
+
+/// A value which is updated using the options it carries, for updating elements of keyed arrays
+struct WithOptions<'a> {
+    key: ElementKey,
+    value: Value,
+    options: &'a JsonOptions,
+}
+
+impl Debug for WithOptions<'_> {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        self.value.fmt(f)
+    }
+}
+
+impl ChangeEq for WithOptions<'_> {
+    fn change_eq(&self, other: &Self) -> bool {
+        self.value == other.value
+    }
+}
+
+impl UpdateSync for WithOptions<'_> {
+    fn update_sync(last_base: Self, new_base: Self, set: Self) -> Self {
+        Self::update_sync_visit(last_base, new_base, set, &mut ())
+    }
+}
+
+impl UpdateSyncVisit for WithOptions<'_> {
+    fn update_sync_visit(
+        last_base: Self,
+        new_base: Self,
+        set: Self,
+        visitor: &mut dyn Visitor,
+    ) -> Self {
+        let options = new_base.options;
+        WithOptions {
+            key: new_base.key,
+            value: update_sync_json_visit(
+                last_base.value,
+                new_base.value,
+                set.value,
+                options,
+                visitor,
+            ),
+            options,
+        }
+    }
+}
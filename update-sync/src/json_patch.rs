@@ -0,0 +1,434 @@
+//! Sending updates as JSON Patch and JSON Merge Patch documents
+//!
+//! Rather than sending a client the whole of a merged value, a server can send the difference
+//! between the `new_base` the client last saw and the merged result, either as a JSON Patch
+//! (RFC 6902), with [`json_patch`], or as a JSON Merge Patch (RFC 7386), with [`merge_patch`].
+//!
+//! In the other direction, a client can send the changes it made since `last_base` as a JSON
+//! Patch, which [`update_sync_json_patch`] applies to `last_base` to recover the client's `set`,
+//! before updating `new_base` with it as usual. This works for any type which implements
+//! `Serialize` and `Deserialize` alongside `UpdateSync`.
+//!
+//! Enabled with the `serde_json` feature.
+
+use std::{error::Error, fmt, mem};
+
+use serde::{de::DeserializeOwned, Serialize};
+use serde_json::{Map, Value};
+
+use crate::{
+    path::{is_index, Path, PathSegment, PointerError},
+    UpdateSync,
+};
+
+/// A single JSON Patch operation
+#[derive(Clone, Debug, PartialEq)]
+pub enum PatchOperation {
+    Add { path: Path, value: Value },
+    Remove { path: Path },
+    Replace { path: Path, value: Value },
+    Move { from: Path, path: Path },
+    Copy { from: Path, path: Path },
+    Test { path: Path, value: Value },
+}
+
+/// A JSON Patch document, the operations of which are applied in order
+#[derive(Clone, Debug, Default, PartialEq)]
+pub struct JsonPatch(pub Vec<PatchOperation>);
+
+/// Why a JSON Patch couldn't be read or applied
+#[derive(Debug)]
+pub enum PatchError {
+    /// The patch, or the value it was applied to, couldn't be converted to or from JSON
+    Json(serde_json::Error),
+    /// An operation wasn't an object with a known `op` and the members it requires
+    InvalidOperation(usize),
+    /// A `path` or `from` wasn't a valid JSON Pointer
+    Pointer(PointerError),
+    /// A `path` or `from` didn't refer to a value, or to a place a value could be added
+    NotFound(String),
+    /// A `test` operation found a different value
+    TestFailed(String),
+}
+
+impl fmt::Display for PatchError {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self {
+            PatchError::Json(error) => write!(f, "{}", error),
+            PatchError::InvalidOperation(index) => {
+                write!(f, "JSON patch operation {} is invalid", index)
+            }
+            PatchError::Pointer(error) => write!(f, "{}", error),
+            PatchError::NotFound(pointer) => write!(f, "no value at `{}`", pointer),
+            PatchError::TestFailed(pointer) => write!(f, "test failed at `{}`", pointer),
+        }
+    }
+}
+
+impl Error for PatchError {
+    fn source(&self) -> Option<&(dyn Error + 'static)> {
+        match self {
+            PatchError::Json(error) => Some(error),
+            PatchError::Pointer(error) => Some(error),
+            _ => None,
+        }
+    }
+}
+
+impl From<serde_json::Error> for PatchError {
+    fn from(error: serde_json::Error) -> Self {
+        PatchError::Json(error)
+    }
+}
+
+impl From<PointerError> for PatchError {
+    fn from(error: PointerError) -> Self {
+        PatchError::Pointer(error)
+    }
+}
+
+impl JsonPatch {
+    /// The operations which turn `from` into `to`
+    ///
+    /// Objects and arrays are compared member by member, so only the values which differ are
+    /// replaced. Elements are added and removed at the end of arrays.
+    pub fn diff(from: &Value, to: &Value) -> JsonPatch {
+        let mut patch = JsonPatch::default();
+        diff_into(&mut Path::new(), from, to, &mut patch.0);
+        patch
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.0.is_empty()
+    }
+
+    /// Reads a patch from its JSON representation, an array of operation objects
+    pub fn from_value(value: &Value) -> Result<JsonPatch, PatchError> {
+        let operations = value.as_array().ok_or(PatchError::InvalidOperation(0))?;
+        operations
+            .iter()
+            .enumerate()
+            .map(|(index, operation)| {
+                read_operation(operation).ok_or(PatchError::InvalidOperation(index))?
+            })
+            .collect::<Result<_, _>>()
+            .map(JsonPatch)
+    }
+
+    /// The JSON representation of the patch
+    pub fn to_value(&self) -> Value {
+        Value::Array(self.0.iter().map(write_operation).collect())
+    }
+
+    /// Applies every operation in order, leaving `target` as it was if any of them fail
+    pub fn apply(&self, target: &mut Value) -> Result<(), PatchError> {
+        let mut patched = target.clone();
+        for operation in &self.0 {
+            apply_operation(&mut patched, operation)?;
+        }
+        *target = patched;
+        Ok(())
+    }
+}
+
+/// The JSON Patch which turns `new_base` into `merged`
+pub fn json_patch<T: Serialize>(new_base: &T, merged: &T) -> Result<JsonPatch, PatchError> {
+    Ok(JsonPatch::diff(
+        &serde_json::to_value(new_base)?,
+        &serde_json::to_value(merged)?,
+    ))
+}
+
+/// The JSON Merge Patch which turns `new_base` into `merged`
+///
+/// Merge patches remove members which are set to `null`, so a member which is `null` in `merged`
+/// is sent as a removal.
+pub fn merge_patch<T: Serialize>(new_base: &T, merged: &T) -> Result<Value, PatchError> {
+    Ok(diff_merge_patch(
+        &serde_json::to_value(new_base)?,
+        &serde_json::to_value(merged)?,
+    ))
+}
+
+/// The JSON Merge Patch which turns `from` into `to`
+pub fn diff_merge_patch(from: &Value, to: &Value) -> Value {
+    match (from, to) {
+        (Value::Object(from), Value::Object(to)) => {
+            let mut patch = Map::new();
+            for key in from.keys() {
+                if !to.contains_key(key) {
+                    patch.insert(key.clone(), Value::Null);
+                }
+            }
+            for (key, to) in to {
+                match from.get(key) {
+                    Some(from) if from == to => {}
+                    Some(from) => {
+                        patch.insert(key.clone(), diff_merge_patch(from, to));
+                    }
+                    None => {
+                        patch.insert(key.clone(), to.clone());
+                    }
+                }
+            }
+            Value::Object(patch)
+        }
+        (_, to) => to.clone(),
+    }
+}
+
+/// Applies a JSON Merge Patch to `target`
+pub fn apply_merge_patch(target: &mut Value, patch: &Value) {
+    let patch = match patch {
+        Value::Object(patch) => patch,
+        patch => return *target = patch.clone(),
+    };
+    if !target.is_object() {
+        *target = Value::Object(Map::new());
+    }
+    if let Value::Object(target) = target {
+        for (key, value) in patch {
+            if value.is_null() {
+                target.remove(key);
+            } else {
+                apply_merge_patch(target.entry(key.clone()).or_insert(Value::Null), value);
+            }
+        }
+    }
+}
+
+/// Recovers the client's `set` by applying its patch to `last_base`, then updates `new_base` with
+/// it
+pub fn update_sync_json_patch<T>(
+    last_base: T,
+    new_base: T,
+    patch: &JsonPatch,
+) -> Result<T, PatchError>
+where
+    T: UpdateSync + Serialize + DeserializeOwned,
+{
+    let mut set = serde_json::to_value(&last_base)?;
+    patch.apply(&mut set)?;
+    let set = serde_json::from_value(set)?;
+    Ok(T::update_sync(last_base, new_base, set))
+}
+
+fn diff_into(path: &mut Path, from: &Value, to: &Value, operations: &mut Vec<PatchOperation>) {
+    match (from, to) {
+        (from, to) if from == to => {}
+        (Value::Object(from), Value::Object(to)) => {
+            for key in from.keys() {
+                if !to.contains_key(key) {
+                    operations.push(PatchOperation::Remove {
+                        path: path.join(field(key)),
+                    });
+                }
+            }
+            for (key, to) in to {
+                path.push(field(key));
+                match from.get(key) {
+                    Some(from) => diff_into(path, from, to, operations),
+                    None => operations.push(PatchOperation::Add {
+                        path: path.clone(),
+                        value: to.clone(),
+                    }),
+                }
+                path.pop();
+            }
+        }
+        (Value::Array(from), Value::Array(to)) => {
+            for (index, (from, to)) in from.iter().zip(to).enumerate() {
+                path.push(PathSegment::Index(index));
+                diff_into(path, from, to, operations);
+                path.pop();
+            }
+            for index in (to.len()..from.len()).rev() {
+                operations.push(PatchOperation::Remove {
+                    path: path.join(PathSegment::Index(index)),
+                });
+            }
+            for (index, to) in to.iter().enumerate().skip(from.len()) {
+                operations.push(PatchOperation::Add {
+                    path: path.join(PathSegment::Index(index)),
+                    value: to.clone(),
+                });
+            }
+        }
+        (_, to) => operations.push(PatchOperation::Replace {
+            path: path.clone(),
+            value: to.clone(),
+        }),
+    }
+}
+
+fn field(key: &str) -> PathSegment {
+    PathSegment::Field(key.to_owned().into())
+}
+
+fn read_operation(operation: &Value) -> Option<Result<PatchOperation, PatchError>> {
+    let pointer = |member| -> Option<Result<Path, PatchError>> {
+        let pointer = operation.get(member)?.as_str()?;
+        Some(Path::from_pointer(pointer).map_err(PatchError::from))
+    };
+    let value = || operation.get("value").cloned();
+    let path = match pointer("path")? {
+        Ok(path) => path,
+        Err(error) => return Some(Err(error)),
+    };
+    let operation = match operation.get("op")?.as_str()? {
+        "add" => PatchOperation::Add {
+            path,
+            value: value()?,
+        },
+        "remove" => PatchOperation::Remove { path },
+        "replace" => PatchOperation::Replace {
+            path,
+            value: value()?,
+        },
+        "move" => PatchOperation::Move {
+            from: match pointer("from")? {
+                Ok(from) => from,
+                Err(error) => return Some(Err(error)),
+            },
+            path,
+        },
+        "copy" => PatchOperation::Copy {
+            from: match pointer("from")? {
+                Ok(from) => from,
+                Err(error) => return Some(Err(error)),
+            },
+            path,
+        },
+        "test" => PatchOperation::Test {
+            path,
+            value: value()?,
+        },
+        _ => return None,
+    };
+    Some(Ok(operation))
+}
+
+fn write_operation(operation: &PatchOperation) -> Value {
+    let mut object = Map::new();
+    let mut member = |name: &str, value: Value| {
+        object.insert(name.to_owned(), value);
+    };
+    let (op, path) = match operation {
+        PatchOperation::Add { path, value } => {
+            member("value", value.clone());
+            ("add", path)
+        }
+        PatchOperation::Remove { path } => ("remove", path),
+        PatchOperation::Replace { path, value } => {
+            member("value", value.clone());
+            ("replace", path)
+        }
+        PatchOperation::Move { from, path } => {
+            member("from", Value::String(from.to_pointer()));
+            ("move", path)
+        }
+        PatchOperation::Copy { from, path } => {
+            member("from", Value::String(from.to_pointer()));
+            ("copy", path)
+        }
+        PatchOperation::Test { path, value } => {
+            member("value", value.clone());
+            ("test", path)
+        }
+    };
+    member("op", Value::String(op.to_owned()));
+    member("path", Value::String(path.to_pointer()));
+    Value::Object(object)
+}
+
+fn apply_operation(target: &mut Value, operation: &PatchOperation) -> Result<(), PatchError> {
+    match operation {
+        PatchOperation::Add { path, value } => add(target, path, value.clone()),
+        PatchOperation::Remove { path } => remove(target, path).map(drop),
+        PatchOperation::Replace { path, value } => {
+            *get_mut(target, path)? = value.clone();
+            Ok(())
+        }
+        PatchOperation::Move { from, path } => {
+            let value = remove(target, from)?;
+            add(target, path, value)
+        }
+        PatchOperation::Copy { from, path } => {
+            let value = get_mut(target, from)?.clone();
+            add(target, path, value)
+        }
+        PatchOperation::Test { path, value } => {
+            if get_mut(target, path)? == value {
+                Ok(())
+            } else {
+                Err(PatchError::TestFailed(path.to_pointer()))
+            }
+        }
+    }
+}
+
+fn get_mut<'a>(target: &'a mut Value, path: &Path) -> Result<&'a mut Value, PatchError> {
+    target
+        .pointer_mut(&path.to_pointer())
+        .ok_or_else(|| PatchError::NotFound(path.to_pointer()))
+}
+
+/// Splits `path` into the path of the value containing it, and its token in that value
+fn parent<'a>(target: &'a mut Value, path: &Path) -> Result<(&'a mut Value, String), PatchError> {
+    let mut parent = path.clone();
+    let token = match parent.pop() {
+        Some(segment) => segment.token().into_owned(),
+        None => return Err(PatchError::NotFound(path.to_pointer())),
+    };
+    Ok((get_mut(target, &parent)?, token))
+}
+
+/// Parses an array index, which RFC 6901 writes without signs or leading zeros
+fn array_index(token: &str) -> Option<usize> {
+    if is_index(token) {
+        token.parse().ok()
+    } else {
+        None
+    }
+}
+
+fn add(target: &mut Value, path: &Path, value: Value) -> Result<(), PatchError> {
+    if path.is_empty() {
+        *target = value;
+        return Ok(());
+    }
+    let not_found = || PatchError::NotFound(path.to_pointer());
+    match parent(target, path)? {
+        (Value::Object(object), token) => {
+            object.insert(token, value);
+        }
+        (Value::Array(array), token) => {
+            let index = if token == "-" {
+                array.len()
+            } else {
+                array_index(&token).ok_or_else(not_found)?
+            };
+            if index > array.len() {
+                return Err(not_found());
+            }
+            array.insert(index, value);
+        }
+        _ => return Err(not_found()),
+    }
+    Ok(())
+}
+
+fn remove(target: &mut Value, path: &Path) -> Result<Value, PatchError> {
+    if path.is_empty() {
+        return Ok(mem::take(target));
+    }
+    let not_found = || PatchError::NotFound(path.to_pointer());
+    match parent(target, path)? {
+        (Value::Object(object), token) => object.remove(&token).ok_or_else(not_found),
+        (Value::Array(array), token) => match array_index(&token) {
+            Some(index) if index < array.len() => Ok(array.remove(index)),
+            _ => Err(not_found()),
+        },
+        _ => Err(not_found()),
+    }
+}
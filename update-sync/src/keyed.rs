@@ -0,0 +1,248 @@
+//! Lists of records matched by identity
+//!
+//! `Vec<T>` updates as a single value, so any change to a list of records replaces it with `set`
+//! as a whole. When each record has a key, such as an id, the lists can instead be matched up
+//! record by record: records in all three lists are updated with `T`'s own `UpdateSync`, records
+//! added or removed by either side are added or removed, and a reordering by either side is kept.
+//! A record one side removed and the other changed is kept with its changes.
+//!
+//! [`KeyedVec<T>`] is a list of [`Keyed`] records. `Vec` fields of derived structs can also be
+//! merged this way with `#[update_sync(key = "id")]`, naming the field of the records holding
+//! their key. Records sharing a key are matched up in the order they appear in each list.
+
+use std::{
+    collections::{BTreeMap, BTreeSet},
+    fmt::Debug,
+};
+
+use crate::{
+    visit::{visit_segment, Leaf, Resolution, Segment, Visitor},
+    ChangeEq, UpdateSync, UpdateSyncVisit,
+};
+
+/// A record which can be told apart from others in the same list by its key
+pub trait Keyed {
+    type Key: Ord + Clone;
+
+    fn key(&self) -> Self::Key;
+}
+
+/// A list of records which is updated by matching the records by key
+#[derive(Clone, Debug, Default, PartialEq, Eq, Hash)]
+pub struct KeyedVec<T>(pub Vec<T>);
+
+impl<T> From<Vec<T>> for KeyedVec<T> {
+    fn from(records: Vec<T>) -> Self {
+        KeyedVec(records)
+    }
+}
+
+impl<T: ChangeEq> ChangeEq for KeyedVec<T> {
+    fn change_eq(&self, other: &Self) -> bool {
+        self.0.change_eq(&other.0)
+    }
+}
+
+impl<T> UpdateSync for KeyedVec<T>
+where
+    T: Keyed + UpdateSync + ChangeEq,
+{
+    fn update_sync(last_base: Self, new_base: Self, set: Self) -> Self {
+        KeyedVec(update_sync_keyed(last_base.0, new_base.0, set.0, T::key))
+    }
+}
+
+impl<T> UpdateSyncVisit for KeyedVec<T>
+where
+    T: Keyed + UpdateSyncVisit + ChangeEq + Debug,
+    T::Key: Debug,
+{
+    fn update_sync_visit(
+        last_base: Self,
+        new_base: Self,
+        set: Self,
+        visitor: &mut dyn Visitor,
+    ) -> Self {
+        KeyedVec(update_sync_keyed_visit(
+            last_base.0,
+            new_base.0,
+            set.0,
+            T::key,
+            visitor,
+        ))
+    }
+}
+
+/// Updates a list of records, matching records between the lists with `key`
+pub fn update_sync_keyed<T, K>(
+    last_base: Vec<T>,
+    new_base: Vec<T>,
+    set: Vec<T>,
+    key: impl Fn(&T) -> K,
+) -> Vec<T>
+where
+    T: UpdateSync + ChangeEq,
+    K: Ord + Clone,
+{
+    update_records(last_base, new_base, set, key, |_, l, n, s| {
+        match (l, n, s) {
+            (Some(l), Some(n), Some(s)) => Some(T::update_sync(l, n, s)),
+            (l, n, s) => {
+                if record_resolution(&l, &n, &s).took_set() {
+                    s
+                } else {
+                    n
+                }
+            }
+        }
+    })
+}
+
+/// Performs the same update as `update_sync_keyed`, reporting each record to `visitor` under its
+/// key
+pub fn update_sync_keyed_visit<T, K>(
+    last_base: Vec<T>,
+    new_base: Vec<T>,
+    set: Vec<T>,
+    key: impl Fn(&T) -> K,
+    visitor: &mut dyn Visitor,
+) -> Vec<T>
+where
+    T: UpdateSyncVisit + ChangeEq + Debug,
+    K: Ord + Clone + Debug,
+{
+    update_records(last_base, new_base, set, key, |key, l, n, s| {
+        match (l, n, s) {
+            (Some(l), Some(n), Some(s)) => Some(visit_segment(Segment::Key(key), l, n, s, visitor)),
+            (l, n, s) => {
+                let resolution = record_resolution(&l, &n, &s);
+                visitor.enter(Segment::Key(key));
+                visitor.leaf(Leaf {
+                    resolution,
+                    last_base: l.as_ref().map(|l| l as &dyn Debug),
+                    new_base: n.as_ref().map(|n| n as &dyn Debug),
+                    set: s.as_ref().map(|s| s as &dyn Debug),
+                });
+                visitor.leave();
+                if resolution.took_set() {
+                    s
+                } else {
+                    n
+                }
+            }
+        }
+    })
+}
+
+/// How a record missing from at least one list is updated
+///
+/// A record changed by one side and removed by the other is kept with its changes, as map entries
+/// are, so neither side loses an edit to the other's removal. Records `set` removed which
+/// `new_base` didn't change are removed, unlike map entries.
+fn record_resolution<T: ChangeEq>(
+    last_base: &Option<T>,
+    new_base: &Option<T>,
+    set: &Option<T>,
+) -> Resolution {
+    match Resolution::classify(last_base, new_base, set, ChangeEq::change_eq) {
+        // `set` removed the record `new_base` changed, which keeps the change
+        Resolution::Conflict if set.is_none() => Resolution::NewBase,
+        resolution => resolution,
+    }
+}
+
+/// Matches up the records of the three lists, and orders the records `update` keeps
+///
+/// Records sharing a key are matched up in the order they appear in each list.
+fn update_records<T, K>(
+    last_base: Vec<T>,
+    new_base: Vec<T>,
+    set: Vec<T>,
+    key: impl Fn(&T) -> K,
+    mut update: impl FnMut(&K, Option<T>, Option<T>, Option<T>) -> Option<T>,
+) -> Vec<T>
+where
+    K: Ord + Clone,
+{
+    // Keys are numbered by occurrence, so records sharing a key are kept apart
+    let keyed = |records: Vec<T>| -> Vec<((K, usize), T)> {
+        let mut occurrences = BTreeMap::new();
+        records
+            .into_iter()
+            .map(|record| {
+                let key = key(&record);
+                let occurrence = occurrences.entry(key.clone()).or_insert(0);
+                *occurrence += 1;
+                ((key, *occurrence), record)
+            })
+            .collect()
+    };
+    let (last_base, new_base, set) = (keyed(last_base), keyed(new_base), keyed(set));
+    let keys = |records: &[((K, usize), T)]| -> Vec<(K, usize)> {
+        records.iter().map(|(key, _)| key.clone()).collect()
+    };
+    let order = merge_order(&keys(&last_base), &keys(&new_base), &keys(&set));
+    let (mut last_base, mut new_base, mut set): (BTreeMap<_, _>, BTreeMap<_, _>, BTreeMap<_, _>) = (
+        last_base.into_iter().collect(),
+        new_base.into_iter().collect(),
+        set.into_iter().collect(),
+    );
+
+    let keys: BTreeSet<(K, usize)> = last_base
+        .keys()
+        .chain(new_base.keys())
+        .chain(set.keys())
+        .cloned()
+        .collect();
+    let mut merged = BTreeMap::new();
+    for key in keys {
+        let (l, n, s) = (
+            last_base.remove(&key),
+            new_base.remove(&key),
+            set.remove(&key),
+        );
+        if let Some(record) = update(&key.0, l, n, s) {
+            merged.insert(key, record);
+        }
+    }
+
+    order
+        .into_iter()
+        .filter_map(|key| merged.remove(&key))
+        .collect()
+}
+
+/// Orders the keys of `new_base` and `set`
+///
+/// If `set` moved any of the records it shares with `last_base` its order is used, otherwise
+/// `new_base`'s is. Records only in the other list are placed after the record before them in
+/// that list.
+fn merge_order<K: Ord + Clone>(last_base: &[K], new_base: &[K], set: &[K]) -> Vec<K> {
+    let (primary, secondary) = if same_relative_order(last_base, set) {
+        (new_base, set)
+    } else {
+        (set, new_base)
+    };
+    let mut order = primary.to_vec();
+    let mut after: Option<usize> = None;
+    for key in secondary {
+        let at = match order.iter().position(|k| k == key) {
+            Some(at) => at,
+            None => {
+                let at = after.map_or(0, |after| after + 1);
+                order.insert(at, key.clone());
+                at
+            }
+        };
+        after = Some(at);
+    }
+    order
+}
+
+/// Returns true if the keys found in both lists are in the same order in each
+fn same_relative_order<K: Ord>(a: &[K], b: &[K]) -> bool {
+    let (in_a, in_b): (BTreeSet<&K>, BTreeSet<&K>) = (a.iter().collect(), b.iter().collect());
+    a.iter()
+        .filter(|k| in_b.contains(k))
+        .eq(b.iter().filter(|k| in_a.contains(k)))
+}
@@ -1,8 +1,57 @@
 use std::{
     collections::{BTreeMap, HashMap},
+    ffi::OsString,
+    fmt::Debug,
     hash::Hash,
+    marker::PhantomData,
+    net::{IpAddr, Ipv4Addr, Ipv6Addr, SocketAddr, SocketAddrV4, SocketAddrV6},
+    num::{
+        NonZeroI128, NonZeroI16, NonZeroI32, NonZeroI64, NonZeroI8, NonZeroIsize, NonZeroU128,
+        NonZeroU16, NonZeroU32, NonZeroU64, NonZeroU8, NonZeroUsize, Wrapping,
+    },
+    path::PathBuf,
+    time::{Duration, SystemTime},
 };
 
+#[macro_use]
+mod macros;
+
+mod change_eq;
+pub mod clock;
+pub mod crdt;
+pub mod float;
+pub mod hash;
+#[cfg(feature = "serde_json")]
+pub mod json;
+#[cfg(feature = "serde_json")]
+pub mod json_patch;
+pub mod keyed;
+pub mod lww;
+pub mod multi;
+pub mod observe;
+pub mod ordered;
+pub mod ot;
+pub mod patch;
+pub mod path;
+pub mod preview;
+pub mod reconcile;
+pub mod rename;
+pub mod tombstone;
+#[cfg(feature = "toml_edit")]
+pub mod toml;
+pub mod track;
+pub mod undo;
+pub mod version;
+pub mod visit;
+
+pub use change_eq::ChangeEq;
+
+use visit::{Leaf, Resolution, Segment, Visitor};
+
+#[cfg(feature = "serde")]
+#[doc(hidden)]
+pub use serde;
+
 #[cfg(feature = "derive")]
 pub mod derive {
     pub use update_sync_derive::*;
@@ -11,7 +60,8 @@ pub mod derive {
 /// Provides a method to syncronise data
 ///
 /// This should be implemented such that if set and last_base differ, set is returned
-/// while if they are the same, then new_base is returned.
+/// while if they are the same, then new_base is returned. Built in implementations decide
+/// whether values differ with [`ChangeEq`].
 ///
 /// This enables a form of change detection syncronisation, where set takes priority
 /// over the last_base.
@@ -19,7 +69,7 @@ pub trait UpdateSync {
     /// Implementations will generally take the form
     ///
     /// ```.ignore
-    /// if last_base != set {
+    /// if !last_base.change_eq(&set) {
     ///     set
     /// } else {
     ///     new_base
@@ -27,42 +77,122 @@ pub trait UpdateSync {
     fn update_sync(last_base: Self, new_base: Self, set: Self) -> Self;
 }
 
-macro_rules! default_impl_update_sync {
-    ($c:ty) => {
-        impl UpdateSync for $c {
-            fn update_sync(last_base: Self, new_base: Self, set: Self) -> Self {
-                if last_base != set {
-                    set
-                } else {
-                    new_base
-                }
-            }
-        }
-    };
-    [$($c:ty),*] => {
-        $(
-            default_impl_update_sync!($c);
-        )+
-    };
-
+/// Performs the same update as [`UpdateSync::update_sync`], reporting what was decided to a
+/// [`Visitor`]
+///
+/// This is separate from `UpdateSync` so that only reporting needs values to implement `Debug`,
+/// such as the values of an `Option` or a map. The default implementation reports nothing, so
+/// types which implement `UpdateSync` by hand can take part in conflict reports and summaries with
+/// an empty `impl UpdateSyncVisit`, and are invisible to them unless they override it. Built in
+/// implementations and `#[derive(UpdateSync)]` implement it for every type whose values can be
+/// reported.
+pub trait UpdateSyncVisit: UpdateSync + Sized {
+    fn update_sync_visit(
+        last_base: Self,
+        new_base: Self,
+        set: Self,
+        visitor: &mut dyn Visitor,
+    ) -> Self {
+        let _ = visitor;
+        Self::update_sync(last_base, new_base, set)
+    }
 }
+
 default_impl_update_sync![u8, u16, u32, u64, u128, usize];
 default_impl_update_sync![i8, i16, i32, i64, i128, isize];
-default_impl_update_sync![f32, f64];
 default_impl_update_sync!(bool);
 default_impl_update_sync!(char);
+default_impl_update_sync!(());
+default_impl_update_sync![
+    NonZeroU8,
+    NonZeroU16,
+    NonZeroU32,
+    NonZeroU64,
+    NonZeroU128,
+    NonZeroUsize
+];
+default_impl_update_sync![
+    NonZeroI8,
+    NonZeroI16,
+    NonZeroI32,
+    NonZeroI64,
+    NonZeroI128,
+    NonZeroIsize
+];
+default_impl_update_sync![Duration, SystemTime];
+default_impl_update_sync![IpAddr, Ipv4Addr, Ipv6Addr];
+default_impl_update_sync![SocketAddr, SocketAddrV4, SocketAddrV6];
+default_impl_update_sync![PathBuf, OsString];
 
 // This is highly subject to change
 default_impl_update_sync!(String);
 // This is esepcailly dodgy, but will likely remain specialised like this as a specialised implementation, because arbitrary binary data is hopefully less volatile than Vec<T>
-default_impl_update_sync!(Vec<u8>);
+change_eq_impl_update_sync!(Vec<u8>);
 
-impl<T: PartialEq> UpdateSync for Option<T> {
+change_eq_impl_update_sync!(impl<T> for Option<T> where { T: ChangeEq } visit where { T: ChangeEq + Debug });
+change_eq_impl_update_sync!(impl<T> for Wrapping<T> where { T: ChangeEq } visit where { T: ChangeEq + Debug });
+
+impl<T: ?Sized> UpdateSync for PhantomData<T> {
+    fn update_sync(_: Self, _: Self, _: Self) -> Self {
+        PhantomData
+    }
+}
+
+impl<T: ?Sized> UpdateSyncVisit for PhantomData<T> {}
+
+/// Merges the same way as a derived enum, if all three are the same variant then the contents are
+/// updated, otherwise the set variant is used.
+impl<T: UpdateSync, E: UpdateSync> UpdateSync for Result<T, E> {
     fn update_sync(last_base: Self, new_base: Self, set: Self) -> Self {
-        if last_base != set {
-            set
-        } else {
-            new_base
+        match (last_base, new_base, set) {
+            (Ok(last_base), Ok(new_base), Ok(set)) => {
+                Ok(UpdateSync::update_sync(last_base, new_base, set))
+            }
+            (Err(last_base), Err(new_base), Err(set)) => {
+                Err(UpdateSync::update_sync(last_base, new_base, set))
+            }
+            (_, _, set) => set,
+        }
+    }
+}
+
+impl<T: UpdateSyncVisit, E: UpdateSyncVisit> UpdateSyncVisit for Result<T, E> {
+    fn update_sync_visit(
+        last_base: Self,
+        new_base: Self,
+        set: Self,
+        visitor: &mut dyn Visitor,
+    ) -> Self {
+        fn variant_name<T, E>(result: &Result<T, E>) -> &'static str {
+            match result {
+                Ok(_) => "Ok",
+                Err(_) => "Err",
+            }
+        }
+        match (last_base, new_base, set) {
+            (Ok(last_base), Ok(new_base), Ok(set)) => Ok(visit::visit_segment(
+                Segment::Variant("Ok"),
+                last_base,
+                new_base,
+                set,
+                visitor,
+            )),
+            (Err(last_base), Err(new_base), Err(set)) => Err(visit::visit_segment(
+                Segment::Variant("Err"),
+                last_base,
+                new_base,
+                set,
+                visitor,
+            )),
+            (last_base, new_base, set) => {
+                visit::visit_variant_change(
+                    variant_name(&last_base),
+                    variant_name(&new_base),
+                    variant_name(&set),
+                    visitor,
+                );
+                set
+            }
         }
     }
 }
@@ -84,6 +214,21 @@ macro_rules! tuple_impl_update_sync {
             }
         }
 
+        impl<$($t),+> UpdateSyncVisit for ($($t,)+)
+        where
+        $(
+            $t: UpdateSyncVisit,
+        )*
+        {
+            fn update_sync_visit(last_base: Self, new_base: Self, set: Self, visitor: &mut dyn Visitor) -> Self {
+                (
+                    $(
+                        visit::visit_segment(Segment::Index($i), last_base.$i, new_base.$i, set.$i, visitor),
+                    )*
+                )
+            }
+        }
+
     }
 }
 
@@ -98,41 +243,108 @@ tuple_impl_update_sync!(T1: 0, T2: 1, T3: 2, T4: 3, T5: 4, T6: 5, T7: 6, T8: 7);
 tuple_impl_update_sync!(T1: 0, T2: 1, T3: 2, T4: 3, T5: 4, T6: 5, T7: 6, T8: 7, T9: 8);
 tuple_impl_update_sync!(T1: 0, T2: 1, T3: 2, T4: 3, T5: 4, T6: 5, T7: 6, T8: 7, T9: 8, T10 : 9);
 
+/// Decides how a single map entry is updated, where `None` is an absent entry
+///
+/// Entries removed from `new_base` are removed, while entries removed from `set` are left as
+/// they are in `new_base`.
+fn map_entry_resolution<V: ChangeEq>(
+    last_base: Option<&V>,
+    new_base: Option<&V>,
+    set: Option<&V>,
+) -> Resolution {
+    let new_base_changed = match (last_base, new_base) {
+        (Some(l), Some(n)) => !l.change_eq(n),
+        (None, None) => false,
+        _ => true,
+    };
+    let set_changed = match (last_base, set) {
+        (Some(l), Some(s)) => !l.change_eq(s),
+        (None, Some(_)) => true,
+        (_, None) => false,
+    };
+    match (new_base_changed, set_changed) {
+        (false, false) => Resolution::Unchanged,
+        (true, false) => Resolution::NewBase,
+        (false, true) => Resolution::Set,
+        (true, true) => match (new_base, set) {
+            (Some(n), Some(s)) if n.change_eq(s) => Resolution::Agreed,
+            _ => Resolution::Conflict,
+        },
+    }
+}
+
 macro_rules! map_impl_update_sync {
+    (@entries $last_base:ident, $new_base:ident, $set:ident, $report:expr) => {{
+        let (mut new_base, mut set) = ($new_base, $set);
+        let mut new = Self::new();
+        let mut update_entry = |key: K, l: Option<V>, n: Option<V>, s: Option<V>| {
+            let resolution = map_entry_resolution(l.as_ref(), n.as_ref(), s.as_ref());
+            ($report)(&key, resolution, l.as_ref(), n.as_ref(), s.as_ref());
+            let value = if resolution.took_set() { s } else { n };
+            if let Some(value) = value {
+                new.insert(key, value);
+            }
+        };
+        // First check for changes to base fields
+        for (last_base_key, last_base_value) in $last_base.into_iter() {
+            let n = new_base.remove(&last_base_key);
+            let s = set.remove(&last_base_key);
+            update_entry(last_base_key, Some(last_base_value), n, s);
+        }
+        // Next, grab any new entries from the new base
+        for (nk, nv) in new_base.into_iter() {
+            let s = set.remove(&nk);
+            update_entry(nk, None, Some(nv), s);
+        }
+        // Finally, bring in any new entries from the set
+        for (sk, sv) in set.into_iter() {
+            update_entry(sk, None, None, Some(sv));
+        }
+
+        new
+    }};
     ($t:tt, $($traits:tt)*) => {
         impl<K, V> UpdateSync for $t<K, V>
         where
             K: $($traits)*,
-            V: UpdateSync + PartialEq,
+            V: UpdateSync + ChangeEq,
+        {
+            fn update_sync(last_base: Self, new_base: Self, set: Self) -> Self {
+                map_impl_update_sync!(
+                    @entries last_base,
+                    new_base,
+                    set,
+                    |_: &K, _: Resolution, _: Option<&V>, _: Option<&V>, _: Option<&V>| ()
+                )
+            }
+        }
+
+        impl<K, V> UpdateSyncVisit for $t<K, V>
+        where
+            K: Debug + $($traits)*,
+            V: UpdateSync + ChangeEq + Debug,
         {
-            fn update_sync(last_base: Self, mut new_base: Self, mut set: Self) -> Self {
-                let mut new = Self::new();
-                // First check for changes to base fields
-                for (last_base_key, last_base_value) in last_base.into_iter() {
-                    let n = new_base.remove(&last_base_key);
-                    let s = match set.remove(&last_base_key) {
-                        Some(sv) if sv != last_base_value => sv,
-                        _ => match n {
-                            None => continue, // If it is removed from new base, remove
-                            Some(nv) => nv,
-                        },
-                    };
-                    new.insert(last_base_key, s);
-                }
-                // Next, grab any new entries from the new base
-                for (nk, nv) in new_base.into_iter() {
-                    let s = match set.remove(&nk) {
-                        None => nv,
-                        Some(sv) => sv,
-                    };
-                    new.insert(nk, s);
-                }
-                // Finally, bring in any new entries from the set
-                for (sk, sv) in set.into_iter() {
-                    new.insert(sk, sv);
-                }
-
-                new
+            fn update_sync_visit(
+                last_base: Self,
+                new_base: Self,
+                set: Self,
+                visitor: &mut dyn Visitor,
+            ) -> Self {
+                map_impl_update_sync!(
+                    @entries last_base,
+                    new_base,
+                    set,
+                    |key: &K, resolution, l: Option<&V>, n: Option<&V>, s: Option<&V>| {
+                        visitor.enter(Segment::Key(key));
+                        visitor.leaf(Leaf {
+                            resolution,
+                            last_base: l.map(|l| l as &dyn Debug),
+                            new_base: n.map(|n| n as &dyn Debug),
+                            set: s.map(|s| s as &dyn Debug),
+                        });
+                        visitor.leave();
+                    }
+                )
             }
         }
     };
@@ -0,0 +1,154 @@
+//! Last writer wins registers
+//!
+//! Rather than letting `set` win whenever it differs from `last_base`, a last writer wins value
+//! keeps whichever of `new_base` and `set` was written most recently according to a hybrid logical
+//! [`Clock`], so the result no longer depends on the order requests reach the server. Ties between
+//! writes with the same timestamp are broken by replica id.
+//!
+//! [`Lww<T>`] wraps any value with its timestamp. Fields of a derived struct whose type is
+//! [`Stamped`] can also be given these semantics with `#[update_sync(lww)]`.
+
+use std::fmt::Debug;
+
+use crate::{
+    clock::{Clock, ReplicaId, TimeSource, Timestamp},
+    visit::{Leaf, Resolution, Visitor},
+    ChangeEq, UpdateSync, UpdateSyncVisit,
+};
+
+/// A value which knows when, and by whom, it was last written
+pub trait Stamped {
+    fn stamp(&self) -> (Timestamp, ReplicaId);
+}
+
+/// A register holding the most recently written value
+#[derive(Clone, Debug, PartialEq, Eq, Hash)]
+pub struct Lww<T> {
+    value: T,
+    timestamp: Timestamp,
+    replica: ReplicaId,
+}
+
+impl<T> Lww<T> {
+    /// Writes an initial value, timestamped by `clock`
+    pub fn new<S: TimeSource>(value: T, clock: &mut Clock<S>) -> Self {
+        Lww {
+            value,
+            timestamp: clock.now(),
+            replica: clock.replica(),
+        }
+    }
+
+    /// Rebuilds a register from a value and the stamp it was written with
+    pub fn from_parts(value: T, timestamp: Timestamp, replica: ReplicaId) -> Self {
+        Lww {
+            value,
+            timestamp,
+            replica,
+        }
+    }
+
+    pub fn get(&self) -> &T {
+        &self.value
+    }
+
+    pub fn timestamp(&self) -> Timestamp {
+        self.timestamp
+    }
+
+    pub fn replica(&self) -> ReplicaId {
+        self.replica
+    }
+
+    pub fn into_inner(self) -> T {
+        self.value
+    }
+
+    /// Writes a new value, timestamped by `clock` so that it is later than the current value
+    pub fn set<S: TimeSource>(&mut self, value: T, clock: &mut Clock<S>) {
+        self.value = value;
+        self.timestamp = clock.observe(self.timestamp);
+        self.replica = clock.replica();
+    }
+}
+
+impl<T> Stamped for Lww<T> {
+    fn stamp(&self) -> (Timestamp, ReplicaId) {
+        (self.timestamp, self.replica)
+    }
+}
+
+/// Two writes are the same if they have the same stamp
+impl<T> ChangeEq for Lww<T> {
+    fn change_eq(&self, other: &Self) -> bool {
+        self.stamp() == other.stamp()
+    }
+}
+
+impl<T> UpdateSync for Lww<T> {
+    fn update_sync(last_base: Self, new_base: Self, set: Self) -> Self {
+        update_sync_lww(last_base, new_base, set)
+    }
+}
+
+impl<T: Debug> UpdateSyncVisit for Lww<T> {
+    fn update_sync_visit(
+        last_base: Self,
+        new_base: Self,
+        set: Self,
+        visitor: &mut dyn Visitor,
+    ) -> Self {
+        update_sync_lww_visit(last_base, new_base, set, visitor)
+    }
+}
+
+/// Keeps whichever of `new_base` and `set` was written last, `last_base` is not needed
+pub fn update_sync_lww<T: Stamped>(last_base: T, new_base: T, set: T) -> T {
+    let _ = last_base;
+    if set.stamp() > new_base.stamp() {
+        set
+    } else {
+        new_base
+    }
+}
+
+/// Performs the same update as `update_sync_lww`, reporting it to `visitor`
+///
+/// A write in `set` which is older than `new_base` is reported as `Resolution::NewBase`.
+pub fn update_sync_lww_visit<T: Stamped + Debug>(
+    last_base: T,
+    new_base: T,
+    set: T,
+    visitor: &mut dyn Visitor,
+) -> T {
+    let (last_base_stamp, new_base_stamp, set_stamp) =
+        (last_base.stamp(), new_base.stamp(), set.stamp());
+    let resolution = if set_stamp > new_base_stamp {
+        if new_base_stamp == last_base_stamp {
+            Resolution::Set
+        } else {
+            Resolution::Conflict
+        }
+    } else if set_stamp == new_base_stamp {
+        if set_stamp == last_base_stamp {
+            Resolution::Unchanged
+        } else {
+            Resolution::Agreed
+        }
+    } else if new_base_stamp == last_base_stamp {
+        Resolution::Unchanged
+    } else {
+        Resolution::NewBase
+    };
+    visitor.leaf(Leaf {
+        resolution,
+        last_base: Some(&last_base),
+        new_base: Some(&new_base),
+        set: Some(&set),
+    });
+    if resolution.took_set() {
+        set
+    } else {
+        new_base
+    }
+}
@@ -0,0 +1,91 @@
+/// Implements `UpdateSync` treating the type as a single value, changed when `ChangeEq` says so
+///
+/// Generic types give the bounds for updating and the bounds for reporting separately, since only
+/// reporting needs `Debug`.
+macro_rules! change_eq_impl_update_sync {
+    (impl<$($g:ident),*> for $c:ty where { $($w:tt)* } visit where { $($v:tt)* }) => {
+        impl<$($g),*> UpdateSync for $c where $($w)* {
+            fn update_sync(last_base: Self, new_base: Self, set: Self) -> Self {
+                if !last_base.change_eq(&set) {
+                    set
+                } else {
+                    new_base
+                }
+            }
+        }
+
+        impl<$($g),*> $crate::UpdateSyncVisit for $c where $($v)* {
+            fn update_sync_visit(
+                last_base: Self,
+                new_base: Self,
+                set: Self,
+                visitor: &mut dyn $crate::visit::Visitor,
+            ) -> Self {
+                $crate::visit::visit_change_eq(last_base, new_base, set, visitor)
+            }
+        }
+    };
+    ($c:ty) => {
+        change_eq_impl_update_sync!(impl<> for $c where {} visit where {});
+    };
+}
+
+/// Implements `ChangeEq` using `PartialEq`, and `UpdateSync` treating the type as a single value
+macro_rules! default_impl_update_sync {
+    ($c:ty) => {
+        impl ChangeEq for $c {
+            fn change_eq(&self, other: &Self) -> bool {
+                self == other
+            }
+        }
+        change_eq_impl_update_sync!($c);
+    };
+    [$($c:ty),*] => {
+        $(
+            default_impl_update_sync!($c);
+        )+
+    };
+
+}
+
+/// Implements `ChangeEq` using `PartialEq`, and `UpdateSync` by joining `set` into `new_base`
+///
+/// Visitors see the whole value as one leaf, which is `Merged` where both sides changed it.
+macro_rules! join_impl_update_sync {
+    (impl<$($g:ident),*> for $c:ty $(where $($w:tt)*)?) => {
+        impl<$($g),*> $crate::ChangeEq for $c $(where $($w)*)? {
+            fn change_eq(&self, other: &Self) -> bool {
+                self == other
+            }
+        }
+
+        impl<$($g),*> $crate::UpdateSync for $c $(where $($w)*)? {
+            fn update_sync(_: Self, mut new_base: Self, set: Self) -> Self {
+                $crate::crdt::Join::join(&mut new_base, set);
+                new_base
+            }
+        }
+
+        impl<$($g),*> $crate::UpdateSyncVisit for $c
+        where
+            Self: ::std::fmt::Debug,
+            $($($w)*)?
+        {
+            fn update_sync_visit(
+                last_base: Self,
+                new_base: Self,
+                set: Self,
+                visitor: &mut dyn $crate::visit::Visitor,
+            ) -> Self {
+                $crate::visit::visit_merge(
+                    last_base,
+                    new_base,
+                    set,
+                    |a, b| a == b,
+                    <Self as $crate::UpdateSync>::update_sync,
+                    visitor,
+                )
+            }
+        }
+    };
+}
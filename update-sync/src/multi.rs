@@ -0,0 +1,132 @@
+//! Merging many concurrent client submissions at once
+//!
+//! A server which batches writes would otherwise apply them one after another with
+//! `update_sync`, letting the order they happened to arrive in pick the winners. Here submissions
+//! are ordered by a client chosen key, such as a client id or a `(timestamp, client id)` pair, so
+//! the result is the same however the batch was collected, and every overwritten change is
+//! reported.
+
+use std::collections::BTreeMap;
+
+use crate::{
+    path::Path,
+    visit::{Recorder, Resolution},
+    UpdateSyncVisit,
+};
+
+/// A single client's change
+#[derive(Clone, Debug, PartialEq)]
+pub struct Submission<C, T> {
+    /// Identifies the client, and orders the submissions, greater keys win ties
+    pub client: C,
+    /// The state this client last synced, if it differs from the shared `last_base`
+    pub last_base: Option<T>,
+    /// The state the client wishes to change to
+    pub set: T,
+}
+
+impl<C, T> Submission<C, T> {
+    /// A submission based on the shared `last_base`
+    pub fn new(client: C, set: T) -> Self {
+        Submission {
+            client,
+            last_base: None,
+            set,
+        }
+    }
+
+    /// A submission based on its own revision of the data
+    pub fn with_base(client: C, last_base: T, set: T) -> Self {
+        Submission {
+            client,
+            last_base: Some(last_base),
+            set,
+        }
+    }
+}
+
+/// Who made a change
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub enum Author<C> {
+    /// The change was already in `new_base`
+    Server,
+    /// The change came from the submission with this client key
+    Client(C),
+}
+
+/// A value which more than one author changed, only one of whom was kept
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct Conflict<C> {
+    /// Where the value is, such as `users["bob"].name`
+    pub location: Path,
+    /// The client whose change is in the merged result
+    pub winner: C,
+    /// The authors whose changes were overwritten, in the order they were applied
+    pub overwritten: Vec<Author<C>>,
+}
+
+/// The result of merging many submissions
+#[derive(Clone, Debug, PartialEq)]
+pub struct MultiMerge<C, T> {
+    pub merged: T,
+    /// Every conflict, ordered by location
+    pub conflicts: Vec<Conflict<C>>,
+}
+
+/// Merges every submission into `new_base`, applying them in order of their client keys
+///
+/// Each submission is updated as though by `update_sync`, against its own `last_base` if it has
+/// one or the shared `last_base` otherwise. When submissions conflict, the greatest client key
+/// wins.
+pub fn update_sync_many<C, T>(
+    last_base: T,
+    new_base: T,
+    submissions: impl IntoIterator<Item = Submission<C, T>>,
+) -> MultiMerge<C, T>
+where
+    C: Ord + Clone,
+    T: UpdateSyncVisit + Clone,
+{
+    let mut submissions: Vec<_> = submissions.into_iter().collect();
+    submissions.sort_by(|a, b| a.client.cmp(&b.client));
+
+    let mut merged = new_base;
+    let mut authors: BTreeMap<Path, C> = BTreeMap::new();
+    let mut conflicts: BTreeMap<Path, Conflict<C>> = BTreeMap::new();
+    for Submission {
+        client,
+        last_base: submission_base,
+        set,
+    } in submissions
+    {
+        let base = submission_base.unwrap_or_else(|| last_base.clone());
+        let mut recorder = Recorder::default();
+        merged = T::update_sync_visit(base, merged, set, &mut recorder);
+        for (location, resolution) in recorder.leaves {
+            if !resolution.took_set() {
+                continue;
+            }
+            let previous = authors.insert(location.clone(), client.clone());
+            if resolution != Resolution::Conflict {
+                continue;
+            }
+            let overwritten = previous.map_or(Author::Server, Author::Client);
+            conflicts
+                .entry(location.clone())
+                .and_modify(|conflict| {
+                    conflict.winner = client.clone();
+                    conflict.overwritten.push(overwritten.clone());
+                })
+                .or_insert_with(|| Conflict {
+                    location,
+                    winner: client.clone(),
+                    overwritten: vec![overwritten],
+                });
+        }
+    }
+
+    MultiMerge {
+        merged,
+        conflicts: conflicts.into_values().collect(),
+    }
+}
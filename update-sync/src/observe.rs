@@ -0,0 +1,130 @@
+//! Notifying observers of the values an update changed
+//!
+//! Once an update is merged, caches built from `new_base` need invalidating, and users watching it
+//! need notifying, but only for the values which actually changed. [`Observers`] performs updates
+//! as a [`Visitor`](crate::visit::Visitor), calling every subscribed callback once for each value
+//! the update changed in `new_base`, with its [`Path`] and its value before and after.
+//!
+//! Subscriptions can be limited to the values under a path prefix, such as `users["bob"]`. This
+//! works for every type implementing `UpdateSyncVisit`, including derived structs and enums,
+//! and maps. Values updated as a whole, such as map entries, are reported at their own path, so a
+//! subscription to a path inside one, such as `users["bob"].name`, is notified whenever the whole
+//! value changes.
+
+use std::fmt::Debug;
+
+use crate::{
+    path::Path,
+    visit::{Leaf, Resolution, Segment, Visitor},
+    UpdateSyncVisit,
+};
+
+/// A value which an update changed in `new_base`
+///
+/// `old` and `new` are `None` where the value is absent, such as a map entry which was added or
+/// removed. Where both sides' changes were merged, with `resolution` as `Resolution::Merged`,
+/// `new` is also `None`, as the merged value isn't known until after it is reported.
+///
+/// `path` may be an ancestor of the subscribed prefix, rather than under it, where a value
+/// containing the prefix was updated as a whole. `old` and `new` are then the whole value, and
+/// `contains_prefix` is true.
+#[derive(Clone, Copy)]
+pub struct ChangeEvent<'a> {
+    pub path: &'a Path,
+    pub resolution: Resolution,
+    pub old: Option<&'a dyn Debug>,
+    pub new: Option<&'a dyn Debug>,
+    /// Whether `path` is above the subscribed prefix, so the change is to a value containing it
+    pub contains_prefix: bool,
+}
+
+/// Identifies a subscription, so it can be removed
+#[derive(Clone, Copy, Debug, PartialEq, Eq, Hash)]
+pub struct SubscriptionId(usize);
+
+type Callback = Box<dyn FnMut(&ChangeEvent<'_>)>;
+
+/// Callbacks which are notified of the values each update changes
+#[derive(Default)]
+pub struct Observers {
+    subscriptions: Vec<(SubscriptionId, Path, Callback)>,
+    next_id: usize,
+    path: Path,
+}
+
+impl Observers {
+    pub fn new() -> Self {
+        Observers::default()
+    }
+
+    /// Calls `callback` for every changed value at or under `prefix`, or containing it
+    pub fn subscribe(
+        &mut self,
+        prefix: Path,
+        callback: impl FnMut(&ChangeEvent<'_>) + 'static,
+    ) -> SubscriptionId {
+        let id = SubscriptionId(self.next_id);
+        self.next_id += 1;
+        self.subscriptions.push((id, prefix, Box::new(callback)));
+        id
+    }
+
+    /// Calls `callback` for every changed value
+    pub fn subscribe_all(
+        &mut self,
+        callback: impl FnMut(&ChangeEvent<'_>) + 'static,
+    ) -> SubscriptionId {
+        self.subscribe(Path::new(), callback)
+    }
+
+    /// Removes a subscription, returning true if it existed
+    pub fn unsubscribe(&mut self, id: SubscriptionId) -> bool {
+        let len = self.subscriptions.len();
+        self.subscriptions
+            .retain(|(subscription, _, _)| *subscription != id);
+        self.subscriptions.len() != len
+    }
+
+    /// Updates `new_base` with `set` as `update_sync` would, notifying subscribers of every value
+    /// it changes
+    pub fn update<T: UpdateSyncVisit>(&mut self, last_base: T, new_base: T, set: T) -> T {
+        self.path = Path::new();
+        T::update_sync_visit(last_base, new_base, set, self)
+    }
+}
+
+impl Visitor for Observers {
+    fn enter(&mut self, segment: Segment<'_>) {
+        self.path.push(segment);
+    }
+
+    fn leave(&mut self) {
+        self.path.pop();
+    }
+
+    fn leaf(&mut self, leaf: Leaf<'_>) {
+        if !leaf.resolution.changed_new_base() {
+            return;
+        }
+        let event = ChangeEvent {
+            path: &self.path,
+            resolution: leaf.resolution,
+            old: leaf.new_base,
+            new: match leaf.resolution {
+                Resolution::Merged => None,
+                _ => leaf.set,
+            },
+            contains_prefix: false,
+        };
+        for (_, prefix, callback) in &mut self.subscriptions {
+            if self.path.starts_with(prefix) {
+                callback(&event);
+            } else if prefix.starts_with(&self.path) {
+                callback(&ChangeEvent {
+                    contains_prefix: true,
+                    ..event
+                });
+            }
+        }
+    }
+}
@@ -0,0 +1,322 @@
+//! Ordered collections which can be reordered concurrently
+//!
+//! Rather than storing its elements in a `Vec`, where moving one element shifts the index of every
+//! other, an [`OrderedList`] gives each element a fractional [`Position`]. Positions are strings
+//! which sort lexicographically, and there is always another position between any two, so inserting
+//! or moving an element only ever changes that element's position.
+//!
+//! The positions and values are kept in maps, so updating an `OrderedList` is done by the `BTreeMap`
+//! `UpdateSync` implementation. Moves and inserts made by both sides are all kept, and only moves
+//! of the same element conflict. Two elements inserted at the same position are ordered by key.
+
+use std::{
+    collections::BTreeMap,
+    fmt::{self, Debug},
+    iter::FromIterator,
+};
+
+use crate::{
+    visit::{visit_segment, Segment, Visitor},
+    ChangeEq, UpdateSync, UpdateSyncVisit,
+};
+
+/// The digits positions are made of, in ascending order
+const DIGITS: &[u8] = b"0123456789ABCDEFGHIJKLMNOPQRSTUVWXYZabcdefghijklmnopqrstuvwxyz";
+
+/// A place in an [`OrderedList`]
+///
+/// Positions never end with the digit `0`, which guarantees there is a position between any two.
+#[derive(Clone, Debug, PartialEq, Eq, PartialOrd, Ord, Hash)]
+pub struct Position(String);
+
+default_impl_update_sync!(Position);
+
+impl Position {
+    /// A position after `before` and before `after`, where `None` is the start or end of the list
+    ///
+    /// # Panics
+    ///
+    /// Panics if `before` is not less than `after`.
+    pub fn between(before: Option<&Position>, after: Option<&Position>) -> Position {
+        let before = before.map_or(&[][..], |before| before.0.as_bytes());
+        let after = after.map(|after| after.0.as_bytes());
+        if let Some(after) = after {
+            assert!(before < after, "positions must be in ascending order");
+        }
+        let digits = midpoint(
+            &before.iter().map(|&digit| value(digit)).collect::<Vec<_>>(),
+            after
+                .map(|after| after.iter().map(|&digit| value(digit)).collect::<Vec<_>>())
+                .as_deref(),
+        );
+        Position(
+            digits
+                .into_iter()
+                .map(|digit| DIGITS[digit] as char)
+                .collect(),
+        )
+    }
+
+    pub fn as_str(&self) -> &str {
+        &self.0
+    }
+}
+
+impl fmt::Display for Position {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        f.write_str(&self.0)
+    }
+}
+
+fn value(digit: u8) -> usize {
+    DIGITS
+        .iter()
+        .position(|&d| d == digit)
+        .expect("positions only contain digits")
+}
+
+/// The digits of a fraction between `before` and `after`, where a missing `after` is one
+fn midpoint(before: &[usize], after: Option<&[usize]>) -> Vec<usize> {
+    if let Some(after) = after {
+        // Keep any digits both share, treating missing digits of `before` as zeros
+        let shared = after
+            .iter()
+            .enumerate()
+            .take_while(|&(i, &digit)| before.get(i).copied().unwrap_or(0) == digit)
+            .count();
+        if shared > 0 {
+            let mut digits = after[..shared].to_vec();
+            digits.extend(midpoint(
+                before.get(shared..).unwrap_or(&[]),
+                Some(&after[shared..]),
+            ));
+            return digits;
+        }
+    }
+    let low = before.first().copied().unwrap_or(0);
+    let high = after.map_or(DIGITS.len(), |after| after[0]);
+    if high - low > 1 {
+        vec![(low + high) / 2]
+    } else if after.is_some_and(|after| after.len() > 1) {
+        // `after` continues past its first digit, so that digit alone is between the two
+        vec![high]
+    } else {
+        let mut digits = vec![low];
+        digits.extend(midpoint(before.get(1..).unwrap_or(&[]), None));
+        digits
+    }
+}
+
+/// A list whose elements are identified by key and ordered by their [`Position`]
+///
+/// Removed elements keep their key, without a position, so that a concurrent move can't bring
+/// them back without conflicting. An element which one side moved and the other removed, without
+/// changing its value, stays removed.
+#[derive(Clone, Debug, PartialEq)]
+pub struct OrderedList<K: Ord, V> {
+    positions: BTreeMap<K, Option<Position>>,
+    values: BTreeMap<K, V>,
+}
+
+impl<K: Ord, V> Default for OrderedList<K, V> {
+    fn default() -> Self {
+        OrderedList {
+            positions: BTreeMap::new(),
+            values: BTreeMap::new(),
+        }
+    }
+}
+
+impl<K: Ord + Clone, V> OrderedList<K, V> {
+    pub fn new() -> Self {
+        OrderedList::default()
+    }
+
+    /// The keys of the elements, in order, along with their positions
+    fn order(&self) -> Vec<(&Position, &K)> {
+        let mut order: Vec<_> = self
+            .positions
+            .iter()
+            .filter_map(|(key, position)| Some((position.as_ref()?, key)))
+            .collect();
+        order.sort();
+        order
+    }
+
+    pub fn len(&self) -> usize {
+        self.positions.values().filter(|p| p.is_some()).count()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.len() == 0
+    }
+
+    pub fn contains_key(&self, key: &K) -> bool {
+        std::matches!(self.positions.get(key), Some(Some(_)))
+    }
+
+    pub fn get(&self, key: &K) -> Option<&V> {
+        self.values.get(key).filter(|_| self.contains_key(key))
+    }
+
+    pub fn get_mut(&mut self, key: &K) -> Option<&mut V> {
+        if self.contains_key(key) {
+            self.values.get_mut(key)
+        } else {
+            None
+        }
+    }
+
+    pub fn position(&self, key: &K) -> Option<&Position> {
+        self.positions.get(key)?.as_ref()
+    }
+
+    /// Where the element is in the list
+    pub fn index_of(&self, key: &K) -> Option<usize> {
+        self.order().iter().position(|(_, k)| *k == key)
+    }
+
+    /// The elements in order
+    pub fn iter(&self) -> impl Iterator<Item = (&K, &V)> {
+        self.order()
+            .into_iter()
+            .map(move |(_, key)| (key, &self.values[key]))
+    }
+
+    pub fn keys(&self) -> impl Iterator<Item = &K> {
+        self.order().into_iter().map(|(_, key)| key)
+    }
+
+    /// A position which places an element at `index` once `key` is taken out of the list
+    ///
+    /// When elements inserted concurrently share a position, the new element is placed after all
+    /// of them, as there is no position between them.
+    fn position_at(&self, index: usize, key: &K) -> Position {
+        let order: Vec<&Position> = self
+            .order()
+            .into_iter()
+            .filter(|(_, k)| *k != key)
+            .map(|(position, _)| position)
+            .collect();
+        assert!(index <= order.len(), "insertion index out of bounds");
+        let before = index.checked_sub(1).map(|i| order[i]);
+        let after = order[index..]
+            .iter()
+            .copied()
+            .find(|&after| before.is_none_or(|before| after > before));
+        Position::between(before, after)
+    }
+
+    /// Inserts an element so that it is at `index`, moving it there if it is already in the list
+    ///
+    /// # Panics
+    ///
+    /// Panics if `index` is greater than the length of the list.
+    pub fn insert(&mut self, index: usize, key: K, value: V) {
+        let position = self.position_at(index, &key);
+        self.positions.insert(key.clone(), Some(position));
+        self.values.insert(key, value);
+    }
+
+    pub fn push(&mut self, key: K, value: V) {
+        let index = self.len() - usize::from(self.contains_key(&key));
+        self.insert(index, key, value);
+    }
+
+    /// Moves an element to `index`, returning false if it is not in the list
+    ///
+    /// # Panics
+    ///
+    /// Panics if `index` is not less than the length of the list.
+    pub fn move_to(&mut self, key: &K, index: usize) -> bool {
+        if !self.contains_key(key) {
+            return false;
+        }
+        let position = self.position_at(index, key);
+        self.positions.insert(key.clone(), Some(position));
+        true
+    }
+
+    pub fn remove(&mut self, key: &K) -> Option<V> {
+        self.positions.get_mut(key)?.take()?;
+        self.values.remove(key)
+    }
+}
+
+impl<K: Ord + Clone, V> FromIterator<(K, V)> for OrderedList<K, V> {
+    fn from_iter<I: IntoIterator<Item = (K, V)>>(iter: I) -> Self {
+        let mut list = OrderedList::new();
+        for (key, value) in iter {
+            list.push(key, value);
+        }
+        list
+    }
+}
+
+impl<K: Ord, V: ChangeEq> ChangeEq for OrderedList<K, V> {
+    fn change_eq(&self, other: &Self) -> bool {
+        self.positions == other.positions && self.values.change_eq(&other.values)
+    }
+}
+
+impl<K: Ord, V> OrderedList<K, V> {
+    /// Puts the updated positions and values back together
+    fn from_updated(
+        mut positions: BTreeMap<K, Option<Position>>,
+        mut values: BTreeMap<K, V>,
+    ) -> Self {
+        // Values of removed elements are only kept by the side which didn't remove them
+        values.retain(|key, _| std::matches!(positions.get(key), Some(Some(_))));
+        // An element moved by one side and removed by the other has lost its value, so it stays
+        // removed rather than being left in the list without one
+        for (key, position) in &mut positions {
+            if !values.contains_key(key) {
+                *position = None;
+            }
+        }
+        OrderedList { positions, values }
+    }
+}
+
+impl<K, V> UpdateSync for OrderedList<K, V>
+where
+    K: Ord + Clone,
+    V: UpdateSync + ChangeEq,
+{
+    fn update_sync(last_base: Self, new_base: Self, set: Self) -> Self {
+        OrderedList::from_updated(
+            UpdateSync::update_sync(last_base.positions, new_base.positions, set.positions),
+            UpdateSync::update_sync(last_base.values, new_base.values, set.values),
+        )
+    }
+}
+
+impl<K, V> UpdateSyncVisit for OrderedList<K, V>
+where
+    K: Ord + Clone + Debug,
+    V: UpdateSyncVisit + ChangeEq + Debug,
+{
+    fn update_sync_visit(
+        last_base: Self,
+        new_base: Self,
+        set: Self,
+        visitor: &mut dyn Visitor,
+    ) -> Self {
+        OrderedList::from_updated(
+            visit_segment(
+                Segment::Field("positions"),
+                last_base.positions,
+                new_base.positions,
+                set.positions,
+                visitor,
+            ),
+            visit_segment(
+                Segment::Field("values"),
+                last_base.values,
+                new_base.values,
+                set.values,
+                visitor,
+            ),
+        )
+    }
+}
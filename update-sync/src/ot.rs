@@ -0,0 +1,396 @@
+//! Operational transformation of text
+//!
+//! Rather than sending whole strings, editors can describe each change as an [`Operation`]: a
+//! walk over the document which retains, inserts and deletes characters. Two operations made
+//! concurrently against the same document can be [`transform`]ed so that each applies after the
+//! other, and both orders give the same document.
+//!
+//! An [`OtServer`] orders the operations of every client, rebasing each one against the operations
+//! committed since the revision the client made it against. `String` fields of derived structs can
+//! merge concurrent edits the same way with
+//! `#[update_sync(with = "update_sync::ot::update_sync_text")]`.
+//!
+//! Lengths and positions count `char`s, not bytes.
+
+use std::{error::Error, fmt, slice};
+
+/// A single step of an [`Operation`]
+#[derive(Clone, Debug, PartialEq, Eq, Hash)]
+pub enum Component {
+    /// Skips over this many characters, leaving them as they are
+    Retain(usize),
+    /// Inserts this text
+    Insert(String),
+    /// Deletes this many characters
+    Delete(usize),
+}
+
+impl Component {
+    /// The number of characters this component covers
+    fn len(&self) -> usize {
+        match self {
+            Component::Retain(len) | Component::Delete(len) => *len,
+            Component::Insert(text) => text.chars().count(),
+        }
+    }
+
+    /// Splits off the first `len` characters, leaving the rest in `self`
+    fn split_off_front(&mut self, len: usize) -> Component {
+        match self {
+            Component::Retain(rest) => {
+                *rest -= len;
+                Component::Retain(len)
+            }
+            Component::Delete(rest) => {
+                *rest -= len;
+                Component::Delete(len)
+            }
+            Component::Insert(text) => {
+                let at = text
+                    .char_indices()
+                    .nth(len)
+                    .map_or(text.len(), |(at, _)| at);
+                let rest = text.split_off(at);
+                Component::Insert(std::mem::replace(text, rest))
+            }
+        }
+    }
+}
+
+/// A change to a document, made up of components which together cover the whole document
+#[derive(Clone, Debug, Default, PartialEq, Eq, Hash)]
+pub struct Operation {
+    components: Vec<Component>,
+    base_len: usize,
+    target_len: usize,
+}
+
+impl Operation {
+    /// An empty operation, which only applies to an empty document until components are added
+    pub fn new() -> Self {
+        Operation::default()
+    }
+
+    pub fn retain(mut self, len: usize) -> Self {
+        self.push(Component::Retain(len));
+        self
+    }
+
+    pub fn insert(mut self, text: &str) -> Self {
+        self.push(Component::Insert(text.to_owned()));
+        self
+    }
+
+    pub fn delete(mut self, len: usize) -> Self {
+        self.push(Component::Delete(len));
+        self
+    }
+
+    /// The operation which changes `old` into `new`, as a single deletion and insertion between
+    /// their longest common prefix and suffix
+    pub fn diff(old: &str, new: &str) -> Self {
+        let old: Vec<char> = old.chars().collect();
+        let new: Vec<char> = new.chars().collect();
+        let prefix = old.iter().zip(&new).take_while(|(a, b)| a == b).count();
+        let suffix = old[prefix..]
+            .iter()
+            .rev()
+            .zip(new[prefix..].iter().rev())
+            .take_while(|(a, b)| a == b)
+            .count();
+        let inserted: String = new[prefix..new.len() - suffix].iter().collect();
+        Operation::new()
+            .retain(prefix)
+            .delete(old.len() - prefix - suffix)
+            .insert(&inserted)
+            .retain(suffix)
+    }
+
+    pub fn components(&self) -> &[Component] {
+        &self.components
+    }
+
+    /// The length of the documents this operation applies to
+    pub fn base_len(&self) -> usize {
+        self.base_len
+    }
+
+    /// The length of the documents this operation produces
+    pub fn target_len(&self) -> usize {
+        self.target_len
+    }
+
+    /// Returns true if applying this operation never changes a document
+    pub fn is_noop(&self) -> bool {
+        self.components
+            .iter()
+            .all(|component| std::matches!(component, Component::Retain(_)))
+    }
+
+    /// Applies the operation to `document`
+    pub fn apply(&self, document: &str) -> Result<String, OtError> {
+        let mut chars = document.chars();
+        let found = chars.clone().count();
+        if found != self.base_len {
+            return Err(OtError::LengthMismatch {
+                expected: self.base_len,
+                found,
+            });
+        }
+        let mut applied = String::with_capacity(document.len());
+        for component in &self.components {
+            match component {
+                Component::Retain(len) => applied.extend(chars.by_ref().take(*len)),
+                Component::Insert(text) => applied.push_str(text),
+                Component::Delete(len) => chars.by_ref().take(*len).for_each(drop),
+            }
+        }
+        Ok(applied)
+    }
+
+    /// Adds a component, merging it with the last one where possible
+    ///
+    /// Inserts are always placed before deletes they follow, so that operations making the same
+    /// change are equal.
+    fn push(&mut self, component: Component) {
+        match &component {
+            Component::Retain(len) => {
+                self.base_len += len;
+                self.target_len += len;
+            }
+            Component::Insert(text) => self.target_len += text.chars().count(),
+            Component::Delete(len) => self.base_len += len,
+        }
+        if component.len() == 0 {
+            return;
+        }
+        let last = self.components.len();
+        match (self.components.last_mut(), component) {
+            (Some(Component::Retain(len)), Component::Retain(more))
+            | (Some(Component::Delete(len)), Component::Delete(more)) => *len += more,
+            (Some(Component::Insert(text)), Component::Insert(more)) => text.push_str(&more),
+            (Some(Component::Delete(_)), Component::Insert(more)) => {
+                match self.components.get_mut(last.wrapping_sub(2)) {
+                    Some(Component::Insert(text)) => text.push_str(&more),
+                    _ => self.components.insert(last - 1, Component::Insert(more)),
+                }
+            }
+            (_, component) => self.components.push(component),
+        }
+    }
+}
+
+/// Walks the components of an operation, splitting them when only part is needed
+struct Cursor<'a> {
+    components: slice::Iter<'a, Component>,
+    head: Option<Component>,
+}
+
+impl<'a> Cursor<'a> {
+    fn new(operation: &'a Operation) -> Self {
+        let mut components = operation.components.iter();
+        let head = components.next().cloned();
+        Cursor { components, head }
+    }
+
+    fn peek(&self) -> Option<&Component> {
+        self.head.as_ref()
+    }
+
+    /// Takes up to `len` characters of the current component
+    fn take(&mut self, len: usize) -> Component {
+        let head = self.head.as_mut().expect("cursor is not exhausted");
+        if len < head.len() {
+            return head.split_off_front(len);
+        }
+        let head = self.head.take().expect("cursor is not exhausted");
+        self.head = self.components.next().cloned();
+        head
+    }
+
+    /// Takes the whole of the current component
+    fn take_all(&mut self) -> Component {
+        self.take(usize::MAX)
+    }
+}
+
+/// Combines `first` and `second`, which applies to the result of `first`, into a single operation
+/// with the same effect as applying one after the other
+pub fn compose(first: &Operation, second: &Operation) -> Result<Operation, OtError> {
+    if first.target_len != second.base_len {
+        return Err(OtError::LengthMismatch {
+            expected: first.target_len,
+            found: second.base_len,
+        });
+    }
+    let mut composed = Operation::new();
+    let (mut a, mut b) = (Cursor::new(first), Cursor::new(second));
+    loop {
+        match (a.peek(), b.peek()) {
+            (None, None) => break,
+            // Deletions from `first` are of text `second` never sees
+            (Some(Component::Delete(_)), _) => composed.push(a.take_all()),
+            // Insertions from `second` are of text `first` never saw
+            (_, Some(Component::Insert(_))) => composed.push(b.take_all()),
+            (Some(x), Some(y)) => {
+                let len = x.len().min(y.len());
+                match (a.take(len), b.take(len)) {
+                    (Component::Retain(_), Component::Retain(_)) => {
+                        composed.push(Component::Retain(len))
+                    }
+                    (Component::Retain(_), Component::Delete(_)) => {
+                        composed.push(Component::Delete(len))
+                    }
+                    (insert @ Component::Insert(_), Component::Retain(_)) => composed.push(insert),
+                    // Text inserted by `first` and deleted by `second` was never there
+                    (Component::Insert(_), Component::Delete(_)) => {}
+                    _ => unreachable!("deletes and inserts are handled above"),
+                }
+            }
+            _ => unreachable!("operations cover documents of the same length"),
+        }
+    }
+    Ok(composed)
+}
+
+/// Transforms two operations made concurrently against the same document, returning
+/// `(a_prime, b_prime)` such that applying `a` then `b_prime` gives the same document as applying
+/// `b` then `a_prime`
+///
+/// When both insert at the same position, the text inserted by `a` comes first.
+pub fn transform(a: &Operation, b: &Operation) -> Result<(Operation, Operation), OtError> {
+    if a.base_len != b.base_len {
+        return Err(OtError::LengthMismatch {
+            expected: a.base_len,
+            found: b.base_len,
+        });
+    }
+    let (mut a_prime, mut b_prime) = (Operation::new(), Operation::new());
+    let (mut a, mut b) = (Cursor::new(a), Cursor::new(b));
+    loop {
+        match (a.peek(), b.peek()) {
+            (None, None) => break,
+            (Some(Component::Insert(_)), _) => {
+                let insert = a.take_all();
+                b_prime.push(Component::Retain(insert.len()));
+                a_prime.push(insert);
+            }
+            (_, Some(Component::Insert(_))) => {
+                let insert = b.take_all();
+                a_prime.push(Component::Retain(insert.len()));
+                b_prime.push(insert);
+            }
+            (Some(x), Some(y)) => {
+                let len = x.len().min(y.len());
+                match (a.take(len), b.take(len)) {
+                    (Component::Retain(_), Component::Retain(_)) => {
+                        a_prime.push(Component::Retain(len));
+                        b_prime.push(Component::Retain(len));
+                    }
+                    // Both deleted the same text, so neither needs to again
+                    (Component::Delete(_), Component::Delete(_)) => {}
+                    (Component::Delete(_), Component::Retain(_)) => {
+                        a_prime.push(Component::Delete(len))
+                    }
+                    (Component::Retain(_), Component::Delete(_)) => {
+                        b_prime.push(Component::Delete(len))
+                    }
+                    _ => unreachable!("inserts are handled above"),
+                }
+            }
+            _ => unreachable!("operations cover documents of the same length"),
+        }
+    }
+    Ok((a_prime, b_prime))
+}
+
+/// Why an operation couldn't be used
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub enum OtError {
+    /// An operation was used with a document, or another operation, of the wrong length
+    LengthMismatch { expected: usize, found: usize },
+    /// A client sent an operation made against a revision the server hasn't reached
+    UnknownRevision { revision: usize, current: usize },
+}
+
+impl fmt::Display for OtError {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self {
+            OtError::LengthMismatch { expected, found } => write!(
+                f,
+                "expected a document of length {}, found length {}",
+                expected, found
+            ),
+            OtError::UnknownRevision { revision, current } => write!(
+                f,
+                "revision {} is ahead of the current revision {}",
+                revision, current
+            ),
+        }
+    }
+}
+
+impl Error for OtError {}
+
+/// Orders the operations of every client editing a single document
+#[derive(Clone, Debug, Default, PartialEq)]
+pub struct OtServer {
+    document: String,
+    history: Vec<Operation>,
+}
+
+impl OtServer {
+    pub fn new(document: String) -> Self {
+        OtServer {
+            document,
+            history: Vec::new(),
+        }
+    }
+
+    pub fn document(&self) -> &str {
+        &self.document
+    }
+
+    /// The number of operations committed so far
+    pub fn revision(&self) -> usize {
+        self.history.len()
+    }
+
+    /// The operations committed after `revision`, which a client at that revision needs to apply
+    pub fn history_since(&self, revision: usize) -> Result<&[Operation], OtError> {
+        self.history
+            .get(revision..)
+            .ok_or(OtError::UnknownRevision {
+                revision,
+                current: self.revision(),
+            })
+    }
+
+    /// Commits an operation a client made against `revision`
+    ///
+    /// The operation is transformed against every operation committed since, and returned as it
+    /// was applied, to be sent to the other clients.
+    pub fn receive(&mut self, revision: usize, operation: Operation) -> Result<Operation, OtError> {
+        let mut operation = operation;
+        for committed in self.history_since(revision)? {
+            operation = transform(&operation, committed)?.0;
+        }
+        self.document = operation.apply(&self.document)?;
+        self.history.push(operation.clone());
+        Ok(operation)
+    }
+}
+
+/// Merges the edits `new_base` and `set` each made to `last_base`, rather than letting `set`
+/// overwrite the whole string
+///
+/// Each side's edit is found with [`Operation::diff`], then `set`'s is transformed to apply after
+/// `new_base`'s. Where both insert at the same position, `new_base`'s text comes first.
+pub fn update_sync_text(last_base: String, new_base: String, set: String) -> String {
+    let server = Operation::diff(&last_base, &new_base);
+    let client = Operation::diff(&last_base, &set);
+    let (_, client) = transform(&server, &client).expect("both edits are of `last_base`");
+    client
+        .apply(&new_base)
+        .expect("transformed edit applies after `new_base`")
+}
@@ -0,0 +1,349 @@
+//! Typed patches holding only what changed
+//!
+//! `#[derive(Patch)]` on a struct with named fields, such as `Record`, generates a `RecordPatch`
+//! with an `Option` for each field, which is `Some` only for fields which changed. Fields whose
+//! types also derive `Patch` hold their own patch type, so a patch to a nested struct holds only
+//! its changed fields, while all other values are replaced whole.
+//!
+//! Maps hold only the entries which were changed or added, as entries removed by `set` are left as
+//! they are in `new_base` by `update_sync`. Fields with an `update_sync` option, such as `epsilon`,
+//! `lww` or `with`, hold both their old and new values, so they can be updated the same way as
+//! `#[derive(UpdateSync)]` updates them, while fields marked `skip_change_eq` are left out.
+//!
+//! Tuples hold a patch for each element. CRDTs and `Lww` values are sent whole and joined into the
+//! value they are applied to. Types which need `last_base` to update, such as `KeyedVec` or
+//! `Versioned`, hold both their old and new values, as do `Result`s, whose contents are updated
+//! when both are the same variant. Enums and other types deriving `Patch` without named fields
+//! are replaced whole.
+//!
+//! `Record::diff(&last_base, &set)` gives the patch a client sends, and
+//! `RecordPatch::apply_to(new_base)` applies it on the server, with the same result as
+//! `update_sync(last_base, new_base, set)`. With the `serde` feature, the generated patches
+//! implement `Serialize` and `Deserialize`, leaving out unchanged fields.
+
+use std::{
+    collections::{BTreeMap, HashMap},
+    ffi::OsString,
+    marker::PhantomData,
+    net::{IpAddr, Ipv4Addr, Ipv6Addr, SocketAddr, SocketAddrV4, SocketAddrV6},
+    num::{
+        NonZeroI128, NonZeroI16, NonZeroI32, NonZeroI64, NonZeroI8, NonZeroIsize, NonZeroU128,
+        NonZeroU16, NonZeroU32, NonZeroU64, NonZeroU8, NonZeroUsize, Wrapping,
+    },
+    path::PathBuf,
+    time::{Duration, SystemTime},
+};
+
+use crate::{
+    clock::{ReplicaId, Timestamp},
+    crdt::{GCounter, Join, LwwMap, MvRegister, OrSet, PnCounter, Sequence},
+    keyed::{Keyed, KeyedVec},
+    lww::{Lww, Stamped},
+    ordered::{OrderedList, Position},
+    tombstone::TombstoneMap,
+    version::{VersionVector, Versioned},
+    ChangeEq, UpdateSync,
+};
+
+/// A value whose changes can be described by a patch, usually derived with `#[derive(Patch)]`
+pub trait Patch {
+    /// The changes to the value, `Self` for values which are replaced whole
+    type Patch;
+
+    /// The changes from `old` to `new`, or `None` if it is unchanged
+    fn diff_patch(old: &Self, new: &Self) -> Option<Self::Patch>;
+
+    /// Applies the changes in `patch`, keeping every value it doesn't change as it is
+    fn apply_patch(&mut self, patch: Self::Patch);
+}
+
+/// Deserializes a field which is present as `Some`, even if it holds `null`
+///
+/// Used for the fields of derived patches, so that a patch setting an `Option` to `None` isn't
+/// read back as leaving it unchanged.
+#[cfg(feature = "serde")]
+#[doc(hidden)]
+pub fn deserialize_present<'de, D, T>(deserializer: D) -> Result<Option<T>, D::Error>
+where
+    D: serde::Deserializer<'de>,
+    T: serde::Deserialize<'de>,
+{
+    T::deserialize(deserializer).map(Some)
+}
+
+/// Defines the patch struct of a `#[derive(Patch)]` type, deriving `Serialize` and `Deserialize`
+/// for it with the `serde` feature
+///
+/// Proc macros can't see which features this crate was built with, so the derive leaves this to
+/// a macro which can.
+#[cfg(feature = "serde")]
+#[doc(hidden)]
+#[macro_export]
+macro_rules! __patch_struct {
+    ($(#[$attr:meta])* $vis:vis struct $name:ident { $($field_vis:vis $field:ident: $ty:ty,)* }) => {
+        $(#[$attr])*
+        #[derive($crate::serde::Serialize, $crate::serde::Deserialize)]
+        #[serde(crate = "::update_sync::serde", default)]
+        $vis struct $name {
+            $(
+                // Fields which are present are always `Some`, so that a change to `None` is kept
+                #[serde(
+                    skip_serializing_if = "Option::is_none",
+                    deserialize_with = "::update_sync::patch::deserialize_present"
+                )]
+                $field_vis $field: $ty,
+            )*
+        }
+    };
+}
+
+#[cfg(not(feature = "serde"))]
+#[doc(hidden)]
+#[macro_export]
+macro_rules! __patch_struct {
+    ($($definition:tt)*) => {
+        $($definition)*
+    };
+}
+
+macro_rules! replace_impl_patch {
+    (impl<$($g:ident),*> for $c:ty where $($w:tt)*) => {
+        impl<$($g),*> Patch for $c where $($w)* {
+            type Patch = Self;
+
+            fn diff_patch(old: &Self, new: &Self) -> Option<Self> {
+                if old.change_eq(new) {
+                    None
+                } else {
+                    Some(new.clone())
+                }
+            }
+
+            fn apply_patch(&mut self, patch: Self) {
+                *self = patch;
+            }
+        }
+    };
+    ($($c:ty),*) => {
+        $(
+            replace_impl_patch!(impl<> for $c where);
+        )*
+    };
+}
+
+replace_impl_patch!(u8, u16, u32, u64, u128, usize, i8, i16, i32, i64, i128, isize);
+replace_impl_patch!(f32, f64, bool, char, (), String);
+replace_impl_patch!(
+    NonZeroU8,
+    NonZeroU16,
+    NonZeroU32,
+    NonZeroU64,
+    NonZeroU128,
+    NonZeroUsize
+);
+replace_impl_patch!(
+    NonZeroI8,
+    NonZeroI16,
+    NonZeroI32,
+    NonZeroI64,
+    NonZeroI128,
+    NonZeroIsize
+);
+replace_impl_patch!(Duration, SystemTime, PathBuf, OsString);
+replace_impl_patch!(
+    IpAddr,
+    Ipv4Addr,
+    Ipv6Addr,
+    SocketAddr,
+    SocketAddrV4,
+    SocketAddrV6
+);
+replace_impl_patch!(ReplicaId, Timestamp, Position);
+replace_impl_patch!(impl<T> for Option<T> where T: ChangeEq + Clone);
+replace_impl_patch!(impl<T> for Wrapping<T> where T: ChangeEq + Clone);
+replace_impl_patch!(impl<T> for Vec<T> where T: ChangeEq + Clone);
+
+impl<T: ?Sized> Patch for PhantomData<T> {
+    type Patch = Self;
+
+    fn diff_patch(_: &Self, _: &Self) -> Option<Self> {
+        None
+    }
+
+    fn apply_patch(&mut self, _: Self) {}
+}
+
+/// Implements `Patch` for types which need `last_base` to update, with patches holding the old
+/// and new values
+macro_rules! pair_impl_patch {
+    (impl<$($g:ident),*> for $c:ty where { $($w:tt)* } unchanged |$old:ident, $new:ident| $unchanged:expr) => {
+        impl<$($g),*> Patch for $c where $($w)* {
+            type Patch = (Self, Self);
+
+            fn diff_patch($old: &Self, $new: &Self) -> Option<(Self, Self)> {
+                if $unchanged {
+                    None
+                } else {
+                    Some(($old.clone(), $new.clone()))
+                }
+            }
+
+            fn apply_patch(&mut self, (last_base, set): (Self, Self)) {
+                *self = UpdateSync::update_sync(last_base, self.clone(), set);
+            }
+        }
+    };
+    (impl<$($g:ident),*> for $c:ty where $($w:tt)*) => {
+        pair_impl_patch!(impl<$($g),*> for $c where { $($w)* } unchanged |old, new| old.change_eq(new));
+    };
+}
+
+pair_impl_patch!(impl<T, E> for Result<T, E> where T: UpdateSync + ChangeEq + Clone, E: UpdateSync + ChangeEq + Clone);
+pair_impl_patch!(impl<T> for KeyedVec<T> where T: Keyed + UpdateSync + ChangeEq + Clone);
+pair_impl_patch!(impl<K, V> for OrderedList<K, V> where K: Ord + Clone, V: UpdateSync + ChangeEq + Clone);
+pair_impl_patch!(impl<K, V> for TombstoneMap<K, V> where K: Ord + Clone, V: UpdateSync + ChangeEq + Clone);
+pair_impl_patch!(
+    impl<T> for Versioned<T> where { T: UpdateSync + ChangeEq + Clone }
+    unchanged |old, new| old.version == new.version && old.value.change_eq(&new.value)
+);
+#[cfg(feature = "serde_json")]
+pair_impl_patch!(impl<> for serde_json::Value where);
+// Documents are compared by the values they hold, ignoring their formatting
+#[cfg(feature = "toml_edit")]
+pair_impl_patch!(
+    impl<> for toml_edit::DocumentMut where {}
+    unchanged |old, new| old.as_item().change_eq(new.as_item())
+);
+
+/// Implements `Patch` for types which update by joining, with patches holding the new value
+macro_rules! join_impl_patch {
+    (impl<$($g:ident),*> for $c:ty where $($w:tt)*) => {
+        impl<$($g),*> Patch for $c where $($w)* {
+            type Patch = Self;
+
+            fn diff_patch(old: &Self, new: &Self) -> Option<Self> {
+                if old == new {
+                    None
+                } else {
+                    Some(new.clone())
+                }
+            }
+
+            fn apply_patch(&mut self, patch: Self) {
+                Join::join(self, patch);
+            }
+        }
+    };
+}
+
+join_impl_patch!(impl<> for GCounter where);
+join_impl_patch!(impl<> for PnCounter where);
+join_impl_patch!(impl<T> for OrSet<T> where T: Ord + Clone);
+join_impl_patch!(impl<K, V> for LwwMap<K, V> where K: Ord + Clone, V: PartialEq + Clone);
+join_impl_patch!(impl<T> for MvRegister<T> where T: PartialEq + Clone);
+join_impl_patch!(impl<T> for Sequence<T> where T: PartialEq + Clone);
+
+impl Patch for VersionVector {
+    type Patch = Self;
+
+    fn diff_patch(old: &Self, new: &Self) -> Option<Self> {
+        if old == new {
+            None
+        } else {
+            Some(new.clone())
+        }
+    }
+
+    fn apply_patch(&mut self, patch: Self) {
+        self.join(&patch);
+    }
+}
+
+/// Patches to `Lww` values hold the new write, which is kept if it was written last
+impl<T: Stamped + Clone> Patch for Lww<T> {
+    type Patch = Self;
+
+    fn diff_patch(old: &Self, new: &Self) -> Option<Self> {
+        if old.change_eq(new) {
+            None
+        } else {
+            Some(new.clone())
+        }
+    }
+
+    fn apply_patch(&mut self, patch: Self) {
+        if patch.stamp() > self.stamp() {
+            *self = patch;
+        }
+    }
+}
+
+macro_rules! tuple_impl_patch {
+    ($($t:ident : $i:tt),+) => {
+        /// Patches to tuples hold a patch for each element which changed
+        impl<$($t: Patch),+> Patch for ($($t,)+) {
+            type Patch = ($(Option<$t::Patch>,)+);
+
+            fn diff_patch(old: &Self, new: &Self) -> Option<Self::Patch> {
+                let patch = ($($t::diff_patch(&old.$i, &new.$i),)+);
+                if true $( && patch.$i.is_none() )+ {
+                    None
+                } else {
+                    Some(patch)
+                }
+            }
+
+            fn apply_patch(&mut self, patch: Self::Patch) {
+                $(
+                    if let Some(patch) = patch.$i {
+                        self.$i.apply_patch(patch);
+                    }
+                )+
+            }
+        }
+    };
+}
+
+tuple_impl_patch!(T1: 0);
+tuple_impl_patch!(T1: 0, T2: 1);
+tuple_impl_patch!(T1: 0, T2: 1, T3: 2);
+tuple_impl_patch!(T1: 0, T2: 1, T3: 2, T4: 3);
+tuple_impl_patch!(T1: 0, T2: 1, T3: 2, T4: 3, T5: 4);
+tuple_impl_patch!(T1: 0, T2: 1, T3: 2, T4: 3, T5: 4, T6: 5);
+tuple_impl_patch!(T1: 0, T2: 1, T3: 2, T4: 3, T5: 4, T6: 5, T7: 6);
+tuple_impl_patch!(T1: 0, T2: 1, T3: 2, T4: 3, T5: 4, T6: 5, T7: 6, T8: 7);
+tuple_impl_patch!(T1: 0, T2: 1, T3: 2, T4: 3, T5: 4, T6: 5, T7: 6, T8: 7, T9: 8);
+tuple_impl_patch!(T1: 0, T2: 1, T3: 2, T4: 3, T5: 4, T6: 5, T7: 6, T8: 7, T9: 8, T10: 9);
+
+macro_rules! map_impl_patch {
+    ($t:tt, $($traits:tt)*) => {
+        /// Patches to maps hold the entries which were changed or added
+        impl<K, V> Patch for $t<K, V>
+        where
+            K: Clone + $($traits)*,
+            V: ChangeEq + Clone,
+        {
+            type Patch = Self;
+
+            fn diff_patch(old: &Self, new: &Self) -> Option<Self> {
+                let changed: Self = new
+                    .iter()
+                    .filter(|(key, value)| !old.get(key).is_some_and(|old| old.change_eq(value)))
+                    .map(|(key, value)| (key.clone(), value.clone()))
+                    .collect();
+                if changed.is_empty() {
+                    None
+                } else {
+                    Some(changed)
+                }
+            }
+
+            fn apply_patch(&mut self, patch: Self) {
+                self.extend(patch);
+            }
+        }
+    };
+}
+
+map_impl_patch!(BTreeMap, Ord);
+map_impl_patch!(HashMap, std::hash::Hash + Eq);
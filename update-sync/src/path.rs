@@ -0,0 +1,267 @@
+//! Locations inside a value
+//!
+//! A [`Path`] names a value inside another, as the sequence of fields, indices, map keys and enum
+//! variants leading to it. Paths are built from the [`Segment`]s a [`Visitor`](crate::visit::Visitor)
+//! is given, so every `#[derive(UpdateSync)]` type names its fields and variants in them.
+//!
+//! Paths display like Rust expressions, such as `users["bob"].name`, and can be rendered as and
+//! parsed from JSON Pointers (RFC 6901), such as `/users/bob/name`, for use in API responses and
+//! logs. A pointer doesn't say whether each of its tokens is a field, key or variant, so segments
+//! are compared by their pointer token, and parsed paths hold fields and indices.
+
+use std::{
+    borrow::Cow,
+    cmp::Ordering,
+    error::Error,
+    fmt,
+    hash::{Hash, Hasher},
+};
+
+use crate::visit::Segment;
+
+/// A single step of a [`Path`]
+#[derive(Clone, Debug)]
+pub enum PathSegment {
+    /// A named field of a struct
+    Field(Cow<'static, str>),
+    /// A position in a tuple, tuple struct, or list
+    Index(usize),
+    /// A key in a map, as its `Debug` representation
+    Key(String),
+    /// The variant of an enum
+    Variant(Cow<'static, str>),
+}
+
+impl PathSegment {
+    /// The segment as a JSON Pointer reference token, before escaping
+    ///
+    /// String keys are shown without their quotes.
+    pub fn token(&self) -> Cow<'_, str> {
+        match self {
+            PathSegment::Field(name) | PathSegment::Variant(name) => Cow::Borrowed(name),
+            PathSegment::Index(index) => Cow::Owned(index.to_string()),
+            PathSegment::Key(key) => unquote(key).map_or(Cow::Borrowed(key), Cow::Owned),
+        }
+    }
+
+    /// Orders numeric tokens numerically, before all others
+    fn sort_key(&self) -> (Option<usize>, Cow<'_, str>) {
+        let token = self.token();
+        (token.parse().ok().filter(|_| is_index(&token)), token)
+    }
+}
+
+impl From<Segment<'_>> for PathSegment {
+    fn from(segment: Segment<'_>) -> Self {
+        match segment {
+            Segment::Field(name) => PathSegment::Field(Cow::Borrowed(name)),
+            Segment::Index(index) => PathSegment::Index(index),
+            Segment::Key(key) => PathSegment::Key(format!("{:?}", key)),
+            Segment::Variant(name) => PathSegment::Variant(Cow::Borrowed(name)),
+        }
+    }
+}
+
+impl PartialEq for PathSegment {
+    fn eq(&self, other: &Self) -> bool {
+        self.token() == other.token()
+    }
+}
+
+impl Eq for PathSegment {}
+
+impl Hash for PathSegment {
+    fn hash<H: Hasher>(&self, state: &mut H) {
+        self.token().hash(state)
+    }
+}
+
+impl PartialOrd for PathSegment {
+    fn partial_cmp(&self, other: &Self) -> Option<Ordering> {
+        Some(self.cmp(other))
+    }
+}
+
+impl Ord for PathSegment {
+    fn cmp(&self, other: &Self) -> Ordering {
+        let (a, b) = (self.sort_key(), other.sort_key());
+        match (a.0, b.0) {
+            (Some(a), Some(b)) => a.cmp(&b),
+            (Some(_), None) => Ordering::Less,
+            (None, Some(_)) => Ordering::Greater,
+            (None, None) => a.1.cmp(&b.1),
+        }
+    }
+}
+
+/// The location of a value inside another, the empty path being the outer value itself
+#[derive(Clone, Debug, Default, PartialEq, Eq, PartialOrd, Ord, Hash)]
+pub struct Path {
+    segments: Vec<PathSegment>,
+}
+
+impl Path {
+    pub fn new() -> Self {
+        Path::default()
+    }
+
+    pub fn segments(&self) -> &[PathSegment] {
+        &self.segments
+    }
+
+    pub fn len(&self) -> usize {
+        self.segments.len()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.segments.is_empty()
+    }
+
+    pub fn push(&mut self, segment: impl Into<PathSegment>) {
+        self.segments.push(segment.into());
+    }
+
+    pub fn pop(&mut self) -> Option<PathSegment> {
+        self.segments.pop()
+    }
+
+    /// This path with `segment` added to the end
+    pub fn join(&self, segment: impl Into<PathSegment>) -> Path {
+        let mut path = self.clone();
+        path.push(segment);
+        path
+    }
+
+    /// Returns true if this path is `prefix`, or is inside it
+    pub fn starts_with(&self, prefix: &Path) -> bool {
+        self.segments.starts_with(&prefix.segments)
+    }
+
+    /// Renders the path as a JSON Pointer, such as `/users/bob/name`
+    pub fn to_pointer(&self) -> String {
+        let mut pointer = String::new();
+        for segment in &self.segments {
+            pointer.push('/');
+            pointer.push_str(&segment.token().replace('~', "~0").replace('/', "~1"));
+        }
+        pointer
+    }
+
+    /// Parses a JSON Pointer, reading tokens which are array indices as `Index`, and all others
+    /// as `Field`
+    pub fn from_pointer(pointer: &str) -> Result<Path, PointerError> {
+        if pointer.is_empty() {
+            return Ok(Path::new());
+        }
+        let tokens = pointer
+            .strip_prefix('/')
+            .ok_or(PointerError::MissingSlash)?;
+        let mut path = Path::new();
+        for token in tokens.split('/') {
+            let token = unescape_token(token)?;
+            match token.parse() {
+                Ok(index) if is_index(&token) => path.push(PathSegment::Index(index)),
+                _ => path.push(PathSegment::Field(Cow::Owned(token))),
+            }
+        }
+        Ok(path)
+    }
+}
+
+impl<S: Into<PathSegment>> std::iter::FromIterator<S> for Path {
+    fn from_iter<I: IntoIterator<Item = S>>(iter: I) -> Self {
+        Path {
+            segments: iter.into_iter().map(Into::into).collect(),
+        }
+    }
+}
+
+/// Displays the path like a Rust expression, such as `users["bob"].name`
+impl fmt::Display for Path {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        for (i, segment) in self.segments.iter().enumerate() {
+            match segment {
+                PathSegment::Key(key) => write!(f, "[{}]", key)?,
+                segment => {
+                    if i > 0 {
+                        f.write_str(".")?;
+                    }
+                    f.write_str(&segment.token())?;
+                }
+            }
+        }
+        Ok(())
+    }
+}
+
+/// Why a JSON Pointer couldn't be parsed
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub enum PointerError {
+    /// A non-empty pointer must start with `/`
+    MissingSlash,
+    /// `~` must be followed by `0` or `1`
+    InvalidEscape,
+}
+
+impl fmt::Display for PointerError {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self {
+            PointerError::MissingSlash => f.write_str("JSON pointer must start with `/`"),
+            PointerError::InvalidEscape => {
+                f.write_str("`~` in a JSON pointer must be followed by `0` or `1`")
+            }
+        }
+    }
+}
+
+impl Error for PointerError {}
+
+/// Returns true if `token` is an array index, which has no leading zeros
+pub(crate) fn is_index(token: &str) -> bool {
+    !token.is_empty()
+        && token.bytes().all(|b| b.is_ascii_digit())
+        && (token == "0" || !token.starts_with('0'))
+}
+
+fn unescape_token(token: &str) -> Result<String, PointerError> {
+    let mut unescaped = String::with_capacity(token.len());
+    let mut chars = token.chars();
+    while let Some(c) = chars.next() {
+        match c {
+            '~' => match chars.next() {
+                Some('0') => unescaped.push('~'),
+                Some('1') => unescaped.push('/'),
+                _ => return Err(PointerError::InvalidEscape),
+            },
+            c => unescaped.push(c),
+        }
+    }
+    Ok(unescaped)
+}
+
+/// Reads the string a `Debug` formatted string literal holds, or `None` if `debug` isn't one
+fn unquote(debug: &str) -> Option<String> {
+    let inner = debug.strip_prefix('"')?.strip_suffix('"')?;
+    let mut unquoted = String::with_capacity(inner.len());
+    let mut chars = inner.chars();
+    while let Some(c) = chars.next() {
+        if c != '\\' {
+            unquoted.push(c);
+            continue;
+        }
+        match chars.next()? {
+            'n' => unquoted.push('\n'),
+            'r' => unquoted.push('\r'),
+            't' => unquoted.push('\t'),
+            '0' => unquoted.push('\0'),
+            'u' => {
+                let rest = chars.as_str().strip_prefix('{')?;
+                let end = rest.find('}')?;
+                unquoted.push(char::from_u32(u32::from_str_radix(&rest[..end], 16).ok()?)?);
+                chars = rest[end + 1..].chars();
+            }
+            c => unquoted.push(c),
+        }
+    }
+    Some(unquoted)
+}
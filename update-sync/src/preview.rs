@@ -0,0 +1,195 @@
+//! Previewing an update before committing it
+//!
+//! [`preview`] performs an update and summarises every value which either side changed: where it
+//! is, what it was in each input, and whose change was kept. This works for any type implementing
+//! `UpdateSyncVisit`, including every `#[derive(UpdateSync)]` type. A [`Summary`] can be shown
+//! to users as text with `Display`, or sent to a client with [`Summary::to_json`].
+
+use std::fmt::{self, Debug, Write};
+
+use crate::{
+    path::Path,
+    visit::{Leaf, Resolution, Segment, Visitor},
+    UpdateSyncVisit,
+};
+
+/// The result of an update, along with what it changed
+#[derive(Clone, Debug, PartialEq)]
+pub struct Preview<T> {
+    pub merged: T,
+    pub summary: Summary,
+}
+
+/// Every value changed by `new_base` or `set`
+#[derive(Clone, Debug, Default, PartialEq, Eq)]
+pub struct Summary {
+    /// In the order they were updated
+    pub changes: Vec<FieldChange>,
+}
+
+/// A single value changed by `new_base` or `set`
+///
+/// Values are shown with their `Debug` representation, or `None` where the value is absent, such
+/// as a map entry which was removed.
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct FieldChange {
+    /// Where the value is, such as `users["bob"].name`
+    pub location: Path,
+    pub resolution: Resolution,
+    pub last_base: Option<String>,
+    pub new_base: Option<String>,
+    pub set: Option<String>,
+}
+
+impl FieldChange {
+    /// Returns true if committing the update changes this value from what is in `new_base`
+    pub fn will_change(&self) -> bool {
+        self.resolution.changed_new_base()
+    }
+
+    /// Whose change was kept, one of `"new_base"`, `"set"`, `"both"` or `"merged"`
+    pub fn winner(&self) -> &'static str {
+        match self.resolution {
+            Resolution::Unchanged | Resolution::NewBase => "new_base",
+            Resolution::Set | Resolution::Conflict => "set",
+            Resolution::Agreed => "both",
+            Resolution::Merged => "merged",
+        }
+    }
+}
+
+impl Summary {
+    /// The changes which committing the update would make to `new_base`
+    pub fn changes_to_new_base(&self) -> impl Iterator<Item = &FieldChange> {
+        self.changes.iter().filter(|change| change.will_change())
+    }
+
+    /// Renders the summary as a JSON object with a `changes` array, locating each change with a
+    /// JSON Pointer
+    pub fn to_json(&self) -> String {
+        let mut json = String::from("{\"changes\":[");
+        for (i, change) in self.changes.iter().enumerate() {
+            if i > 0 {
+                json.push(',');
+            }
+            json.push_str("{\"location\":");
+            push_json_string(&mut json, Some(&change.location.to_pointer()));
+            json.push_str(",\"resolution\":");
+            push_json_string(&mut json, Some(&format!("{:?}", change.resolution)));
+            json.push_str(",\"winner\":");
+            push_json_string(&mut json, Some(change.winner()));
+            json.push_str(",\"last_base\":");
+            push_json_string(&mut json, change.last_base.as_deref());
+            json.push_str(",\"new_base\":");
+            push_json_string(&mut json, change.new_base.as_deref());
+            json.push_str(",\"set\":");
+            push_json_string(&mut json, change.set.as_deref());
+            json.push('}');
+        }
+        json.push_str("]}");
+        json
+    }
+}
+
+/// Pushes a JSON string, or `null`
+fn push_json_string(json: &mut String, value: Option<&str>) {
+    let value = match value {
+        Some(value) => value,
+        None => return json.push_str("null"),
+    };
+    json.push('"');
+    for c in value.chars() {
+        match c {
+            '"' => json.push_str("\\\""),
+            '\\' => json.push_str("\\\\"),
+            '\n' => json.push_str("\\n"),
+            '\r' => json.push_str("\\r"),
+            '\t' => json.push_str("\\t"),
+            c if (c as u32) < 0x20 => {
+                let _ = write!(json, "\\u{:04x}", c as u32);
+            }
+            c => json.push(c),
+        }
+    }
+    json.push('"');
+}
+
+/// One line per change, such as `users["bob"].name: "Bob" -> "Robert"`
+impl fmt::Display for Summary {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        for change in &self.changes {
+            writeln!(f, "{}", change)?;
+        }
+        Ok(())
+    }
+}
+
+impl fmt::Display for FieldChange {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        let show = |value: &Option<String>| value.clone().unwrap_or_else(|| "(none)".into());
+        let location = if self.location.is_empty() {
+            "(value)".to_owned()
+        } else {
+            self.location.to_string()
+        };
+        let (last_base, new_base, set) =
+            (show(&self.last_base), show(&self.new_base), show(&self.set));
+        match self.resolution {
+            Resolution::Unchanged => write!(f, "{}: {} (unchanged)", location, new_base),
+            Resolution::NewBase => write!(
+                f,
+                "{}: {} (already changed from {})",
+                location, new_base, last_base
+            ),
+            Resolution::Set => write!(f, "{}: {} -> {}", location, new_base, set),
+            Resolution::Agreed => write!(f, "{}: {} (changed by both)", location, new_base),
+            Resolution::Conflict => write!(
+                f,
+                "{}: {} -> {} (overwriting its change from {})",
+                location, new_base, set, last_base
+            ),
+            Resolution::Merged => write!(f, "{}: {} -> merged with {}", location, new_base, set),
+        }
+    }
+}
+
+/// Collects a change for every leaf either side changed
+#[derive(Default)]
+struct SummaryVisitor {
+    location: Path,
+    summary: Summary,
+}
+
+impl Visitor for SummaryVisitor {
+    fn enter(&mut self, segment: Segment<'_>) {
+        self.location.push(segment);
+    }
+
+    fn leave(&mut self) {
+        self.location.pop();
+    }
+
+    fn leaf(&mut self, leaf: Leaf<'_>) {
+        if leaf.resolution == Resolution::Unchanged {
+            return;
+        }
+        let show = |value: Option<&dyn Debug>| value.map(|value| format!("{:?}", value));
+        self.summary.changes.push(FieldChange {
+            location: self.location.clone(),
+            resolution: leaf.resolution,
+            last_base: show(leaf.last_base),
+            new_base: show(leaf.new_base),
+            set: show(leaf.set),
+        });
+    }
+}
+
+/// Updates `new_base` with `set` as `update_sync` would, summarising every change made by either
+pub fn preview<T: UpdateSyncVisit>(last_base: T, new_base: T, set: T) -> Preview<T> {
+    let mut visitor = SummaryVisitor::default();
+    let merged = T::update_sync_visit(last_base, new_base, set, &mut visitor);
+    Preview {
+        merged,
+        summary: visitor.summary,
+    }
+}
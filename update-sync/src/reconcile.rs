@@ -0,0 +1,352 @@
+//! Syncing large maps by reconciling ranges of keys
+//!
+//! Updating a large `BTreeMap` needs the entries which differ between the client's `set` and the
+//! server's `new_base`, but sending either whole map to find them is expensive. Instead, each side
+//! runs a [`Reconciler`] over its map, and they exchange [`RangeMessage`]s, which describe ranges
+//! of keys by a [`Fingerprint`] of their entries. Ranges with matching fingerprints are skipped,
+//! while ranges which differ are split into smaller ranges, until they are small enough to list
+//! their keys along with the hash of each entry. This finds a few differing keys among hundreds of
+//! thousands of entries in a handful of round trips.
+//!
+//! Once both sides know which keys differ, the client sends [`changed_entries`], its entries for
+//! those keys in `last_base` and `set`, and the server updates just those entries with
+//! [`update_sync_entries`], with the same semantics as updating the whole map. The merged entries
+//! it replies with make the client's map the same as the server's, with [`apply_entries`].
+//!
+//! Messages are plain values, so they can be sent over any [`Transport`]. [`InProcess`] runs the
+//! other side in the same process, for tests.
+
+use std::{
+    collections::{BTreeMap, BTreeSet},
+    fmt::Debug,
+    ops::Bound,
+};
+
+use crate::{
+    hash::{SyncHash, SyncHasher},
+    ChangeEq, UpdateSync,
+};
+
+/// A summary of the entries in a range of keys, which is the same for the same entries
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq, Hash)]
+pub struct Fingerprint {
+    /// The wrapping sum of the hash of every entry
+    pub hash: u64,
+    pub len: usize,
+}
+
+impl Fingerprint {
+    fn add(&mut self, entry_hash: u64) {
+        self.hash = self.hash.wrapping_add(entry_hash);
+        self.len += 1;
+    }
+}
+
+/// What one side says about a range of keys
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub enum Range<K> {
+    /// The range is known to be the same on both sides
+    Skip,
+    /// The fingerprint of the sender's entries in the range
+    Fingerprint(Fingerprint),
+    /// Every key in the range, along with the hash of its entry
+    ///
+    /// The receiver replies with its own entries if `reply` is set.
+    Entries { entries: Vec<(K, u64)>, reply: bool },
+}
+
+/// Consecutive ranges covering every key, each given by the key it ends before, or `None` for the
+/// last range
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct RangeMessage<K> {
+    pub ranges: Vec<(Option<K>, Range<K>)>,
+}
+
+/// Carries messages to the other side, returning its reply, or `None` once it has nothing more to
+/// say
+pub trait Transport<K> {
+    fn send(&mut self, message: RangeMessage<K>) -> Option<RangeMessage<K>>;
+}
+
+/// One side of reconciling a map
+#[derive(Clone, Debug)]
+pub struct Reconciler<'a, K, V> {
+    map: &'a BTreeMap<K, V>,
+    threshold: usize,
+    branching: usize,
+    differing: BTreeSet<K>,
+}
+
+impl<'a, K, V> Reconciler<'a, K, V>
+where
+    K: Ord + Clone + SyncHash,
+    V: SyncHash,
+{
+    /// Reconciles `map`, listing ranges of up to 16 entries, and splitting larger ranges in 16
+    pub fn new(map: &'a BTreeMap<K, V>) -> Self {
+        Reconciler {
+            map,
+            threshold: 16,
+            branching: 16,
+            differing: BTreeSet::new(),
+        }
+    }
+
+    /// Lists the keys of ranges with up to `threshold` entries, rather than splitting them
+    pub fn with_threshold(mut self, threshold: usize) -> Self {
+        self.threshold = threshold.max(1);
+        self
+    }
+
+    /// Splits each differing range into `branching` smaller ranges
+    pub fn with_branching(mut self, branching: usize) -> Self {
+        self.branching = branching.max(2);
+        self
+    }
+
+    /// The keys found to differ so far
+    pub fn differing(&self) -> &BTreeSet<K> {
+        &self.differing
+    }
+
+    pub fn into_differing(self) -> BTreeSet<K> {
+        self.differing
+    }
+
+    /// The first message, describing the whole map
+    pub fn initiate(&self) -> RangeMessage<K> {
+        RangeMessage {
+            ranges: self.describe(None, None),
+        }
+    }
+
+    /// Replies to a message from the other side, or returns `None` if every range is resolved
+    pub fn respond(&mut self, message: &RangeMessage<K>) -> Option<RangeMessage<K>> {
+        let mut ranges = Vec::new();
+        let mut lower = None;
+        for (upper, range) in &message.ranges {
+            match range {
+                Range::Skip => ranges.push((upper.clone(), Range::Skip)),
+                Range::Fingerprint(fingerprint) => {
+                    if self.fingerprint(lower, upper.as_ref()) == *fingerprint {
+                        ranges.push((upper.clone(), Range::Skip));
+                    } else {
+                        ranges.extend(self.describe(lower, upper.as_ref()));
+                    }
+                }
+                Range::Entries { entries, reply } => {
+                    let own = self.entries(lower, upper.as_ref());
+                    self.record_differences(entries, &own);
+                    let range = if *reply {
+                        Range::Entries {
+                            entries: own,
+                            reply: false,
+                        }
+                    } else {
+                        Range::Skip
+                    };
+                    ranges.push((upper.clone(), range));
+                }
+            }
+            lower = upper.as_ref();
+        }
+        if ranges.iter().all(|(_, range)| *range == Range::Skip) {
+            None
+        } else {
+            Some(RangeMessage { ranges })
+        }
+    }
+
+    /// Exchanges messages over `transport` until every range is resolved, returning the number
+    /// of round trips taken
+    pub fn run(&mut self, transport: &mut impl Transport<K>) -> usize {
+        let mut message = self.initiate();
+        let mut round_trips = 0;
+        loop {
+            round_trips += 1;
+            let reply = match transport.send(message) {
+                Some(reply) => reply,
+                None => return round_trips,
+            };
+            message = match self.respond(&reply) {
+                Some(message) => message,
+                None => return round_trips,
+            };
+        }
+    }
+
+    fn bounds<'k>(lower: Option<&'k K>, upper: Option<&'k K>) -> (Bound<&'k K>, Bound<&'k K>) {
+        (
+            lower.map_or(Bound::Unbounded, Bound::Included),
+            upper.map_or(Bound::Unbounded, Bound::Excluded),
+        )
+    }
+
+    fn fingerprint(&self, lower: Option<&K>, upper: Option<&K>) -> Fingerprint {
+        let mut fingerprint = Fingerprint::default();
+        for (key, value) in self.map.range(Self::bounds(lower, upper)) {
+            fingerprint.add(entry_hash(key, value));
+        }
+        fingerprint
+    }
+
+    fn entries(&self, lower: Option<&K>, upper: Option<&K>) -> Vec<(K, u64)> {
+        self.map
+            .range(Self::bounds(lower, upper))
+            .map(|(key, value)| (key.clone(), entry_hash(key, value)))
+            .collect()
+    }
+
+    /// Describes a range which differs, by its entries if it is small enough, and otherwise by the
+    /// fingerprints of smaller ranges
+    fn describe(&self, lower: Option<&K>, upper: Option<&K>) -> Vec<(Option<K>, Range<K>)> {
+        let keys: Vec<&K> = self
+            .map
+            .range(Self::bounds(lower, upper))
+            .map(|(key, _)| key)
+            .collect();
+        if keys.len() <= self.threshold {
+            return vec![(
+                upper.cloned(),
+                Range::Entries {
+                    entries: self.entries(lower, upper),
+                    reply: true,
+                },
+            )];
+        }
+        let mut ranges = Vec::with_capacity(self.branching);
+        let mut sub_lower = lower;
+        for i in 1..=self.branching {
+            let sub_upper = if i == self.branching {
+                upper
+            } else {
+                Some(keys[keys.len() * i / self.branching])
+            };
+            ranges.push((
+                sub_upper.cloned(),
+                Range::Fingerprint(self.fingerprint(sub_lower, sub_upper)),
+            ));
+            sub_lower = sub_upper;
+        }
+        ranges
+    }
+
+    fn record_differences(&mut self, theirs: &[(K, u64)], own: &[(K, u64)]) {
+        let theirs: BTreeMap<_, _> = theirs.iter().map(|(key, hash)| (key, hash)).collect();
+        let own: BTreeMap<_, _> = own.iter().map(|(key, hash)| (key, hash)).collect();
+        for (key, hash) in &theirs {
+            if own.get(key) != Some(hash) {
+                self.differing.insert((*key).clone());
+            }
+        }
+        for key in own.keys() {
+            if !theirs.contains_key(key) {
+                self.differing.insert((*key).clone());
+            }
+        }
+    }
+}
+
+fn entry_hash<K: SyncHash, V: SyncHash>(key: &K, value: &V) -> u64 {
+    let mut hasher = SyncHasher::new();
+    hasher.write_u64(key.sync_hash());
+    hasher.write_u64(value.sync_hash());
+    hasher.finish()
+}
+
+/// A transport to a reconciler in the same process
+#[derive(Debug)]
+pub struct InProcess<'a, K, V> {
+    pub remote: Reconciler<'a, K, V>,
+}
+
+impl<'a, K, V> InProcess<'a, K, V> {
+    pub fn new(remote: Reconciler<'a, K, V>) -> Self {
+        InProcess { remote }
+    }
+}
+
+impl<K, V> Transport<K> for InProcess<'_, K, V>
+where
+    K: Ord + Clone + SyncHash,
+    V: SyncHash,
+{
+    fn send(&mut self, message: RangeMessage<K>) -> Option<RangeMessage<K>> {
+        self.remote.respond(&message)
+    }
+}
+
+/// An entry of the client's maps, for a key which differs from the server's
+#[derive(Clone, Debug, PartialEq)]
+pub struct EntryChange<K, V> {
+    pub key: K,
+    pub last_base: Option<V>,
+    pub set: Option<V>,
+}
+
+/// The merged value of each key the server updated, or `None` where the entry was removed
+pub type MergedEntries<K, V> = Vec<(K, Option<V>)>;
+
+/// The client's entries for `keys`
+pub fn changed_entries<K: Ord + Clone, V: Clone>(
+    last_base: &BTreeMap<K, V>,
+    set: &BTreeMap<K, V>,
+    keys: &BTreeSet<K>,
+) -> Vec<EntryChange<K, V>> {
+    keys.iter()
+        .map(|key| EntryChange {
+            key: key.clone(),
+            last_base: last_base.get(key).cloned(),
+            set: set.get(key).cloned(),
+        })
+        .collect()
+}
+
+/// Updates the entries of `new_base` for the keys in `changes`, as updating the whole map would,
+/// and returns the updated map along with the merged entries for those keys
+///
+/// Entries which are the same in `new_base` and `set` update to their value in `new_base`
+/// whatever `last_base` holds, so only the entries which differ need updating.
+pub fn update_sync_entries<K, V>(
+    mut new_base: BTreeMap<K, V>,
+    changes: Vec<EntryChange<K, V>>,
+) -> (BTreeMap<K, V>, MergedEntries<K, V>)
+where
+    K: Ord + Clone + Debug,
+    V: UpdateSync + ChangeEq + Clone + Debug,
+{
+    let (mut last_base, mut differing, mut set) =
+        (BTreeMap::new(), BTreeMap::new(), BTreeMap::new());
+    let keys: Vec<K> = changes.iter().map(|change| change.key.clone()).collect();
+    for change in changes {
+        if let Some(value) = new_base.remove(&change.key) {
+            differing.insert(change.key.clone(), value);
+        }
+        if let Some(value) = change.last_base {
+            last_base.insert(change.key.clone(), value);
+        }
+        if let Some(value) = change.set {
+            set.insert(change.key, value);
+        }
+    }
+    let merged = BTreeMap::update_sync(last_base, differing, set);
+    let reply = keys
+        .into_iter()
+        .map(|key| {
+            let value = merged.get(&key).cloned();
+            (key, value)
+        })
+        .collect();
+    new_base.extend(merged);
+    (new_base, reply)
+}
+
+/// Applies the merged entries the server replied with to the client's map
+pub fn apply_entries<K: Ord, V>(map: &mut BTreeMap<K, V>, entries: MergedEntries<K, V>) {
+    for (key, value) in entries {
+        match value {
+            Some(value) => map.insert(key, value),
+            None => map.remove(&key),
+        };
+    }
+}
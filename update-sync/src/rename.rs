@@ -0,0 +1,139 @@
+//! Rename aware map updates
+//!
+//! The `BTreeMap` implementation of `UpdateSync` sees a renamed key as one entry removed and
+//! another added, so a change the other side made to the value under the old key ends up under
+//! the old key, while the renamed entry carries the value from before the change.
+//!
+//! The functions here first look for keys one side removed whose value appears under a key that
+//! side added, either by equality or by an identity function such as one returning an id stored
+//! in the value. The old key is then moved to the new key in the other inputs, so the entry is
+//! updated as a whole and changes made under the old key are kept under the new one.
+//!
+//! They can be used for fields of derived structs with `#[update_sync(with = "...")]`.
+
+use std::{
+    collections::{BTreeMap, BTreeSet},
+    fmt::Debug,
+};
+
+use crate::{visit::Visitor, ChangeEq, UpdateSync, UpdateSyncVisit};
+
+/// Updates a map, treating an entry removed by one side and added under another key with a value
+/// that is `ChangeEq` as a rename
+pub fn update_sync_renamed<K, V>(
+    last_base: BTreeMap<K, V>,
+    new_base: BTreeMap<K, V>,
+    set: BTreeMap<K, V>,
+) -> BTreeMap<K, V>
+where
+    K: Ord + Clone,
+    V: UpdateSync + ChangeEq,
+{
+    let (last_base, new_base, set) = follow_renames(last_base, new_base, set, V::change_eq);
+    UpdateSync::update_sync(last_base, new_base, set)
+}
+
+/// Updates a map, treating an entry removed by one side and added under another key with a value
+/// of the same identity as a rename
+///
+/// Unlike `update_sync_renamed`, this finds renames of values which were also changed.
+pub fn update_sync_renamed_by<K, V, I>(
+    last_base: BTreeMap<K, V>,
+    new_base: BTreeMap<K, V>,
+    set: BTreeMap<K, V>,
+    identity: impl Fn(&V) -> I,
+) -> BTreeMap<K, V>
+where
+    K: Ord + Clone,
+    V: UpdateSync + ChangeEq,
+    I: PartialEq,
+{
+    let (last_base, new_base, set) =
+        follow_renames(last_base, new_base, set, |a, b| identity(a) == identity(b));
+    UpdateSync::update_sync(last_base, new_base, set)
+}
+
+/// Updates a map, treating an entry removed by one side and added under another key with a value
+/// `same` as the removed one as a rename, and reports the update of the renamed map to `visitor`
+///
+/// When both sides rename the same key to different keys, `set`'s new key is kept.
+pub fn update_sync_renamed_visit<K, V>(
+    last_base: BTreeMap<K, V>,
+    new_base: BTreeMap<K, V>,
+    set: BTreeMap<K, V>,
+    same: impl Fn(&V, &V) -> bool,
+    visitor: &mut dyn Visitor,
+) -> BTreeMap<K, V>
+where
+    K: Ord + Clone + Debug,
+    V: UpdateSync + ChangeEq + Debug,
+{
+    let (last_base, new_base, set) = follow_renames(last_base, new_base, set, same);
+    UpdateSyncVisit::update_sync_visit(last_base, new_base, set, visitor)
+}
+
+/// Moves the entries each side renamed to their new key in the other inputs
+fn follow_renames<K: Ord + Clone, V>(
+    mut last_base: BTreeMap<K, V>,
+    mut new_base: BTreeMap<K, V>,
+    mut set: BTreeMap<K, V>,
+    same: impl Fn(&V, &V) -> bool,
+) -> (BTreeMap<K, V>, BTreeMap<K, V>, BTreeMap<K, V>) {
+    let new_base_renames = renames(&last_base, &new_base, &same);
+    let set_renames = renames(&last_base, &set, &same);
+
+    for (old, new) in &set_renames {
+        let moved = match new_base_renames.get(old) {
+            Some(new_base_new) => new_base.remove(new_base_new),
+            None => new_base.remove(old),
+        };
+        if let Some(moved) = moved {
+            new_base.entry(new.clone()).or_insert(moved);
+        }
+        rename(&mut last_base, old, new);
+    }
+    for (old, new) in &new_base_renames {
+        if set_renames.contains_key(old) {
+            continue;
+        }
+        if let Some(moved) = set.remove(old) {
+            set.entry(new.clone()).or_insert(moved);
+        }
+        rename(&mut last_base, old, new);
+    }
+
+    (last_base, new_base, set)
+}
+
+/// Moves the value at `old` to `new`
+fn rename<K: Ord + Clone, V>(map: &mut BTreeMap<K, V>, old: &K, new: &K) {
+    if let Some(value) = map.remove(old) {
+        map.insert(new.clone(), value);
+    }
+}
+
+/// Finds the keys of `last_base` which `other` moved to another key, mapped to their new key
+///
+/// Each removed key is matched with the first added key, in key order, holding the same value.
+fn renames<K: Ord + Clone, V>(
+    last_base: &BTreeMap<K, V>,
+    other: &BTreeMap<K, V>,
+    same: &impl Fn(&V, &V) -> bool,
+) -> BTreeMap<K, K> {
+    let added: Vec<(&K, &V)> = other
+        .iter()
+        .filter(|(key, _)| !last_base.contains_key(key))
+        .collect();
+    let mut taken = BTreeSet::new();
+    let mut renames = BTreeMap::new();
+    for (old, value) in last_base.iter().filter(|(key, _)| !other.contains_key(key)) {
+        let new = added
+            .iter()
+            .find(|(new, added)| !taken.contains(new) && same(value, added));
+        if let Some((new, _)) = new {
+            taken.insert(*new);
+            renames.insert(old.clone(), (*new).clone());
+        }
+    }
+    renames
+}
@@ -0,0 +1,339 @@
+//! Maps which remember their deletions
+//!
+//! A client whose `last_base` is older than a deletion, or which leaves out its own `last_base`
+//! and relies on a shared one, can send back an entry the server has since removed, and the map
+//! update can't tell that from the client adding it. A [`TombstoneMap`] keeps a tombstone in place
+//! of each removed entry, recording the revision it was removed at, and every entry records the
+//! revision it was last written at. An entry from `set` which was written before a deletion in
+//! `new_base` is stale, so the deletion is kept.
+//!
+//! Revisions are counted by the map in `new_base`, which should be the server's copy: every update
+//! taking a change from `set` produces a new revision. Tombstones older than a garbage collection
+//! horizon can be dropped with [`TombstoneMap::collect_garbage`], or automatically by creating
+//! the map [`with_retention`](TombstoneMap::with_retention). Entries from a `set` written before
+//! the horizon can no longer be checked against the tombstones which were dropped, so they are
+//! only kept if `new_base` still has them.
+
+use std::{
+    collections::{BTreeMap, BTreeSet},
+    fmt::Debug,
+};
+
+use crate::{
+    visit::{Leaf, Resolution, Segment, Visitor},
+    ChangeEq, UpdateSync, UpdateSyncVisit,
+};
+
+#[derive(Clone, Debug, PartialEq)]
+struct Entry<V> {
+    /// The value, or `None` for a tombstone
+    value: Option<V>,
+    /// The revision the value was written, or removed, at
+    revision: u64,
+}
+
+/// A map which keeps tombstones for removed entries, so that stale copies aren't resurrected
+#[derive(Clone, Debug, PartialEq)]
+pub struct TombstoneMap<K: Ord, V> {
+    entries: BTreeMap<K, Entry<V>>,
+    revision: u64,
+    horizon: u64,
+    retention: Option<u64>,
+}
+
+impl<K: Ord, V> Default for TombstoneMap<K, V> {
+    fn default() -> Self {
+        TombstoneMap {
+            entries: BTreeMap::new(),
+            revision: 0,
+            horizon: 0,
+            retention: None,
+        }
+    }
+}
+
+impl<K: Ord, V> TombstoneMap<K, V> {
+    /// A map which keeps every tombstone until `collect_garbage` is called
+    pub fn new() -> Self {
+        TombstoneMap::default()
+    }
+
+    /// A map which drops tombstones once they are more than `revisions` revisions old
+    pub fn with_retention(revisions: u64) -> Self {
+        TombstoneMap {
+            retention: Some(revisions),
+            ..TombstoneMap::default()
+        }
+    }
+
+    /// The revision of the latest update
+    pub fn revision(&self) -> u64 {
+        self.revision
+    }
+
+    /// Tombstones removed at or before this revision have been dropped
+    pub fn horizon(&self) -> u64 {
+        self.horizon
+    }
+
+    pub fn get(&self, key: &K) -> Option<&V> {
+        self.entries.get(key)?.value.as_ref()
+    }
+
+    pub fn get_mut(&mut self, key: &K) -> Option<&mut V> {
+        self.entries.get_mut(key)?.value.as_mut()
+    }
+
+    pub fn contains_key(&self, key: &K) -> bool {
+        self.get(key).is_some()
+    }
+
+    /// Inserts a value, returning the value it replaced
+    pub fn insert(&mut self, key: K, value: V) -> Option<V> {
+        let entry = Entry {
+            value: Some(value),
+            revision: self.revision,
+        };
+        self.entries.insert(key, entry)?.value
+    }
+
+    /// Removes a value, leaving a tombstone in its place
+    pub fn remove(&mut self, key: &K) -> Option<V> {
+        let entry = self.entries.get_mut(key)?;
+        let value = entry.value.take()?;
+        entry.revision = self.revision;
+        Some(value)
+    }
+
+    /// The entries which haven't been removed
+    pub fn iter(&self) -> impl Iterator<Item = (&K, &V)> {
+        self.entries
+            .iter()
+            .filter_map(|(key, entry)| Some((key, entry.value.as_ref()?)))
+    }
+
+    pub fn len(&self) -> usize {
+        self.iter().count()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.len() == 0
+    }
+
+    /// The keys of removed entries, along with the revision each was removed at
+    pub fn tombstones(&self) -> impl Iterator<Item = (&K, u64)> {
+        self.entries
+            .iter()
+            .filter(|(_, entry)| entry.value.is_none())
+            .map(|(key, entry)| (key, entry.revision))
+    }
+
+    /// Drops every tombstone removed at or before `horizon`
+    pub fn collect_garbage(&mut self, horizon: u64) {
+        self.entries
+            .retain(|_, entry| entry.value.is_some() || entry.revision > horizon);
+        self.horizon = self.horizon.max(horizon);
+    }
+}
+
+impl<K: Ord, V: ChangeEq> ChangeEq for TombstoneMap<K, V> {
+    fn change_eq(&self, other: &Self) -> bool {
+        self.revision == other.revision
+            && self.entries.len() == other.entries.len()
+            && self
+                .entries
+                .iter()
+                .zip(&other.entries)
+                .all(|((a_key, a), (b_key, b))| a_key == b_key && a.value.change_eq(&b.value))
+    }
+}
+
+/// Entries from `set` are updated against those in `new_base` as by the `BTreeMap` update, except
+/// that `new_base`'s tombstones are kept over entries written before them, and entries which
+/// `set` removed are removed
+impl<K, V> UpdateSync for TombstoneMap<K, V>
+where
+    K: Ord + Clone,
+    V: UpdateSync + ChangeEq,
+{
+    fn update_sync(last_base: Self, new_base: Self, set: Self) -> Self {
+        TombstoneMap::update_entries(last_base, new_base, set, &mut Plain)
+    }
+}
+
+impl<K, V> UpdateSyncVisit for TombstoneMap<K, V>
+where
+    K: Ord + Clone + Debug,
+    V: UpdateSyncVisit + ChangeEq + Debug,
+{
+    fn update_sync_visit(
+        last_base: Self,
+        new_base: Self,
+        set: Self,
+        mut visitor: &mut dyn Visitor,
+    ) -> Self {
+        TombstoneMap::update_entries(last_base, new_base, set, &mut visitor)
+    }
+}
+
+impl<K: Ord + Clone, V> TombstoneMap<K, V> {
+    /// Updates the map, updating each entry with `updater`
+    fn update_entries(
+        mut last_base: Self,
+        mut new_base: Self,
+        mut set: Self,
+        updater: &mut impl UpdateEntry<K, V>,
+    ) -> Self {
+        let revision = new_base.revision + 1;
+        let mut changed = false;
+        let keys: BTreeSet<K> = new_base
+            .entries
+            .keys()
+            .chain(set.entries.keys())
+            .cloned()
+            .collect();
+        let mut entries = BTreeMap::new();
+        for key in keys {
+            let l = last_base.entries.remove(&key).and_then(|entry| entry.value);
+            let entry = match (new_base.entries.remove(&key), set.entries.remove(&key)) {
+                (Some(n), None) => n,
+                (n, Some(s)) => {
+                    let stale = match &n {
+                        // The entry was removed after `set`'s copy was written
+                        Some(n) => n.value.is_none() && s.revision < n.revision,
+                        // Its tombstone may have been dropped
+                        None => s.revision < new_base.horizon,
+                    };
+                    let entry = if stale {
+                        updater.stale(&key, l.as_ref(), s.value.as_ref());
+                        n
+                    } else {
+                        let n_revision = n.as_ref().map(|n| n.revision);
+                        let (value, set_changed) =
+                            updater.update(&key, l, n.and_then(|n| n.value), s.value);
+                        changed |= set_changed;
+                        Some(Entry {
+                            value,
+                            revision: match n_revision {
+                                Some(n_revision) if !set_changed => n_revision,
+                                _ => revision,
+                            },
+                        })
+                    };
+                    match entry {
+                        Some(entry) => entry,
+                        None => continue,
+                    }
+                }
+                (None, None) => unreachable!("every key is in `new_base` or `set`"),
+            };
+            entries.insert(key, entry);
+        }
+
+        let mut merged = TombstoneMap {
+            entries,
+            revision: if changed { revision } else { new_base.revision },
+            horizon: new_base.horizon,
+            retention: new_base.retention,
+        };
+        if let Some(retention) = merged.retention {
+            merged.collect_garbage(merged.revision.saturating_sub(retention));
+        }
+        merged
+    }
+}
+
+/// Updates the single entries of a `TombstoneMap`, with or without reporting them
+trait UpdateEntry<K, V> {
+    /// Drops an entry from `set` which was written before `new_base` removed it
+    fn stale(&mut self, key: &K, last_base: Option<&V>, set: Option<&V>);
+
+    /// Updates a single value, where `None` is a removed or missing value, returning the value and
+    /// whether it was changed by `set`
+    fn update(
+        &mut self,
+        key: &K,
+        last_base: Option<V>,
+        new_base: Option<V>,
+        set: Option<V>,
+    ) -> (Option<V>, bool);
+}
+
+/// Updates entries without reporting them
+struct Plain;
+
+impl<K, V: UpdateSync + ChangeEq> UpdateEntry<K, V> for Plain {
+    fn stale(&mut self, _: &K, _: Option<&V>, _: Option<&V>) {}
+
+    fn update(
+        &mut self,
+        _: &K,
+        last_base: Option<V>,
+        new_base: Option<V>,
+        set: Option<V>,
+    ) -> (Option<V>, bool) {
+        match (last_base, new_base, set) {
+            (Some(l), Some(n), Some(s)) => {
+                let set_changed = !l.change_eq(&s);
+                (Some(V::update_sync(l, n, s)), set_changed)
+            }
+            (l, n, s) => {
+                if Resolution::classify(&l, &n, &s, ChangeEq::change_eq).took_set() {
+                    (s, true)
+                } else {
+                    (n, false)
+                }
+            }
+        }
+    }
+}
+
+/// Reports each entry under its key
+impl<K, V> UpdateEntry<K, V> for &mut dyn Visitor
+where
+    K: Debug,
+    V: UpdateSyncVisit + ChangeEq + Debug,
+{
+    fn stale(&mut self, key: &K, last_base: Option<&V>, set: Option<&V>) {
+        self.enter(Segment::Key(key));
+        self.leaf(Leaf {
+            resolution: Resolution::NewBase,
+            last_base: last_base.map(|l| l as &dyn Debug),
+            new_base: None,
+            set: set.map(|s| s as &dyn Debug),
+        });
+        self.leave();
+    }
+
+    fn update(
+        &mut self,
+        key: &K,
+        last_base: Option<V>,
+        new_base: Option<V>,
+        set: Option<V>,
+    ) -> (Option<V>, bool) {
+        self.enter(Segment::Key(key));
+        let updated = match (last_base, new_base, set) {
+            (Some(l), Some(n), Some(s)) => {
+                let set_changed = !l.change_eq(&s);
+                (Some(V::update_sync_visit(l, n, s, *self)), set_changed)
+            }
+            (l, n, s) => {
+                let resolution = Resolution::classify(&l, &n, &s, ChangeEq::change_eq);
+                self.leaf(Leaf {
+                    resolution,
+                    last_base: l.as_ref().map(|l| l as &dyn Debug),
+                    new_base: n.as_ref().map(|n| n as &dyn Debug),
+                    set: s.as_ref().map(|s| s as &dyn Debug),
+                });
+                if resolution.took_set() {
+                    (s, true)
+                } else {
+                    (n, false)
+                }
+            }
+        };
+        self.leave();
+        updated
+    }
+}
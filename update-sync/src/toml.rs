@@ -0,0 +1,172 @@
+//! Updating TOML documents without losing their formatting
+//!
+//! Enabled with the `toml_edit` feature, this implements `UpdateSync` for `toml_edit::DocumentMut`.
+//! Tables are updated key by key, with the same semantics as maps, recursing into tables in all
+//! three documents, and all other values are updated as a whole. Values are compared by what they
+//! hold, so reformatting a value, or moving between inline and standard tables, isn't a change.
+//!
+//! The update is made in place in `new_base`, so everything it doesn't change, including comments,
+//! whitespace and the order of keys, is kept byte for byte. A value taken from `set` keeps the
+//! whitespace and comments around it in `new_base`, and entries added by `set` are added with
+//! their formatting in `set`.
+//!
+//! YAML has no format preserving document library to build on, so YAML is best merged as a
+//! `serde_json::Value`, which loses comments.
+
+use std::fmt::Debug;
+
+use toml_edit::{DocumentMut, Item, TableLike, Value};
+
+use crate::{
+    map_entry_resolution,
+    visit::{Leaf, Recorder, Resolution, Segment, Visitor},
+    ChangeEq, UpdateSync, UpdateSyncVisit,
+};
+
+/// Compares items by the values they hold, ignoring their formatting
+impl ChangeEq for Item {
+    fn change_eq(&self, other: &Self) -> bool {
+        match (self.as_table_like(), other.as_table_like()) {
+            (Some(a), Some(b)) => table_eq(a, b),
+            (None, None) => match (self, other) {
+                (Item::None, Item::None) => true,
+                (Item::Value(a), Item::Value(b)) => value_eq(a, b),
+                (Item::ArrayOfTables(a), Item::ArrayOfTables(b)) => {
+                    a.len() == b.len() && a.iter().zip(b.iter()).all(|(a, b)| table_eq(a, b))
+                }
+                _ => false,
+            },
+            _ => false,
+        }
+    }
+}
+
+impl UpdateSync for DocumentMut {
+    fn update_sync(last_base: Self, new_base: Self, set: Self) -> Self {
+        Self::update_sync_visit(last_base, new_base, set, &mut ())
+    }
+}
+
+impl UpdateSyncVisit for DocumentMut {
+    fn update_sync_visit(
+        last_base: Self,
+        mut new_base: Self,
+        set: Self,
+        visitor: &mut dyn Visitor,
+    ) -> Self {
+        update_table(
+            last_base.as_table(),
+            new_base.as_table_mut(),
+            set.as_table(),
+            visitor,
+        );
+        new_base
+    }
+}
+
+/// The result of [`merge`]
+#[derive(Clone, Debug)]
+pub struct TomlMerge {
+    pub merged: DocumentMut,
+    /// JSON Pointers to every value both sides changed differently, where `set`'s change was kept
+    pub conflicts: Vec<String>,
+}
+
+/// Updates a TOML document, reporting every conflict
+pub fn merge(last_base: DocumentMut, new_base: DocumentMut, set: DocumentMut) -> TomlMerge {
+    let mut recorder = Recorder::default();
+    let merged = DocumentMut::update_sync_visit(last_base, new_base, set, &mut recorder);
+    TomlMerge {
+        merged,
+        conflicts: recorder
+            .leaves
+            .into_iter()
+            .filter(|(_, resolution)| *resolution == Resolution::Conflict)
+            .map(|(path, _)| path.to_pointer())
+            .collect(),
+    }
+}
+
+fn update_table(
+    last_base: &dyn TableLike,
+    new_base: &mut dyn TableLike,
+    set: &dyn TableLike,
+    visitor: &mut dyn Visitor,
+) {
+    let mut keys: Vec<String> = Vec::new();
+    for table in [last_base, &*new_base, set].iter() {
+        for (key, _) in table.iter() {
+            if !keys.iter().any(|known| known == key) {
+                keys.push(key.to_owned());
+            }
+        }
+    }
+    for key in keys {
+        visitor.enter(Segment::Key(&key));
+        let (l, s) = (last_base.get(&key), set.get(&key));
+        let tables = (
+            l.and_then(Item::as_table_like),
+            new_base.get_mut(&key).and_then(Item::as_table_like_mut),
+            s.and_then(Item::as_table_like),
+        );
+        if let (Some(l), Some(n), Some(s)) = tables {
+            update_table(l, n, s, visitor);
+            visitor.leave();
+            continue;
+        }
+        let n = new_base.get(&key);
+        let resolution = map_entry_resolution(l, n, s);
+        visitor.leaf(Leaf {
+            resolution,
+            last_base: l.map(|l| l as &dyn Debug),
+            new_base: n.map(|n| n as &dyn Debug),
+            set: s.map(|s| s as &dyn Debug),
+        });
+        visitor.leave();
+        if !resolution.took_set() {
+            continue;
+        }
+        match (new_base.get_mut(&key), set.get_key_value(&key)) {
+            (Some(n), Some((_, s))) => replace(n, s),
+            (None, Some((key, s))) => {
+                new_base.entry_format(key).or_insert(s.clone());
+            }
+            (_, None) => {
+                new_base.remove(&key);
+            }
+        }
+    }
+}
+
+/// Replaces `new_base` with `set`, keeping the whitespace and comments around a value
+fn replace(new_base: &mut Item, set: &Item) {
+    match (new_base, set) {
+        (Item::Value(new_base), Item::Value(set)) => {
+            let decor = new_base.decor().clone();
+            *new_base = set.clone();
+            *new_base.decor_mut() = decor;
+        }
+        (new_base, set) => *new_base = set.clone(),
+    }
+}
+
+fn table_eq(a: &dyn TableLike, b: &dyn TableLike) -> bool {
+    a.len() == b.len()
+        && a.iter()
+            .all(|(key, a)| b.get(key).is_some_and(|b| a.change_eq(b)))
+}
+
+fn value_eq(a: &Value, b: &Value) -> bool {
+    match (a, b) {
+        (Value::String(a), Value::String(b)) => a.value() == b.value(),
+        (Value::Integer(a), Value::Integer(b)) => a.value() == b.value(),
+        (Value::Float(a), Value::Float(b)) => a.value() == b.value(),
+        (Value::Boolean(a), Value::Boolean(b)) => a.value() == b.value(),
+        (Value::Datetime(a), Value::Datetime(b)) => a.value() == b.value(),
+        (Value::Array(a), Value::Array(b)) => {
+            a.len() == b.len() && a.iter().zip(b.iter()).all(|(a, b)| value_eq(a, b))
+        }
+        (Value::InlineTable(a), Value::InlineTable(b)) => table_eq(a, b),
+        _ => false,
+    }
+}
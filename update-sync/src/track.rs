@@ -0,0 +1,111 @@
+//! Tracking which fields a client changed
+//!
+//! A client syncing a value needs `last_base` as well as its edited `set`, which usually means
+//! keeping a second full copy of the value from when it last synced. A [`Tracked`] value instead
+//! keeps only the original value of each field it changed, and rebuilds `last_base` from them when
+//! it is needed, since every other field is unchanged.
+//!
+//! `#[derive(Track)]` implements [`Track`] for structs with named fields, along with a trait named
+//! after the struct, such as `RecordSetters` for `Record`, which gives `Tracked<Record>` a
+//! `set_<field>` and `<field>_mut` method for each field. Both mark the field as changed, keeping
+//! its original value the first time.
+//!
+//! Types which also implement [`Patch`] can send only their changed fields, as a patch from
+//! [`Tracked::changes`], rather than a whole `last_base` and `set`.
+
+use std::ops::Deref;
+
+use crate::patch::Patch;
+
+/// A struct whose changed fields can be tracked, usually derived with `#[derive(Track)]`
+pub trait Track: Clone {
+    /// The original value of every changed field
+    type Originals: Default + Clone;
+
+    /// Puts back the original value of every field in `originals`
+    fn restore(&mut self, originals: Self::Originals);
+
+    /// The names of the fields in `originals`
+    fn changed_fields(originals: &Self::Originals) -> Vec<&'static str>;
+}
+
+/// A value along with the original values of the fields which were changed since it last synced
+#[derive(Clone)]
+pub struct Tracked<T: Track> {
+    value: T,
+    originals: T::Originals,
+}
+
+impl<T: Track> Tracked<T> {
+    /// Tracks changes to a value which was just synced
+    pub fn new(value: T) -> Self {
+        Tracked {
+            value,
+            originals: T::Originals::default(),
+        }
+    }
+
+    pub fn get(&self) -> &T {
+        &self.value
+    }
+
+    pub fn into_inner(self) -> T {
+        self.value
+    }
+
+    /// Returns true if any field was changed since the value last synced
+    pub fn is_changed(&self) -> bool {
+        !self.changed_fields().is_empty()
+    }
+
+    /// The names of the fields which were changed since the value last synced
+    pub fn changed_fields(&self) -> Vec<&'static str> {
+        T::changed_fields(&self.originals)
+    }
+
+    /// The value when it last synced
+    pub fn last_base(&self) -> T {
+        let mut last_base = self.value.clone();
+        last_base.restore(self.originals.clone());
+        last_base
+    }
+
+    /// The changes to send to the server, or `None` if nothing changed
+    ///
+    /// Only fields which were changed are in the patch, so applying it to `new_base` gives the
+    /// same result as `update_sync(last_base, new_base, set)`.
+    pub fn changes(&self) -> Option<T::Patch>
+    where
+        T: Patch,
+    {
+        if self.is_changed() {
+            T::diff_patch(&self.last_base(), &self.value)
+        } else {
+            None
+        }
+    }
+
+    /// Replaces the value with the merged value the server replied with, forgetting every change
+    pub fn synced(&mut self, merged: T) {
+        *self = Tracked::new(merged);
+    }
+
+    /// Undoes every change since the value last synced
+    pub fn discard(&mut self) {
+        self.value.restore(std::mem::take(&mut self.originals));
+    }
+
+    /// The value and the original values of its changed fields, for use by derived setters
+    #[doc(hidden)]
+    pub fn parts_mut(&mut self) -> (&mut T, &mut T::Originals) {
+        (&mut self.value, &mut self.originals)
+    }
+}
+
+impl<T: Track> Deref for Tracked<T> {
+    type Target = T;
+
+    fn deref(&self) -> &T {
+        &self.value
+    }
+}
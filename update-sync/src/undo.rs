@@ -0,0 +1,121 @@
+//! Undoing synced changes
+//!
+//! Restoring a snapshot taken before a change would also throw away every change other users made
+//! since. Instead, applying a change records a [`Change`]: the state it was applied to and the state
+//! it produced. Undoing it is another update, whose `last_base` is the state the change produced
+//! and whose `set` is the state it was applied to, so only the values the change overwrote are put
+//! back, while values changed by anyone else are left as they now are.
+//!
+//! A [`History`] keeps the changes made by one user, so they can be undone and redone in order.
+
+use crate::{visit::Visitor, UpdateSync, UpdateSyncVisit};
+
+/// A change which was applied with `update_sync`
+#[derive(Clone, Debug, PartialEq)]
+pub struct Change<T> {
+    before: T,
+    after: T,
+}
+
+impl<T: UpdateSync + Clone> Change<T> {
+    /// Updates `new_base` with `set`, returning the result along with the change made
+    pub fn apply(last_base: T, new_base: T, set: T) -> (T, Change<T>) {
+        let after = T::update_sync(last_base, new_base.clone(), set);
+        (after.clone(), Change::new(new_base, after))
+    }
+
+    /// Records a change which turned `before` into `after`
+    pub fn new(before: T, after: T) -> Self {
+        Change { before, after }
+    }
+
+    /// The state the change was applied to
+    pub fn before(&self) -> &T {
+        &self.before
+    }
+
+    /// The state the change produced
+    pub fn after(&self) -> &T {
+        &self.after
+    }
+
+    /// Undoes the change in `current`, keeping changes made since by others, and returns the
+    /// result along with the change which redoes it
+    ///
+    /// A value which this change overwrote, and which was changed again since, is overwritten by
+    /// the undo as it would be by any other `set`.
+    pub fn undo(&self, current: T) -> (T, Change<T>) {
+        let undone = T::update_sync(self.after.clone(), current.clone(), self.before.clone());
+        (undone.clone(), Change::new(current, undone))
+    }
+}
+
+impl<T: UpdateSyncVisit + Clone> Change<T> {
+    /// Performs the same undo as `undo`, reporting the update to `visitor`
+    pub fn undo_visit(&self, current: T, visitor: &mut dyn Visitor) -> (T, Change<T>) {
+        let undone = T::update_sync_visit(
+            self.after.clone(),
+            current.clone(),
+            self.before.clone(),
+            visitor,
+        );
+        (undone.clone(), Change::new(current, undone))
+    }
+}
+
+/// The changes made by a single user, which can be undone and redone in order
+#[derive(Clone, Debug, PartialEq)]
+pub struct History<T> {
+    undo: Vec<Change<T>>,
+    redo: Vec<Change<T>>,
+}
+
+impl<T> Default for History<T> {
+    fn default() -> Self {
+        History {
+            undo: Vec::new(),
+            redo: Vec::new(),
+        }
+    }
+}
+
+impl<T: UpdateSync + Clone> History<T> {
+    pub fn new() -> Self {
+        History::default()
+    }
+
+    /// Updates `new_base` with `set` and records the change, forgetting any undone changes
+    pub fn apply(&mut self, last_base: T, new_base: T, set: T) -> T {
+        let (after, change) = Change::apply(last_base, new_base, set);
+        self.record(change);
+        after
+    }
+
+    /// Records a change which was applied elsewhere, forgetting any undone changes
+    pub fn record(&mut self, change: Change<T>) {
+        self.undo.push(change);
+        self.redo.clear();
+    }
+
+    /// Undoes the latest change in `current`, or returns `None` if there is nothing to undo
+    pub fn undo(&mut self, current: T) -> Option<T> {
+        let (undone, redo) = self.undo.pop()?.undo(current);
+        self.redo.push(redo);
+        Some(undone)
+    }
+
+    /// Redoes the latest undone change in `current`, or returns `None` if there is nothing to redo
+    pub fn redo(&mut self, current: T) -> Option<T> {
+        let (redone, undo) = self.redo.pop()?.undo(current);
+        self.undo.push(undo);
+        Some(redone)
+    }
+
+    pub fn can_undo(&self) -> bool {
+        !self.undo.is_empty()
+    }
+
+    pub fn can_redo(&self) -> bool {
+        !self.redo.is_empty()
+    }
+}
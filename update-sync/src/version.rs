@@ -0,0 +1,233 @@
+//! Version vectors
+//!
+//! `last_base` is all the three-way rule has to go on, so it can't tell a `set` written after
+//! seeing the server's change from one written at the same time as it, or a stale `set` delivered
+//! twice. A [`VersionVector`] counts the edits each replica has made, which orders two states by
+//! whether one has seen everything in the other.
+//!
+//! [`Versioned<T>`] pairs a value with its version vector. When updating, a `set` which has seen
+//! everything in `new_base` replaces it, a `set` which `new_base` has already seen is ignored,
+//! and only truly concurrent states are merged with `T`'s own `UpdateSync`.
+//!
+//! Peers without a server can each hold a [`Replica<T>`], which remembers the last state it shared
+//! with each peer to use as `last_base`.
+
+use std::{
+    cmp::Ordering,
+    collections::{btree_map::Entry, BTreeMap},
+};
+
+use crate::{clock::ReplicaId, visit::Visitor, ChangeEq, UpdateSync, UpdateSyncVisit};
+
+/// How two versions are related
+#[derive(Clone, Copy, Debug, PartialEq, Eq, Hash)]
+pub enum Causality {
+    /// Both have seen exactly the same edits
+    Equal,
+    /// The first has seen a subset of the edits the second has
+    Before,
+    /// The first has seen every edit the second has, and more
+    After,
+    /// Each has seen edits the other hasn't
+    Concurrent,
+}
+
+/// Counts the edits made by each replica
+#[derive(Clone, Debug, Default, PartialEq, Eq, Hash)]
+pub struct VersionVector(BTreeMap<ReplicaId, u64>);
+
+impl VersionVector {
+    pub fn new() -> Self {
+        VersionVector::default()
+    }
+
+    /// The number of edits seen from `replica`
+    pub fn get(&self, replica: ReplicaId) -> u64 {
+        self.0.get(&replica).copied().unwrap_or(0)
+    }
+
+    /// Records a new edit by `replica`, returning its edit count
+    pub fn increment(&mut self, replica: ReplicaId) -> u64 {
+        let counter = self.0.entry(replica).or_insert(0);
+        *counter += 1;
+        *counter
+    }
+
+    /// Records every edit seen by `other`
+    pub fn join(&mut self, other: &VersionVector) {
+        for (&replica, &counter) in &other.0 {
+            match self.0.entry(replica) {
+                Entry::Vacant(entry) => {
+                    entry.insert(counter);
+                }
+                Entry::Occupied(mut entry) => {
+                    let existing = entry.get_mut();
+                    *existing = (*existing).max(counter);
+                }
+            }
+        }
+    }
+
+    /// Determines how `self` is related to `other`
+    pub fn compare(&self, other: &VersionVector) -> Causality {
+        let mut ahead = false;
+        let mut behind = false;
+        for replica in self.0.keys().chain(other.0.keys()) {
+            match self.get(*replica).cmp(&other.get(*replica)) {
+                Ordering::Greater => ahead = true,
+                Ordering::Less => behind = true,
+                Ordering::Equal => {}
+            }
+        }
+        match (ahead, behind) {
+            (false, false) => Causality::Equal,
+            (false, true) => Causality::Before,
+            (true, false) => Causality::After,
+            (true, true) => Causality::Concurrent,
+        }
+    }
+
+    pub fn iter(&self) -> impl Iterator<Item = (ReplicaId, u64)> + '_ {
+        self.0.iter().map(|(&replica, &counter)| (replica, counter))
+    }
+}
+
+impl ChangeEq for VersionVector {
+    fn change_eq(&self, other: &Self) -> bool {
+        self == other
+    }
+}
+
+/// Version vectors only ever grow, so they update by joining
+impl UpdateSync for VersionVector {
+    fn update_sync(_: Self, mut new_base: Self, set: Self) -> Self {
+        new_base.join(&set);
+        new_base
+    }
+}
+
+impl UpdateSyncVisit for VersionVector {}
+
+/// A value along with the edits it has seen
+#[derive(Clone, Debug, Default, PartialEq)]
+pub struct Versioned<T> {
+    pub value: T,
+    pub version: VersionVector,
+}
+
+impl<T> Versioned<T> {
+    /// A value which has seen no edits
+    pub fn new(value: T) -> Self {
+        Versioned {
+            value,
+            version: VersionVector::new(),
+        }
+    }
+
+    /// Edits the value, recording the edit as made by `replica`
+    pub fn edit<R>(&mut self, replica: ReplicaId, edit: impl FnOnce(&mut T) -> R) -> R {
+        self.version.increment(replica);
+        edit(&mut self.value)
+    }
+}
+
+impl<T: UpdateSync> Versioned<T> {
+    fn update_sync_with(
+        last_base: Self,
+        mut new_base: Self,
+        set: Self,
+        update: impl FnOnce(T, T, T) -> T,
+    ) -> Self {
+        match set.version.compare(&new_base.version) {
+            Causality::Equal | Causality::Before => new_base,
+            Causality::After => set,
+            Causality::Concurrent => {
+                new_base.version.join(&set.version);
+                Versioned {
+                    value: update(last_base.value, new_base.value, set.value),
+                    version: new_base.version,
+                }
+            }
+        }
+    }
+}
+
+/// Sequential states replace each other, only concurrent states are merged, and only these are
+/// reported to a visitor
+impl<T: UpdateSync> UpdateSync for Versioned<T> {
+    fn update_sync(last_base: Self, new_base: Self, set: Self) -> Self {
+        Versioned::update_sync_with(last_base, new_base, set, T::update_sync)
+    }
+}
+
+impl<T: UpdateSyncVisit> UpdateSyncVisit for Versioned<T> {
+    fn update_sync_visit(
+        last_base: Self,
+        new_base: Self,
+        set: Self,
+        visitor: &mut dyn Visitor,
+    ) -> Self {
+        Versioned::update_sync_with(last_base, new_base, set, |l, n, s| {
+            T::update_sync_visit(l, n, s, visitor)
+        })
+    }
+}
+
+/// A peer holding its own copy of a value, which can sync with any other peer
+#[derive(Clone, Debug)]
+pub struct Replica<T> {
+    id: ReplicaId,
+    current: Versioned<T>,
+    /// The state every peer started from
+    origin: Versioned<T>,
+    /// The last state received from each peer, which both sides are known to have seen
+    synced: BTreeMap<ReplicaId, Versioned<T>>,
+}
+
+impl<T: UpdateSync + Clone> Replica<T> {
+    /// A replica starting from `origin`, which every peer it syncs with must also start from
+    pub fn new(id: ReplicaId, origin: Versioned<T>) -> Self {
+        Replica {
+            id,
+            current: origin.clone(),
+            origin,
+            synced: BTreeMap::new(),
+        }
+    }
+
+    pub fn id(&self) -> ReplicaId {
+        self.id
+    }
+
+    pub fn current(&self) -> &Versioned<T> {
+        &self.current
+    }
+
+    pub fn value(&self) -> &T {
+        &self.current.value
+    }
+
+    /// Edits the local copy
+    pub fn edit<R>(&mut self, edit: impl FnOnce(&mut T) -> R) -> R {
+        self.current.edit(self.id, edit)
+    }
+
+    /// Merges the state sent by the peer `from` into the local copy
+    ///
+    /// The state received becomes the `last_base` used the next time `from` sends a state.
+    pub fn receive(&mut self, from: ReplicaId, remote: Versioned<T>) {
+        let last_base = self.synced.get(&from).unwrap_or(&self.origin).clone();
+        let current = std::mem::replace(&mut self.current, self.origin.clone());
+        self.current = UpdateSync::update_sync(last_base, current, remote.clone());
+        self.synced.insert(from, remote);
+    }
+
+    /// Exchanges states with another replica, after which both hold the same value
+    pub fn sync_with(&mut self, other: &mut Replica<T>) {
+        self.receive(other.id, other.current.clone());
+        other.receive(self.id, self.current.clone());
+        // Both now hold the same state, which makes it the best base for their next sync
+        self.synced.insert(other.id, self.current.clone());
+        other.synced.insert(self.id, other.current.clone());
+    }
+}
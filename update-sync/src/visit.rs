@@ -0,0 +1,229 @@
+//! Observing the decisions made during an update
+//!
+//! [`UpdateSyncVisit::update_sync_visit`] walks the values being updated, telling a [`Visitor`] where it
+//! is with [`Segment`]s and reporting every value that was updated as a whole as a [`Leaf`]. This
+//! is what conflict reports and merge summaries are built from.
+
+use std::fmt::{self, Debug};
+
+use crate::{path::Path, ChangeEq, UpdateSyncVisit};
+
+/// Which input a value was taken from during an update, and why
+#[derive(Clone, Copy, Debug, PartialEq, Eq, Hash)]
+pub enum Resolution {
+    /// Neither `new_base` nor `set` changed the value
+    Unchanged,
+    /// Only `new_base` changed the value, so it was kept
+    NewBase,
+    /// Only `set` changed the value, so it was applied
+    Set,
+    /// Both changed the value to the same thing
+    Agreed,
+    /// Both changed the value differently, and `set` overwrote `new_base`
+    Conflict,
+    /// Both changed the value differently, and their changes were combined
+    Merged,
+}
+
+impl Resolution {
+    /// Classifies an update of a single value, using `eq` to decide if values have changed
+    pub fn classify<T>(last_base: &T, new_base: &T, set: &T, eq: impl Fn(&T, &T) -> bool) -> Self {
+        match (eq(last_base, new_base), eq(last_base, set)) {
+            (true, true) => Resolution::Unchanged,
+            (false, true) => Resolution::NewBase,
+            (true, false) => Resolution::Set,
+            (false, false) if eq(new_base, set) => Resolution::Agreed,
+            (false, false) => Resolution::Conflict,
+        }
+    }
+
+    /// Returns true if the value was taken from `set`
+    pub fn took_set(self) -> bool {
+        std::matches!(
+            self,
+            Resolution::Set | Resolution::Agreed | Resolution::Conflict
+        )
+    }
+
+    /// Returns true if the updated value differs from `new_base`
+    pub fn changed_new_base(self) -> bool {
+        std::matches!(
+            self,
+            Resolution::Set | Resolution::Conflict | Resolution::Merged
+        )
+    }
+}
+
+/// A step into a value being updated
+#[derive(Clone, Copy)]
+pub enum Segment<'a> {
+    /// A named field of a struct
+    Field(&'static str),
+    /// A position in a tuple, tuple struct, or list
+    Index(usize),
+    /// A key in a map
+    Key(&'a dyn Debug),
+    /// The variant of an enum, entered when all three inputs share it
+    Variant(&'static str),
+}
+
+impl Debug for Segment<'_> {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self {
+            Segment::Field(name) | Segment::Variant(name) => f.write_str(name),
+            Segment::Index(index) => write!(f, "{}", index),
+            Segment::Key(key) => write!(f, "[{:?}]", key),
+        }
+    }
+}
+
+/// A value which was updated as a whole
+///
+/// Inputs are `None` where the value is absent, such as a map entry which was removed.
+pub struct Leaf<'a> {
+    pub resolution: Resolution,
+    pub last_base: Option<&'a dyn Debug>,
+    pub new_base: Option<&'a dyn Debug>,
+    pub set: Option<&'a dyn Debug>,
+}
+
+/// Displays the name of an enum variant, used for leaves where the variant changed
+pub struct VariantName(pub &'static str);
+
+impl Debug for VariantName {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        f.write_str(self.0)
+    }
+}
+
+/// Receives the structure and decisions of an update
+pub trait Visitor {
+    /// Called before updating the value at `segment`, inside the current value
+    fn enter(&mut self, segment: Segment<'_>);
+
+    /// Called after updating the value most recently entered
+    fn leave(&mut self);
+
+    /// Called for every value updated as a whole
+    fn leaf(&mut self, leaf: Leaf<'_>);
+}
+
+/// Ignores everything
+impl Visitor for () {
+    fn enter(&mut self, _: Segment<'_>) {}
+    fn leave(&mut self) {}
+    fn leaf(&mut self, _: Leaf<'_>) {}
+}
+
+/// Updates a value as a whole, using `eq` to decide if it has changed, and reports it to `visitor`
+pub fn visit_leaf<T: Debug>(
+    last_base: T,
+    new_base: T,
+    set: T,
+    eq: impl Fn(&T, &T) -> bool,
+    visitor: &mut dyn Visitor,
+) -> T {
+    let resolution = Resolution::classify(&last_base, &new_base, &set, eq);
+    visitor.leaf(Leaf {
+        resolution,
+        last_base: Some(&last_base),
+        new_base: Some(&new_base),
+        set: Some(&set),
+    });
+    if resolution.took_set() {
+        set
+    } else {
+        new_base
+    }
+}
+
+/// Updates a value with `update`, which combines the changes made by `new_base` and `set`, and
+/// reports it to `visitor`
+///
+/// `eq` decides if the value has changed, values changed by both sides are reported as
+/// `Resolution::Merged`.
+pub fn visit_merge<T: Debug>(
+    last_base: T,
+    new_base: T,
+    set: T,
+    eq: impl Fn(&T, &T) -> bool,
+    update: impl FnOnce(T, T, T) -> T,
+    visitor: &mut dyn Visitor,
+) -> T {
+    let resolution = match Resolution::classify(&last_base, &new_base, &set, eq) {
+        Resolution::Conflict => Resolution::Merged,
+        resolution => resolution,
+    };
+    visitor.leaf(Leaf {
+        resolution,
+        last_base: Some(&last_base),
+        new_base: Some(&new_base),
+        set: Some(&set),
+    });
+    update(last_base, new_base, set)
+}
+
+/// Updates the value found at `segment`, surrounding it with `enter` and `leave`
+pub fn visit_segment<T: UpdateSyncVisit>(
+    segment: Segment<'_>,
+    last_base: T,
+    new_base: T,
+    set: T,
+    visitor: &mut dyn Visitor,
+) -> T {
+    visitor.enter(segment);
+    let new = T::update_sync_visit(last_base, new_base, set, visitor);
+    visitor.leave();
+    new
+}
+
+/// Updates a value as a whole with `ChangeEq`, reporting it to `visitor`
+pub fn visit_change_eq<T: ChangeEq + Debug>(
+    last_base: T,
+    new_base: T,
+    set: T,
+    visitor: &mut dyn Visitor,
+) -> T {
+    visit_leaf(last_base, new_base, set, T::change_eq, visitor)
+}
+
+/// Reports an enum whose variant differs between the inputs, in which case `set` is always used
+pub fn visit_variant_change(
+    last_base: &'static str,
+    new_base: &'static str,
+    set: &'static str,
+    visitor: &mut dyn Visitor,
+) {
+    let resolution = match Resolution::classify(&last_base, &new_base, &set, |a, b| a == b) {
+        // The variant from `set` overwrites the one `new_base` changed to
+        Resolution::NewBase => Resolution::Conflict,
+        resolution => resolution,
+    };
+    visitor.leaf(Leaf {
+        resolution,
+        last_base: Some(&VariantName(last_base)),
+        new_base: Some(&VariantName(new_base)),
+        set: Some(&VariantName(set)),
+    });
+}
+
+/// A visitor which records the resolution of every leaf, along with where it was
+#[derive(Default, Debug)]
+pub(crate) struct Recorder {
+    path: Path,
+    pub(crate) leaves: Vec<(Path, Resolution)>,
+}
+
+impl Visitor for Recorder {
+    fn enter(&mut self, segment: Segment<'_>) {
+        self.path.push(segment);
+    }
+
+    fn leave(&mut self) {
+        self.path.pop();
+    }
+
+    fn leaf(&mut self, leaf: Leaf<'_>) {
+        self.leaves.push((self.path.clone(), leaf.resolution));
+    }
+}
@@ -0,0 +1,91 @@
+//! Reading, writing and merging the supported file formats
+
+use std::{error::Error, path::Path, str::FromStr};
+
+use serde_json::Value;
+use toml_edit::DocumentMut;
+use update_sync::json::{self, JsonOptions};
+
+/// A structured file format
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum Format {
+    Json,
+    Toml,
+    Yaml,
+}
+
+impl Format {
+    /// Guesses the format of a file from its extension
+    pub fn from_path(path: &Path) -> Option<Format> {
+        path.extension()?.to_str()?.parse().ok()
+    }
+
+    /// Guesses the format of a file from its contents, trying JSON, then TOML, then YAML
+    ///
+    /// JSON is also YAML, so is tried first, while YAML accepts almost anything, so is tried last.
+    pub fn sniff(text: &str) -> Format {
+        if serde_json::from_str::<Value>(text).is_ok() {
+            Format::Json
+        } else if text.parse::<DocumentMut>().is_ok() {
+            Format::Toml
+        } else {
+            Format::Yaml
+        }
+    }
+
+    pub fn parse(self, text: &str) -> Result<Value, Box<dyn Error>> {
+        Ok(match self {
+            Format::Json => serde_json::from_str(text)?,
+            Format::Toml => toml::from_str(text)?,
+            Format::Yaml => serde_yaml::from_str(text)?,
+        })
+    }
+
+    pub fn render(self, value: &Value) -> Result<String, Box<dyn Error>> {
+        Ok(match self {
+            Format::Json => serde_json::to_string_pretty(value)? + "\n",
+            Format::Toml => toml::to_string_pretty(value)?,
+            Format::Yaml => serde_yaml::to_string(value)?,
+        })
+    }
+
+    /// Merges `[base, ours, theirs]`, given both as text and as values, returning the merged text
+    /// and JSON Pointers to every conflict
+    ///
+    /// TOML is merged as a document, keeping the formatting of `ours`.
+    pub fn merge(
+        self,
+        texts: &[String; 3],
+        values: &[Value],
+    ) -> Result<(String, Vec<String>), Box<dyn Error>> {
+        if self == Format::Toml {
+            let [base, ours, theirs] = texts;
+            let merged = update_sync::toml::merge(
+                base.parse::<DocumentMut>()?,
+                ours.parse()?,
+                theirs.parse()?,
+            );
+            return Ok((merged.merged.to_string(), merged.conflicts));
+        }
+        let merged = json::merge(
+            values[0].clone(),
+            values[1].clone(),
+            values[2].clone(),
+            &JsonOptions::default(),
+        );
+        Ok((self.render(&merged.merged)?, merged.conflicts))
+    }
+}
+
+impl FromStr for Format {
+    type Err = String;
+
+    fn from_str(name: &str) -> Result<Self, Self::Err> {
+        match name.to_ascii_lowercase().as_str() {
+            "json" => Ok(Format::Json),
+            "toml" => Ok(Format::Toml),
+            "yaml" | "yml" => Ok(Format::Yaml),
+            _ => Err(format!("unknown format `{}`", name)),
+        }
+    }
+}
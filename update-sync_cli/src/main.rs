@@ -0,0 +1,189 @@
+//! Three-way merging of JSON, TOML and YAML files
+//!
+//! ```text
+//! update-sync-cli merge [--format json|toml|yaml] [--path NAME] [--output FILE] BASE OURS THEIRS
+//! ```
+//!
+//! `OURS` is updated with the changes `THEIRS` made since `BASE`, as `update_sync` would with
+//! `BASE` as `last_base`, `OURS` as `new_base` and `THEIRS` as `set`. Objects are merged key by
+//! key, with the same semantics as maps, and all other values are merged as a whole. TOML files
+//! keep their comments and formatting, other formats are rewritten.
+//!
+//! Without `--format`, the format is guessed from the extension of `--path`, the name the file
+//! is checked in under, or else of `OURS`. Files with neither are recognised by their contents.
+//!
+//! The merged file is written to `--output`, or to standard output. Where both sides changed a
+//! value differently, `THEIRS` is kept, the conflict is reported on standard error, and the exit
+//! status is 1. Keys `THEIRS` deleted are only removed if `OURS` deleted them too, as `set` can't
+//! delete map entries, so keys which `THEIRS` deleted and the merged file still has are reported
+//! as conflicts as well. Errors exit with status 2. This makes it usable as a git merge driver:
+//!
+//! ```text
+//! # .git/config
+//! [merge "update-sync"]
+//!     name = three-way merge of structured config files
+//!     driver = update-sync-cli merge --path %P --output %A %O %A %B
+//!
+//! # .gitattributes
+//! *.toml merge=update-sync
+//! ```
+
+mod format;
+
+use std::{borrow::Cow, error::Error, fs, path::PathBuf, process};
+
+use format::Format;
+use serde_json::Value;
+use update_sync::path::{Path, PathSegment};
+
+const USAGE: &str = "usage: update-sync-cli merge [--format json|toml|yaml] [--path NAME] \
+                     [--output FILE] BASE OURS THEIRS";
+
+const HELP: &str = "
+
+Updates OURS with the changes THEIRS made since BASE. Where both changed a value, THEIRS is kept,
+and where THEIRS deleted a key which OURS kept, the key is kept. Both are reported as conflicts,
+with exit status 1.";
+
+/// The arguments to `merge`
+#[derive(Debug, Default)]
+struct Merge {
+    format: Option<Format>,
+    path: Option<PathBuf>,
+    output: Option<PathBuf>,
+    files: Vec<PathBuf>,
+}
+
+impl Merge {
+    fn parse(mut args: impl Iterator<Item = String>) -> Result<Merge, String> {
+        if args.next().as_deref() != Some("merge") {
+            return Err(USAGE.into());
+        }
+        let mut merge = Merge::default();
+        while let Some(arg) = args.next() {
+            let mut value = |name: &str| args.next().ok_or(format!("{} needs a value", name));
+            match arg.as_str() {
+                "-f" | "--format" => merge.format = Some(value("--format")?.parse()?),
+                "-p" | "--path" => merge.path = Some(value("--path")?.into()),
+                "-o" | "--output" => merge.output = Some(value("--output")?.into()),
+                "-h" | "--help" => return Err(format!("{}{}", USAGE, HELP)),
+                _ => merge.files.push(arg.into()),
+            }
+        }
+        if merge.files.len() != 3 {
+            return Err(USAGE.into());
+        }
+        Ok(merge)
+    }
+
+    /// Performs the merge, returning true if there were conflicts
+    fn run(&self) -> Result<bool, Box<dyn Error>> {
+        let read = |path: &PathBuf| {
+            fs::read_to_string(path).map_err(|error| format!("{}: {}", path.display(), error))
+        };
+        let texts = [
+            read(&self.files[0])?,
+            read(&self.files[1])?,
+            read(&self.files[2])?,
+        ];
+        // Git's temporary files have no extension, so fall back to the name from `--path`
+        let format = self
+            .format
+            .or_else(|| self.path.as_deref().and_then(Format::from_path))
+            .or_else(|| Format::from_path(&self.files[1]))
+            .unwrap_or_else(|| Format::sniff(&texts[1]));
+        let mut values = Vec::new();
+        for (path, text) in self.files.iter().zip(&texts) {
+            let value = format
+                .parse(text)
+                .map_err(|error| format!("{}: {}", path.display(), error))?;
+            values.push(value);
+        }
+
+        let (rendered, conflicts) = format.merge(&texts, &values)?;
+        let show = |value: &Value, pointer: &str| {
+            value
+                .pointer(pointer)
+                .map_or_else(|| "(none)".into(), Value::to_string)
+        };
+        for pointer in &conflicts {
+            eprintln!(
+                "conflict at {}: base {}, ours {}, theirs {} (kept theirs)",
+                if pointer.is_empty() { "/" } else { pointer },
+                show(&values[0], pointer),
+                show(&values[1], pointer),
+                show(&values[2], pointer)
+            );
+        }
+
+        // Read back what was written, as TOML documents are merged without `values`
+        let merged = format.parse(&rendered)?;
+        let mut deletions = Vec::new();
+        kept_deletions(
+            &values[0],
+            &values[2],
+            &merged,
+            &mut Path::new(),
+            &mut deletions,
+        );
+        for pointer in &deletions {
+            eprintln!(
+                "conflict at {}: base {}, ours {}, deleted by theirs (kept ours)",
+                pointer,
+                show(&values[0], pointer),
+                show(&values[1], pointer)
+            );
+        }
+
+        match &self.output {
+            Some(path) => fs::write(path, rendered)
+                .map_err(|error| format!("{}: {}", path.display(), error))?,
+            None => print!("{}", rendered),
+        }
+        Ok(!conflicts.is_empty() || !deletions.is_empty())
+    }
+}
+
+/// Collects JSON Pointers to the keys of objects in `base` which `theirs` deleted and `merged` kept
+fn kept_deletions(
+    base: &Value,
+    theirs: &Value,
+    merged: &Value,
+    path: &mut Path,
+    deletions: &mut Vec<String>,
+) {
+    let (base, theirs, merged) = match (base, theirs, merged) {
+        (Value::Object(base), Value::Object(theirs), Value::Object(merged)) => {
+            (base, theirs, merged)
+        }
+        _ => return,
+    };
+    for (key, base) in base {
+        if let Some(merged) = merged.get(key) {
+            path.push(PathSegment::Field(Cow::Owned(key.clone())));
+            match theirs.get(key) {
+                Some(theirs) => kept_deletions(base, theirs, merged, path, deletions),
+                None => deletions.push(path.to_pointer()),
+            }
+            path.pop();
+        }
+    }
+}
+
+fn main() {
+    let merge = match Merge::parse(std::env::args().skip(1)) {
+        Ok(merge) => merge,
+        Err(message) => {
+            eprintln!("{}", message);
+            process::exit(2);
+        }
+    };
+    match merge.run() {
+        Ok(false) => {}
+        Ok(true) => process::exit(1),
+        Err(error) => {
+            eprintln!("error: {}", error);
+            process::exit(2);
+        }
+    }
+}
@@ -0,0 +1,142 @@
+use std::{
+    fs,
+    path::{Path, PathBuf},
+    process::{Command, Output},
+};
+
+/// A fresh directory for a single test's files
+fn scratch(name: &str) -> PathBuf {
+    let dir = std::env::temp_dir().join(format!("update-sync-cli-{}-{}", name, std::process::id()));
+    let _ = fs::remove_dir_all(&dir);
+    fs::create_dir_all(&dir).unwrap();
+    dir
+}
+
+/// Writes `[base, ours, theirs]` to files with the given extension, returning their paths
+fn write(dir: &Path, extension: &str, texts: [&str; 3]) -> Vec<PathBuf> {
+    ["base", "ours", "theirs"]
+        .iter()
+        .zip(&texts)
+        .map(|(name, text)| {
+            let path = dir.join(name).with_extension(extension);
+            fs::write(&path, text).unwrap();
+            path
+        })
+        .collect()
+}
+
+fn merge(args: &[&str], files: &[PathBuf]) -> Output {
+    Command::new(env!("CARGO_BIN_EXE_update-sync-cli"))
+        .arg("merge")
+        .args(args)
+        .args(files)
+        .output()
+        .unwrap()
+}
+
+#[test]
+fn clean_merge() {
+    let dir = scratch("clean");
+    let files = write(
+        &dir,
+        "json",
+        [
+            r#"{"a": 1, "b": 1}"#,
+            r#"{"a": 2, "b": 1}"#,
+            r#"{"a": 1, "b": 3}"#,
+        ],
+    );
+    let output = merge(&[], &files);
+    assert_eq!(output.status.code(), Some(0));
+    let merged: serde_json::Value = serde_json::from_slice(&output.stdout).unwrap();
+    assert_eq!(merged, serde_json::json!({ "a": 2, "b": 3 }));
+}
+
+#[test]
+fn conflicts_are_reported() {
+    let dir = scratch("conflict");
+    let files = write(&dir, "yaml", ["a: 1\n", "a: 2\n", "a: 3\n"]);
+    let out = dir.join("merged.yaml");
+    let output = merge(&["--output", out.to_str().unwrap()], &files);
+    assert_eq!(output.status.code(), Some(1));
+    assert!(output.stdout.is_empty());
+    assert!(String::from_utf8_lossy(&output.stderr).contains("conflict at /a"));
+    assert_eq!(fs::read_to_string(out).unwrap(), "a: 3\n");
+}
+
+#[test]
+fn errors() {
+    let dir = scratch("errors");
+    let files = write(&dir, "json", ["{}", "{", "{}"]);
+    assert_eq!(merge(&[], &files).status.code(), Some(2));
+    assert_eq!(merge(&[], &files[..2]).status.code(), Some(2));
+    assert_eq!(merge(&["--format", "ini"], &files).status.code(), Some(2));
+}
+
+#[test]
+fn format_detection() {
+    // Git passes temporary files without extensions, with the checked in name as `--path`
+    let dir = scratch("detect");
+    let texts = [
+        "# settings\na = 1\nb = 1\n",
+        "# settings\na = 2\nb = 1\n",
+        "# settings\na = 1\nb = 3\n",
+    ];
+    let files = write(&dir, "", texts);
+    let output = merge(&["--path", "config/settings.toml"], &files);
+    assert_eq!(output.status.code(), Some(0));
+    assert_eq!(
+        String::from_utf8(output.stdout).unwrap(),
+        "# settings\na = 2\nb = 3\n"
+    );
+
+    // Without a name, the format is recognised from the contents
+    let output = merge(&[], &files);
+    assert_eq!(output.status.code(), Some(0));
+    assert_eq!(
+        String::from_utf8(output.stdout).unwrap(),
+        "# settings\na = 2\nb = 3\n"
+    );
+    let files = write(&dir, "", ["[1]", "[2]", "[1]"]);
+    let output = merge(&[], &files);
+    assert_eq!(output.status.code(), Some(0));
+    assert_eq!(String::from_utf8(output.stdout).unwrap(), "[\n  2\n]\n");
+    let files = write(&dir, "", ["a: 1\n", "a: 2\n", "a: 1\n"]);
+    let output = merge(&[], &files);
+    assert_eq!(String::from_utf8(output.stdout).unwrap(), "a: 2\n");
+
+    // `--format` takes precedence over the name
+    let output = merge(&["--path", "settings.json", "--format", "yaml"], &files);
+    assert_eq!(String::from_utf8(output.stdout).unwrap(), "a: 2\n");
+}
+
+#[test]
+fn deletions_by_theirs_are_reported() {
+    let dir = scratch("deletions");
+    let files = write(
+        &dir,
+        "json",
+        [
+            r#"{"a": 1, "b": {"c": 1, "d": 1}, "e": 1}"#,
+            r#"{"a": 2, "b": {"c": 1, "d": 1}}"#,
+            r#"{"a": 1, "b": {"d": 1}, "e": 1}"#,
+        ],
+    );
+    let output = merge(&[], &files);
+    assert_eq!(output.status.code(), Some(1));
+    let merged: serde_json::Value = serde_json::from_slice(&output.stdout).unwrap();
+    assert_eq!(
+        merged,
+        serde_json::json!({ "a": 2, "b": { "c": 1, "d": 1 } })
+    );
+    assert_eq!(
+        String::from_utf8_lossy(&output.stderr),
+        "conflict at /b/c: base 1, ours 1, deleted by theirs (kept ours)\n"
+    );
+
+    // Keys both sides deleted are gone without a conflict
+    let files = write(&dir, "toml", ["a = 1\nb = 1\n", "a = 1\n", "a = 1\n"]);
+    let output = merge(&[], &files);
+    assert_eq!(output.status.code(), Some(0));
+    assert_eq!(String::from_utf8(output.stdout).unwrap(), "a = 1\n");
+}
@@ -0,0 +1,311 @@
+//! Parsing for the `#[update_sync(...)]` helper attribute
+
+use proc_macro2::TokenStream as TokenStream2;
+use quote::quote;
+use syn::{
+    Attribute, Error, GenericArgument, Lit, Member, Meta, MetaNameValue, NestedMeta, Path,
+    PathArguments, Result, Type,
+};
+
+/// How a single field should be updated
+enum FieldStrategy {
+    /// Recurse into the fields own `UpdateSync` implementation
+    Default,
+    /// Treat floats within the given literal of each other as unchanged
+    Epsilon(Lit),
+    /// Treat floats within the given number of representable values as unchanged
+    Ulps(Lit),
+    /// Treat the field as a single value, compared with the given function
+    ChangeEqWith(Path),
+    /// Never treat the field as changed, so `new_base` is always kept
+    SkipChangeEq,
+    /// Keep whichever of `new_base` and `set` was written last
+    Lww,
+    /// Update with the given function, which merges the changes of both sides
+    With(Path),
+    /// Match up the records of a list by the given field of each record
+    Key(Member),
+}
+
+/// The options set on a field with `#[update_sync(...)]`
+pub(crate) struct FieldAttrs {
+    strategy: FieldStrategy,
+}
+
+impl FieldAttrs {
+    pub(crate) fn parse(attrs: &[Attribute]) -> Result<Self> {
+        let mut field_attrs = FieldAttrs {
+            strategy: FieldStrategy::Default,
+        };
+        for meta in update_sync_metas(attrs)? {
+            match meta {
+                Meta::NameValue(MetaNameValue { path, lit, .. }) if path.is_ident("epsilon") => {
+                    field_attrs.set_strategy(&path, FieldStrategy::Epsilon(lit))?
+                }
+                Meta::NameValue(MetaNameValue { path, lit, .. }) if path.is_ident("ulps") => {
+                    field_attrs.set_strategy(&path, FieldStrategy::Ulps(lit))?
+                }
+                Meta::NameValue(MetaNameValue { path, lit, .. })
+                    if path.is_ident("change_eq_with") =>
+                {
+                    let with = parse_lit_path(&lit)?;
+                    field_attrs.set_strategy(&path, FieldStrategy::ChangeEqWith(with))?
+                }
+                Meta::NameValue(MetaNameValue { path, lit, .. }) if path.is_ident("with") => {
+                    let with = parse_lit_path(&lit)?;
+                    field_attrs.set_strategy(&path, FieldStrategy::With(with))?
+                }
+                Meta::NameValue(MetaNameValue { path, lit, .. }) if path.is_ident("key") => {
+                    let key = match &lit {
+                        Lit::Str(lit) => lit.parse()?,
+                        lit => {
+                            return Err(Error::new_spanned(
+                                lit,
+                                "expected a string containing a field name",
+                            ))
+                        }
+                    };
+                    field_attrs.set_strategy(&path, FieldStrategy::Key(key))?
+                }
+                Meta::Path(path) if path.is_ident("skip_change_eq") => {
+                    field_attrs.set_strategy(&path, FieldStrategy::SkipChangeEq)?
+                }
+                Meta::Path(path) if path.is_ident("lww") => {
+                    field_attrs.set_strategy(&path, FieldStrategy::Lww)?
+                }
+                meta => return Err(Error::new_spanned(meta, "unknown `update_sync` option")),
+            }
+        }
+        Ok(field_attrs)
+    }
+
+    /// Returns true if the field is updated with its own `UpdateSync` implementation
+    pub(crate) fn is_default(&self) -> bool {
+        std::matches!(self.strategy, FieldStrategy::Default)
+    }
+
+    /// Returns the name of the option if the field can be unchanged while its value differs, such
+    /// as a float compared with an `epsilon`
+    pub(crate) fn inexact(&self) -> Option<&'static str> {
+        match self.strategy {
+            FieldStrategy::Epsilon(_) => Some("epsilon"),
+            FieldStrategy::Ulps(_) => Some("ulps"),
+            FieldStrategy::ChangeEqWith(_) => Some("change_eq_with"),
+            FieldStrategy::SkipChangeEq => Some("skip_change_eq"),
+            _ => None,
+        }
+    }
+
+    /// Returns true if the field never counts as changed
+    pub(crate) fn is_skipped(&self) -> bool {
+        std::matches!(self.strategy, FieldStrategy::SkipChangeEq)
+    }
+
+    fn set_strategy(&mut self, path: &Path, strategy: FieldStrategy) -> Result<()> {
+        if !std::matches!(self.strategy, FieldStrategy::Default) {
+            return Err(Error::new_spanned(
+                path,
+                "only one update strategy may be set per field",
+            ));
+        }
+        self.strategy = strategy;
+        Ok(())
+    }
+
+    /// Produces an expression updating the field from the three given expressions
+    pub(crate) fn update(
+        &self,
+        last_base: TokenStream2,
+        new_base: TokenStream2,
+        set: TokenStream2,
+    ) -> TokenStream2 {
+        match &self.strategy {
+            FieldStrategy::Default => quote! {
+                ::update_sync::UpdateSync::update_sync(#last_base, #new_base, #set)
+            },
+            FieldStrategy::Epsilon(epsilon) => quote! {
+                ::update_sync::float::update_sync_epsilon(#last_base, #new_base, #set, #epsilon)
+            },
+            FieldStrategy::Ulps(ulps) => quote! {
+                ::update_sync::float::update_sync_ulps(#last_base, #new_base, #set, #ulps)
+            },
+            FieldStrategy::ChangeEqWith(with) => quote! {
+                {
+                    let (last_base, new_base, set) = (#last_base, #new_base, #set);
+                    if !#with(&last_base, &set) {
+                        set
+                    } else {
+                        new_base
+                    }
+                }
+            },
+            FieldStrategy::SkipChangeEq => new_base,
+            FieldStrategy::Lww => quote! {
+                ::update_sync::lww::update_sync_lww(#last_base, #new_base, #set)
+            },
+            FieldStrategy::With(with) => quote! {
+                #with(#last_base, #new_base, #set)
+            },
+            FieldStrategy::Key(key) => quote! {
+                ::update_sync::keyed::update_sync_keyed(
+                    #last_base,
+                    #new_base,
+                    #set,
+                    |record| ::std::clone::Clone::clone(&record.#key),
+                )
+            },
+        }
+    }
+
+    /// Produces an expression updating the field like `update`, reporting it to `visitor` under
+    /// `segment`
+    pub(crate) fn update_visit(
+        &self,
+        segment: TokenStream2,
+        last_base: TokenStream2,
+        new_base: TokenStream2,
+        set: TokenStream2,
+    ) -> TokenStream2 {
+        let update = match &self.strategy {
+            FieldStrategy::Default => {
+                return quote! {
+                    ::update_sync::visit::visit_segment(#segment, #last_base, #new_base, #set, visitor)
+                }
+            }
+            FieldStrategy::SkipChangeEq => return new_base,
+            FieldStrategy::Lww => quote! {
+                ::update_sync::lww::update_sync_lww_visit(#last_base, #new_base, #set, visitor)
+            },
+            FieldStrategy::With(with) => quote! {
+                ::update_sync::visit::visit_merge(
+                    #last_base,
+                    #new_base,
+                    #set,
+                    ::update_sync::ChangeEq::change_eq,
+                    #with,
+                    visitor,
+                )
+            },
+            FieldStrategy::Key(key) => quote! {
+                ::update_sync::keyed::update_sync_keyed_visit(
+                    #last_base,
+                    #new_base,
+                    #set,
+                    |record| ::std::clone::Clone::clone(&record.#key),
+                    visitor,
+                )
+            },
+            FieldStrategy::Epsilon(_) | FieldStrategy::Ulps(_) | FieldStrategy::ChangeEqWith(_) => {
+                let change_eq = self.change_eq(quote! { a }, quote! { b });
+                quote! {
+                    ::update_sync::visit::visit_leaf(
+                        #last_base,
+                        #new_base,
+                        #set,
+                        |a, b| #change_eq,
+                        visitor,
+                    )
+                }
+            }
+        };
+        quote! {
+            {
+                visitor.enter(#segment);
+                let new = #update;
+                visitor.leave();
+                new
+            }
+        }
+    }
+
+    /// Produces the bound a field of type `ty` needs for its update to be reported, under
+    /// `for<'__visit>` so that an unmet bound leaves the type without `UpdateSyncVisit`
+    pub(crate) fn visit_bound(&self, ty: &Type) -> TokenStream2 {
+        match &self.strategy {
+            FieldStrategy::Default => quote! { for<'__visit> #ty: ::update_sync::UpdateSyncVisit, },
+            FieldStrategy::SkipChangeEq => quote! {},
+            // Records are reported one by one, so it is their type which needs the bounds
+            FieldStrategy::Key(_) => match element_type(ty) {
+                Some(record) => quote! {
+                    for<'__visit> #record: ::update_sync::UpdateSyncVisit
+                        + ::update_sync::ChangeEq
+                        + ::std::fmt::Debug,
+                },
+                None => quote! {},
+            },
+            _ => quote! { for<'__visit> #ty: ::std::fmt::Debug, },
+        }
+    }
+
+    /// Produces an expression comparing the field for `ChangeEq`, or `None` if it is skipped
+    pub(crate) fn change_eq(&self, a: TokenStream2, b: TokenStream2) -> Option<TokenStream2> {
+        Some(match &self.strategy {
+            FieldStrategy::Default | FieldStrategy::With(_) | FieldStrategy::Key(_) => quote! {
+                ::update_sync::ChangeEq::change_eq(#a, #b)
+            },
+            FieldStrategy::Epsilon(epsilon) => quote! {
+                ::update_sync::float::Float::within_epsilon(*#a, *#b, #epsilon)
+            },
+            FieldStrategy::Ulps(ulps) => quote! {
+                ::update_sync::float::Float::ulps_between(*#a, *#b) <= #ulps
+            },
+            FieldStrategy::ChangeEqWith(with) => quote! {
+                #with(#a, #b)
+            },
+            FieldStrategy::SkipChangeEq => return None,
+            FieldStrategy::Lww => quote! {
+                ::update_sync::lww::Stamped::stamp(#a) == ::update_sync::lww::Stamped::stamp(#b)
+            },
+        })
+    }
+}
+
+/// The type of the elements of a list, such as `T` in `Vec<T>`
+fn element_type(ty: &Type) -> Option<&Type> {
+    let segment = match ty {
+        Type::Path(ty) => ty.path.segments.last()?,
+        _ => return None,
+    };
+    match &segment.arguments {
+        PathArguments::AngleBracketed(arguments) => match arguments.args.first()? {
+            GenericArgument::Type(element) => Some(element),
+            _ => None,
+        },
+        _ => None,
+    }
+}
+
+/// Parses a string literal containing a path, such as `"my_mod::my_fn"`
+fn parse_lit_path(lit: &Lit) -> Result<Path> {
+    match lit {
+        Lit::Str(lit) => lit.parse(),
+        lit => Err(Error::new_spanned(
+            lit,
+            "expected a string containing a path",
+        )),
+    }
+}
+
+/// Flattens every `#[update_sync(...)]` attribute into the list of options inside it
+fn update_sync_metas(attrs: &[Attribute]) -> Result<Vec<Meta>> {
+    let mut metas = Vec::new();
+    for attr in attrs
+        .iter()
+        .filter(|attr| attr.path.is_ident("update_sync"))
+    {
+        match attr.parse_meta()? {
+            Meta::List(list) => {
+                for nested in list.nested {
+                    match nested {
+                        NestedMeta::Meta(meta) => metas.push(meta),
+                        NestedMeta::Lit(lit) => {
+                            return Err(Error::new_spanned(lit, "expected an `update_sync` option"))
+                        }
+                    }
+                }
+            }
+            meta => return Err(Error::new_spanned(meta, "expected `#[update_sync(...)]`")),
+        }
+    }
+    Ok(metas)
+}
@@ -0,0 +1,92 @@
+use proc_macro2::TokenStream as TokenStream2;
+use quote::{format_ident, quote};
+use syn::{Data, DataEnum, DataStruct, DeriveInput, Fields, Ident, Result, Variant};
+
+use crate::attr::FieldAttrs;
+
+pub(crate) fn change_eq_impl(DeriveInput { ident, data, .. }: DeriveInput) -> Result<TokenStream2> {
+    let body = match data {
+        Data::Struct(DataStruct { fields, .. }) => {
+            let (self_pattern, self_names) = bind_fields(&fields, "self");
+            let (other_pattern, other_names) = bind_fields(&fields, "other");
+            let compare = compare_fields(&fields, &self_names, &other_names)?;
+            quote! {
+                let #ident #self_pattern = self;
+                let #ident #other_pattern = other;
+                #compare
+            }
+        }
+        Data::Enum(DataEnum { variants, .. }) => {
+            let arms = variants
+                .iter()
+                .map(
+                    |Variant {
+                         ident: v_ident,
+                         fields,
+                         ..
+                     }| {
+                        let (self_pattern, self_names) = bind_fields(fields, "self");
+                        let (other_pattern, other_names) = bind_fields(fields, "other");
+                        let compare = compare_fields(fields, &self_names, &other_names)?;
+                        Ok(quote! {
+                            (#ident :: #v_ident #self_pattern, #ident :: #v_ident #other_pattern) => {
+                                #compare
+                            }
+                        })
+                    },
+                )
+                .collect::<Result<TokenStream2>>()?;
+            quote! {
+                #[allow(unreachable_patterns)]
+                match (self, other) {
+                    #arms
+                    _ => false,
+                }
+            }
+        }
+        Data::Union(_) => return Ok(quote! {}),
+    };
+    Ok(quote! {
+        impl ::update_sync::ChangeEq for #ident {
+            #[allow(unused_variables)]
+            fn change_eq(&self, other: &Self) -> bool {
+                #body
+            }
+        }
+    })
+}
+
+/// Produces a pattern destructuring the fields into bindings with the given prefix, along with the
+/// names of those bindings
+pub(crate) fn bind_fields(fields: &Fields, prefix: &str) -> (TokenStream2, Vec<Ident>) {
+    let names: Vec<Ident> = fields
+        .iter()
+        .enumerate()
+        .map(|(i, field)| match &field.ident {
+            Some(ident) => format_ident!("__{}_{}", prefix, ident),
+            None => format_ident!("__{}_{}", prefix, i),
+        })
+        .collect();
+    let pattern = match fields {
+        Fields::Named(_) => {
+            let idents = fields.iter().map(|field| &field.ident);
+            quote! { { #( #idents: #names ),* } }
+        }
+        Fields::Unnamed(_) => quote! { ( #( #names ),* ) },
+        Fields::Unit => quote! {},
+    };
+    (pattern, names)
+}
+
+/// Produces an expression which is true if every field that is not skipped is `ChangeEq`
+fn compare_fields(fields: &Fields, a: &[Ident], b: &[Ident]) -> Result<TokenStream2> {
+    let mut compare = quote! { true };
+    for ((field, a), b) in fields.iter().zip(a).zip(b) {
+        if let Some(field_eq) =
+            FieldAttrs::parse(&field.attrs)?.change_eq(quote! { #a }, quote! { #b })
+        {
+            compare = quote! { #compare && #field_eq };
+        }
+    }
+    Ok(compare)
+}
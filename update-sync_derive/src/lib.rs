@@ -2,25 +2,123 @@ use proc_macro::TokenStream as TokenStream1;
 use proc_macro2::{Span as Span2, TokenStream as TokenStream2};
 use quote::{format_ident, quote, ToTokens};
 use syn::{
-    parse_macro_input, punctuated::Punctuated, Data, DataEnum, DataStruct, DeriveInput, Field,
-    Fields, FieldsNamed, FieldsUnnamed, Ident, Index, Token, Variant,
+    ext::IdentExt, parse_macro_input, punctuated::Punctuated, Data, DataEnum, DataStruct,
+    DeriveInput, Field, Fields, FieldsNamed, FieldsUnnamed, Ident, Index, Result, Token, Variant,
 };
 
+mod attr;
+mod change_eq;
+mod patch;
+mod sync_hash;
+mod track;
+
+use attr::FieldAttrs;
+
 /// Automatically derives `UpdateSync` to update the fields of structs, so long as they are all themselves `UpdateSync`
 /// It will do the same for enums, but syncing to different variants where appropriate
-#[proc_macro_derive(UpdateSync)]
+///
+/// `UpdateSyncVisit` is implemented too, whenever every field's type implements it.
+///
+/// Fields can be annotated with `#[update_sync(epsilon = 1e-6)]` or `#[update_sync(ulps = 4)]`
+/// to ignore small changes to floats, or with the options described on `ChangeEq` to control when
+/// they are treated as changed. Fields whose type implements `update_sync::lww::Stamped` can be
+/// annotated with `#[update_sync(lww)]` to keep whichever value was written last.
+///
+/// Fields annotated with `#[update_sync(skip_change_eq)]` always keep the value in `new_base`,
+/// even when only `set` changed them, since a change to them is never a change to the struct.
+#[proc_macro_derive(UpdateSync, attributes(update_sync))]
 pub fn derive_update_sync(input: TokenStream1) -> TokenStream1 {
-    let DeriveInput { ident, data, .. } = parse_macro_input!(input as DeriveInput);
+    let input = parse_macro_input!(input as DeriveInput);
+    update_sync_impl(input)
+        .unwrap_or_else(|e| e.to_compile_error())
+        .into()
+}
+
+/// Derives `ChangeEq` by comparing every field, so long as they are all themselves `ChangeEq`
+/// Enums are only equal if they are the same variant
+///
+/// Fields can be annotated with `#[update_sync(skip_change_eq)]` to never count as changed, or
+/// `#[update_sync(change_eq_with = "path::to::fn")]` to compare them with a function taking two
+/// references.
+#[proc_macro_derive(ChangeEq, attributes(update_sync))]
+pub fn derive_change_eq(input: TokenStream1) -> TokenStream1 {
+    let input = parse_macro_input!(input as DeriveInput);
+    change_eq::change_eq_impl(input)
+        .unwrap_or_else(|e| e.to_compile_error())
+        .into()
+}
+
+/// Derives `Patch` for structs with named fields, generating a patch struct, such as
+/// `RecordPatch` for `Record`, with an `Option` for each field
+///
+/// Also generates `Record::diff(&old, &new)` to create patches, and `RecordPatch::apply_to` to
+/// apply them. Patches implement `Serialize` and `Deserialize` with the `serde` feature.
+///
+/// Fields are patched the way `#[derive(UpdateSync)]` updates them. Fields with an
+/// `#[update_sync(...)]` option hold their old value along with their new one, and fields marked
+/// `skip_change_eq` are left out of the patch.
+///
+/// Enums, and structs without named fields, are replaced whole, so must also implement
+/// `ChangeEq` and `Clone`, and are their own patch.
+#[proc_macro_derive(Patch, attributes(update_sync))]
+pub fn derive_patch(input: TokenStream1) -> TokenStream1 {
+    let input = parse_macro_input!(input as DeriveInput);
+    patch::patch_impl(input)
+        .unwrap_or_else(|e| e.to_compile_error())
+        .into()
+}
+
+/// Derives `SyncHash`, hashing every field, so long as they are all themselves `SyncHash`
+/// The hash tree has a child for each field, and for enums a single child for the variant
+///
+/// Fields with `epsilon`, `ulps`, `change_eq_with` or `skip_change_eq` are refused, as they can
+/// differ without changing, which a hash can't tell apart from a change.
+#[proc_macro_derive(SyncHash, attributes(update_sync))]
+pub fn derive_sync_hash(input: TokenStream1) -> TokenStream1 {
+    let input = parse_macro_input!(input as DeriveInput);
+    sync_hash::sync_hash_impl(input)
+        .unwrap_or_else(|e| e.to_compile_error())
+        .into()
+}
 
-    match data {
+/// Derives `Track` for structs with named fields, so that changes to them can be tracked with
+/// `update_sync::track::Tracked`
+///
+/// Also generates a trait named after the struct, such as `RecordSetters` for `Record`, with a
+/// `set_<field>` and `<field>_mut` method for each field of `Tracked<Record>`, and a struct, such
+/// as `RecordOriginals`, holding the original values of changed fields.
+#[proc_macro_derive(Track)]
+pub fn derive_track(input: TokenStream1) -> TokenStream1 {
+    let input = parse_macro_input!(input as DeriveInput);
+    track::track_impl(input)
+        .unwrap_or_else(|e| e.to_compile_error())
+        .into()
+}
+
+fn update_sync_impl(DeriveInput { ident, data, .. }: DeriveInput) -> Result<TokenStream2> {
+    Ok(match data {
         Data::Struct(DataStruct { fields, .. }) => {
-            let update_fields = struct_update_fields(&fields);
+            let update_fields = struct_update_fields(&fields, false)?;
+            let visit_fields = struct_update_fields(&fields, true)?;
+            let visit_bounds = visit_bounds(&fields)?;
             quote! {
                 impl ::update_sync::UpdateSync for #ident {
                     fn update_sync(last_base: Self, new_base: Self, set: Self) -> Self {
                         #ident #update_fields
                     }
                 }
+
+                impl ::update_sync::UpdateSyncVisit for #ident where #visit_bounds {
+                    #[allow(unused_variables)]
+                    fn update_sync_visit(
+                        last_base: Self,
+                        new_base: Self,
+                        set: Self,
+                        visitor: &mut dyn ::update_sync::visit::Visitor,
+                    ) -> Self {
+                        #ident #visit_fields
+                    }
+                }
             }
         }
         Data::Enum(DataEnum { variants, .. }) => {
@@ -33,16 +131,16 @@ pub fn derive_update_sync(input: TokenStream1) -> TokenStream1 {
                          fields,
                          ..
                      }| {
-                        let terminator =
-                            if std::matches!(fields, Fields::Unit | Fields::Unnamed(_)) {
-                                <Token![;]>::default().to_token_stream()
-                            } else {
-                                quote! {}
-                            };
+                        let terminator = if std::matches!(fields, Fields::Unit | Fields::Unnamed(_))
+                        {
+                            <Token![;]>::default().to_token_stream()
+                        } else {
+                            quote! {}
+                        };
                         let field_assign = field_match_assign(&ident, v_ident, fields);
                         let unassign = field_match_unassign(v_ident, fields);
                         quote! {
-                            #[derive(::update_sync::derive::UpdateSync, PartialEq)]
+                            #[derive(::update_sync::derive::UpdateSync)]
                             struct #v_ident #fields #terminator
                             impl #v_ident {
                                 fn from_enum(from: #ident) -> Option<#v_ident> {
@@ -74,6 +172,44 @@ pub fn derive_update_sync(input: TokenStream1) -> TokenStream1 {
                     }
                 })
                 .collect();
+            let visit_matches: TokenStream2 = variants
+                .iter()
+                .map(|Variant { ident: v_ident, .. }| {
+                    let name = v_ident.unraw().to_string();
+                    quote! {
+                        #ident :: #v_ident { .. } => {
+                            let last_base = #mod_ident :: #v_ident :: from_enum ( last_base ).unwrap();
+                            let new_base = #mod_ident :: #v_ident :: from_enum ( new_base ).unwrap();
+                            let set = #mod_ident :: #v_ident :: from_enum ( set ).unwrap();
+                            let new = ::update_sync::visit::visit_segment(
+                                ::update_sync::visit::Segment::Variant(#name),
+                                last_base,
+                                new_base,
+                                set,
+                                visitor,
+                            );
+                            new.to_enum()
+                        },
+                    }
+                })
+                .collect();
+            let visit_bounds: TokenStream2 = variants
+                .iter()
+                .map(|Variant { ident: v_ident, .. }| {
+                    quote! {
+                        for<'__visit> #mod_ident :: #v_ident: ::update_sync::UpdateSyncVisit,
+                    }
+                })
+                .collect();
+            let variant_names: TokenStream2 = variants
+                .iter()
+                .map(|Variant { ident: v_ident, .. }| {
+                    let name = v_ident.unraw().to_string();
+                    quote! {
+                        #ident :: #v_ident { .. } => #name,
+                    }
+                })
+                .collect();
             quote! {
                 #[doc(hidden)]
                 #[allow(non_snake_case)]
@@ -96,28 +232,75 @@ pub fn derive_update_sync(input: TokenStream1) -> TokenStream1 {
                             }
                         }
                     }
+
+                    impl ::update_sync::UpdateSyncVisit for #ident where #visit_bounds {
+                        fn update_sync_visit(
+                            last_base: Self,
+                            new_base: Self,
+                            set: Self,
+                            visitor: &mut dyn ::update_sync::visit::Visitor,
+                        ) -> Self {
+                            fn variant_name(value: &#ident) -> &'static str {
+                                match value {
+                                    #variant_names
+                                }
+                            }
+                            let last_base_discriminant = std::mem::discriminant(&last_base);
+                            let new_base_discriminant = std::mem::discriminant(&new_base);
+                            let set_discriminant = std::mem::discriminant(&set);
+                            if last_base_discriminant != set_discriminant || last_base_discriminant != new_base_discriminant {
+                                ::update_sync::visit::visit_variant_change(
+                                    variant_name(&last_base),
+                                    variant_name(&new_base),
+                                    variant_name(&set),
+                                    visitor,
+                                );
+                                set
+                            } else {
+                                match last_base {
+                                    #visit_matches
+                                    _ => std::unreachable!()
+                                }
+                            }
+                        }
+                    }
                 }
             }
         }
         Data::Union(_) => quote! {},
-    }
-    .into()
+    })
 }
 
-fn struct_update_fields(fields: &Fields) -> TokenStream2 {
-    match fields {
+/// Produces the where clause requiring every field updated by its own `UpdateSync` to be visitable
+///
+/// The bounds are placed under `for<'__visit>` so that a field which can't be visited, such as an
+/// `Option` of a type without `Debug`, leaves the type without `UpdateSyncVisit` instead of
+/// failing to compile.
+fn visit_bounds(fields: &Fields) -> Result<TokenStream2> {
+    fields
+        .iter()
+        .map(|field| Ok(FieldAttrs::parse(&field.attrs)?.visit_bound(&field.ty)))
+        .collect()
+}
+
+/// Produces the braces constructing the updated struct, reporting to `visitor` if `visit` is set
+fn struct_update_fields(fields: &Fields, visit: bool) -> Result<TokenStream2> {
+    Ok(match fields {
         Fields::Named(FieldsNamed { named: fields, .. })
         | Fields::Unnamed(FieldsUnnamed {
             unnamed: fields, ..
         }) => {
-            let fields = struct_update_named_or_unnamed(fields);
-            (quote! { { #fields } }).into()
+            let fields = struct_update_named_or_unnamed(fields, visit)?;
+            quote! { { #fields } }
         }
         Fields::Unit => quote! {},
-    }
+    })
 }
 
-fn struct_update_named_or_unnamed<T>(fields: &Punctuated<Field, T>) -> TokenStream2 {
+fn struct_update_named_or_unnamed<T>(
+    fields: &Punctuated<Field, T>,
+    visit: bool,
+) -> Result<TokenStream2> {
     fields
         .iter()
         .enumerate()
@@ -129,13 +312,27 @@ fn struct_update_named_or_unnamed<T>(fields: &Punctuated<Field, T>) -> TokenStre
                 }
                 .to_token_stream(),
             );
-            quote! {
-                #field: ::update_sync::UpdateSync::update_sync(
-                    last_base.#field,
-                    new_base.#field,
-                    set.#field,
-                ),
-            }
+            let attrs = FieldAttrs::parse(&f.attrs)?;
+            let (last_base, new_base, set) = (
+                quote! { last_base.#field },
+                quote! { new_base.#field },
+                quote! { set.#field },
+            );
+            let update = if visit {
+                let segment = match &f.ident {
+                    Some(ident) => {
+                        let name = ident.unraw().to_string();
+                        quote! { ::update_sync::visit::Segment::Field(#name) }
+                    }
+                    None => quote! { ::update_sync::visit::Segment::Index(#i) },
+                };
+                attrs.update_visit(segment, last_base, new_base, set)
+            } else {
+                attrs.update(last_base, new_base, set)
+            };
+            Ok(quote! {
+                #field: #update,
+            })
         })
         .collect()
 }
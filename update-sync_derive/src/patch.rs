@@ -0,0 +1,145 @@
+use proc_macro2::TokenStream as TokenStream2;
+use quote::{format_ident, quote};
+use syn::{Data, DataStruct, DeriveInput, Fields, Result};
+
+use crate::attr::FieldAttrs;
+
+pub(crate) fn patch_impl(
+    DeriveInput {
+        ident, vis, data, ..
+    }: DeriveInput,
+) -> Result<TokenStream2> {
+    let fields = match data {
+        Data::Struct(DataStruct {
+            fields: Fields::Named(fields),
+            ..
+        }) => fields.named,
+        // Enums, and structs without named fields, are replaced whole
+        _ => {
+            return Ok(quote! {
+                impl ::update_sync::patch::Patch for #ident {
+                    type Patch = Self;
+
+                    fn diff_patch(old: &Self, new: &Self) -> Option<Self> {
+                        if ::update_sync::ChangeEq::change_eq(old, new) {
+                            None
+                        } else {
+                            Some(::std::clone::Clone::clone(new))
+                        }
+                    }
+
+                    fn apply_patch(&mut self, patch: Self) {
+                        *self = patch;
+                    }
+                }
+            })
+        }
+    };
+    let patch_ident = format_ident!("{}Patch", ident);
+    let mut idents = Vec::new();
+    let mut field_vis = Vec::new();
+    let mut patch_types = Vec::new();
+    let mut diffs = Vec::new();
+    let mut applies = Vec::new();
+    for field in &fields {
+        let attrs = FieldAttrs::parse(&field.attrs)?;
+        if attrs.is_skipped() {
+            continue;
+        }
+        let (field_ident, ty) = (&field.ident, &field.ty);
+        match attrs.change_eq(quote! { old }, quote! { new }) {
+            Some(change_eq) if !attrs.is_default() => {
+                // Other strategies need `last_base` too, so the old value is sent along with the new
+                let update = attrs.update(
+                    quote! { last_base },
+                    quote! { ::std::clone::Clone::clone(&self.#field_ident) },
+                    quote! { set },
+                );
+                patch_types.push(quote! { (#ty, #ty) });
+                diffs.push(quote! {
+                    {
+                        let (old, new) = (&old.#field_ident, &new.#field_ident);
+                        if #change_eq {
+                            None
+                        } else {
+                            Some((::std::clone::Clone::clone(old), ::std::clone::Clone::clone(new)))
+                        }
+                    }
+                });
+                applies.push(quote! {
+                    if let Some((last_base, set)) = patch.#field_ident {
+                        self.#field_ident = #update;
+                    }
+                });
+            }
+            _ => {
+                patch_types.push(quote! { <#ty as ::update_sync::patch::Patch>::Patch });
+                diffs.push(quote! {
+                    ::update_sync::patch::Patch::diff_patch(&old.#field_ident, &new.#field_ident)
+                });
+                applies.push(quote! {
+                    if let Some(patch) = patch.#field_ident {
+                        ::update_sync::patch::Patch::apply_patch(&mut self.#field_ident, patch);
+                    }
+                });
+            }
+        }
+        idents.push(field_ident);
+        field_vis.push(&field.vis);
+    }
+    let patch_doc = format!(
+        "The changed fields of a `{}`, created with `{}::diff`",
+        ident, ident
+    );
+    Ok(quote! {
+        ::update_sync::__patch_struct! {
+            #[doc = #patch_doc]
+            #[derive(Clone, Debug, Default, PartialEq)]
+            #vis struct #patch_ident {
+                #( #field_vis #idents: Option<#patch_types>, )*
+            }
+        }
+
+        impl ::update_sync::patch::Patch for #ident {
+            type Patch = #patch_ident;
+
+            fn diff_patch(old: &Self, new: &Self) -> Option<#patch_ident> {
+                let patch = #patch_ident {
+                    #( #idents: #diffs, )*
+                };
+                if patch.is_empty() {
+                    None
+                } else {
+                    Some(patch)
+                }
+            }
+
+            fn apply_patch(&mut self, patch: #patch_ident) {
+                #( #applies )*
+            }
+        }
+
+        impl #ident {
+            /// The fields which changed from `old` to `new`
+            #vis fn diff(old: &Self, new: &Self) -> #patch_ident {
+                ::update_sync::patch::Patch::diff_patch(old, new).unwrap_or_default()
+            }
+        }
+
+        impl #patch_ident {
+            /// Returns true if no field changed
+            #vis fn is_empty(&self) -> bool {
+                true #( && self.#idents.is_none() )*
+            }
+
+            /// Applies the changed fields to `new_base`, keeping every other field as it is
+            ///
+            /// Gives the same result as `update_sync(last_base, new_base, set)`, where the patch
+            /// was created with `diff(&last_base, &set)`.
+            #vis fn apply_to(self, mut new_base: #ident) -> #ident {
+                ::update_sync::patch::Patch::apply_patch(&mut new_base, self);
+                new_base
+            }
+        }
+    })
+}
@@ -0,0 +1,119 @@
+use proc_macro2::TokenStream as TokenStream2;
+use quote::quote;
+use syn::{
+    ext::IdentExt, Data, DataEnum, DataStruct, DeriveInput, Error, Fields, Ident, Result, Variant,
+};
+
+use crate::{attr::FieldAttrs, change_eq::bind_fields};
+
+pub(crate) fn sync_hash_impl(DeriveInput { ident, data, .. }: DeriveInput) -> Result<TokenStream2> {
+    let (sync_hash, hash_tree) = match data {
+        Data::Struct(DataStruct { fields, .. }) => {
+            let (pattern, names) = bind_fields(&fields, "self");
+            let (hashes, trees) = hash_fields(&fields, &names)?;
+            (
+                quote! {
+                    let #ident #pattern = self;
+                    ::update_sync::hash::node_hash(&#hashes)
+                },
+                quote! {
+                    let #ident #pattern = self;
+                    ::update_sync::hash::HashTree::node(#trees)
+                },
+            )
+        }
+        Data::Enum(DataEnum { variants, .. }) => {
+            let (hash_arms, tree_arms): (TokenStream2, TokenStream2) = variants
+                .iter()
+                .map(
+                    |Variant {
+                         ident: v_ident,
+                         fields,
+                         ..
+                     }| {
+                        let (pattern, names) = bind_fields(fields, "self");
+                        let (hashes, trees) = hash_fields(fields, &names)?;
+                        let name = v_ident.unraw().to_string();
+                        let segment = quote! {
+                            ::update_sync::path::PathSegment::Variant(
+                                ::std::borrow::Cow::Borrowed(#name),
+                            )
+                        };
+                        Ok((
+                            quote! {
+                                #ident :: #v_ident #pattern => ::update_sync::hash::node_hash(&[(
+                                    #segment,
+                                    ::update_sync::hash::node_hash(&#hashes),
+                                )]),
+                            },
+                            quote! {
+                                #ident :: #v_ident #pattern => ::update_sync::hash::HashTree::node(vec![(
+                                    #segment,
+                                    ::update_sync::hash::HashTree::node(#trees),
+                                )]),
+                            },
+                        ))
+                    },
+                )
+                .collect::<Result<Vec<_>>>()?
+                .into_iter()
+                .unzip();
+            (
+                quote! { match self { #hash_arms } },
+                quote! { match self { #tree_arms } },
+            )
+        }
+        Data::Union(_) => return Ok(quote! {}),
+    };
+    Ok(quote! {
+        impl ::update_sync::hash::SyncHash for #ident {
+            #[allow(unused_variables)]
+            fn sync_hash(&self) -> u64 {
+                #sync_hash
+            }
+
+            #[allow(unused_variables)]
+            fn hash_tree(&self) -> ::update_sync::hash::HashTree {
+                #hash_tree
+            }
+        }
+    })
+}
+
+/// Produces an array of every field's hash, and a `Vec` of every field's hash tree, each along
+/// with the field's path segment
+///
+/// Fields whose value can change without the field counting as changed are refused, as hashing
+/// them would make `update_sync_hashed` take `set`'s value where `update_sync` keeps `new_base`'s,
+/// and leaving them out would hide changes which do count.
+fn hash_fields(fields: &Fields, names: &[Ident]) -> Result<(TokenStream2, TokenStream2)> {
+    for field in fields {
+        if let Some(option) = FieldAttrs::parse(&field.attrs)?.inexact() {
+            return Err(Error::new_spanned(
+                field,
+                format!("`SyncHash` can't be derived for fields with `{}`", option),
+            ));
+        }
+    }
+    let segments: Vec<TokenStream2> = fields
+        .iter()
+        .enumerate()
+        .map(|(i, field)| match &field.ident {
+            Some(ident) => {
+                let name = ident.unraw().to_string();
+                quote! {
+                    ::update_sync::path::PathSegment::Field(::std::borrow::Cow::Borrowed(#name))
+                }
+            }
+            None => quote! { ::update_sync::path::PathSegment::Index(#i) },
+        })
+        .collect();
+    Ok((
+        quote! {
+            [#( (#segments, ::update_sync::hash::SyncHash::sync_hash(#names)) ),*]
+        },
+        quote! {
+            vec![#( (#segments, ::update_sync::hash::SyncHash::hash_tree(#names)) ),*]
+        },
+    ))
+}
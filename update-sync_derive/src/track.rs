@@ -0,0 +1,111 @@
+use proc_macro2::TokenStream as TokenStream2;
+use quote::{format_ident, quote};
+use syn::{ext::IdentExt, Data, DataStruct, DeriveInput, Error, Fields, Result};
+
+pub(crate) fn track_impl(
+    DeriveInput {
+        ident, vis, data, ..
+    }: DeriveInput,
+) -> Result<TokenStream2> {
+    let fields = match data {
+        Data::Struct(DataStruct {
+            fields: Fields::Named(fields),
+            ..
+        }) => fields.named,
+        _ => {
+            return Err(Error::new_spanned(
+                ident,
+                "`Track` can only be derived for structs with named fields",
+            ))
+        }
+    };
+    let originals_ident = format_ident!("{}Originals", ident);
+    let setters_ident = format_ident!("{}Setters", ident);
+    let idents: Vec<_> = fields.iter().map(|field| &field.ident).collect();
+    let types: Vec<_> = fields.iter().map(|field| &field.ty).collect();
+    let names: Vec<_> = idents
+        .iter()
+        .map(|ident| ident.as_ref().unwrap().unraw().to_string())
+        .collect();
+    let setters: Vec<_> = names
+        .iter()
+        .map(|name| format_ident!("set_{}", name))
+        .collect();
+    let getters: Vec<_> = names
+        .iter()
+        .map(|name| format_ident!("{}_mut", name))
+        .collect();
+    let setter_docs: Vec<_> = names
+        .iter()
+        .map(|name| format!("Sets `{}`, marking it as changed", name))
+        .collect();
+    let getter_docs: Vec<_> = names
+        .iter()
+        .map(|name| format!("Borrows `{}` mutably, marking it as changed", name))
+        .collect();
+    let originals_doc = format!(
+        "The original values of the changed fields of a tracked `{}`",
+        ident
+    );
+    let setters_doc = format!(
+        "Setters which mark the fields of a tracked `{}` as changed",
+        ident
+    );
+    Ok(quote! {
+        #[doc = #originals_doc]
+        #[derive(Default, Clone)]
+        #vis struct #originals_ident {
+            #( #idents: Option<#types>, )*
+        }
+
+        impl ::update_sync::track::Track for #ident {
+            type Originals = #originals_ident;
+
+            fn restore(&mut self, originals: Self::Originals) {
+                #(
+                    if let Some(original) = originals.#idents {
+                        self.#idents = original;
+                    }
+                )*
+            }
+
+            fn changed_fields(originals: &Self::Originals) -> Vec<&'static str> {
+                let mut changed = Vec::new();
+                #(
+                    if originals.#idents.is_some() {
+                        changed.push(#names);
+                    }
+                )*
+                changed
+            }
+        }
+
+        #[doc = #setters_doc]
+        #vis trait #setters_ident {
+            #(
+                #[doc = #setter_docs]
+                fn #setters(&mut self, value: #types);
+                #[doc = #getter_docs]
+                fn #getters(&mut self) -> &mut #types;
+            )*
+        }
+
+        impl #setters_ident for ::update_sync::track::Tracked<#ident> {
+            #(
+                fn #setters(&mut self, value: #types) {
+                    let (tracked, originals) = self.parts_mut();
+                    let original = ::std::mem::replace(&mut tracked.#idents, value);
+                    originals.#idents.get_or_insert(original);
+                }
+
+                fn #getters(&mut self) -> &mut #types {
+                    let (tracked, originals) = self.parts_mut();
+                    if originals.#idents.is_none() {
+                        originals.#idents = Some(::std::clone::Clone::clone(&tracked.#idents));
+                    }
+                    &mut tracked.#idents
+                }
+            )*
+        }
+    })
+}
@@ -82,10 +82,10 @@ impl Client {
 #[test]
 fn full_demo() {
     // Lets set up the clients and server for our demo
-    let ref mut server = Server::default();
+    let server = &mut Server::default();
     server.user = Some(Record::default());
-    let ref mut client_1 = Client::default();
-    let ref mut client_2 = Client::default();
+    let client_1 = &mut Client::default();
+    let client_2 = &mut Client::default();
     client_1.sync(server);
     client_2.sync(server);
 
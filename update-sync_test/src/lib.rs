@@ -1,6 +1,6 @@
 #![cfg(test)]
 use std::collections::BTreeMap;
-use update_sync::UpdateSync;
+use update_sync::{UpdateSync, UpdateSyncVisit};
 
 #[test]
 fn maps() {
@@ -41,7 +41,7 @@ fn unit_struct() {
     assert_eq!(is, should_be);
 }
 
-#[derive(update_sync::derive::UpdateSync, PartialEq, Debug)]
+#[derive(update_sync::derive::UpdateSync, PartialEq, Debug, Clone, Copy)]
 struct WithFields {
     foo: i32,
     bar: char,
@@ -118,4 +118,2134 @@ fn enums_change_variant() {
     assert_eq!(is, should_be);
 }
 
+#[test]
+fn result_same_variant() {
+    let base: Result<(u8, char), String> = Ok((0, 'a'));
+    let one = Ok((1, 'a'));
+    let two = Ok((0, 'b'));
+    let should_be = Ok((1, 'b'));
+    let is = UpdateSync::update_sync(base, one, two);
+    assert_eq!(is, should_be);
+}
+
+#[test]
+fn result_change_variant() {
+    let base: Result<u8, String> = Ok(0);
+    let one = Ok(1);
+    let two = Err("oops".to_string());
+    let should_be = Err("oops".to_string());
+    let is = UpdateSync::update_sync(base, one, two);
+    assert_eq!(is, should_be);
+}
+
+#[test]
+fn std_atomics() {
+    use std::{net::IpAddr, path::PathBuf, time::Duration};
+
+    let base = (
+        Duration::from_secs(1),
+        IpAddr::from([127, 0, 0, 1]),
+        PathBuf::from("/tmp"),
+    );
+    let one = (
+        Duration::from_secs(2),
+        IpAddr::from([127, 0, 0, 1]),
+        PathBuf::from("/var"),
+    );
+    let two = (
+        Duration::from_secs(1),
+        IpAddr::from([10, 0, 0, 1]),
+        PathBuf::from("/tmp"),
+    );
+    let should_be = (
+        Duration::from_secs(2),
+        IpAddr::from([10, 0, 0, 1]),
+        PathBuf::from("/var"),
+    );
+    let is = UpdateSync::update_sync(base, one, two);
+    assert_eq!(is, should_be);
+}
+
+#[test]
+fn nan_is_unchanged() {
+    let is = UpdateSync::update_sync(f64::NAN, 1.0, f64::NAN);
+    assert_eq!(is, 1.0);
+
+    let is: f32 = UpdateSync::update_sync(0.0, 1.0, f32::NAN);
+    assert!(is.is_nan());
+}
+
+#[derive(update_sync::derive::UpdateSync, PartialEq, Debug)]
+struct WithTolerance {
+    #[update_sync(epsilon = 1e-6)]
+    x: f64,
+    #[update_sync(ulps = 4)]
+    y: f32,
+    z: f64,
+}
+
+#[test]
+fn float_tolerance() {
+    let base = WithTolerance {
+        x: 0.1,
+        y: 0.1,
+        z: 0.1,
+    };
+    let one = WithTolerance {
+        x: 0.5,
+        y: 0.5,
+        z: 0.5,
+    };
+    let two = WithTolerance {
+        x: 0.1 + 1e-9,
+        y: f32::from_bits(0.1f32.to_bits() + 1),
+        z: 0.1 + 1e-9,
+    };
+    let should_be = WithTolerance {
+        x: 0.5,
+        y: 0.5,
+        z: 0.1 + 1e-9,
+    };
+    let is = UpdateSync::update_sync(base, one, two);
+    assert_eq!(is, should_be);
+}
+
+/// Deliberately not `PartialEq`, with a field that should never count as an edit
+/// Deliberately without `Debug`, which updating doesn't need
+#[derive(update_sync::derive::UpdateSync, update_sync::derive::ChangeEq, Clone, PartialEq)]
+struct Secret(u32);
+
+#[derive(update_sync::derive::UpdateSync, Clone, PartialEq)]
+struct Vault {
+    pin: Option<Secret>,
+    secrets: BTreeMap<String, Secret>,
+}
+
+/// Fields with update options need `Debug` only for reporting too
+#[derive(update_sync::derive::UpdateSync, Clone, PartialEq)]
+struct Archive {
+    #[update_sync(with = "update_sync::rename::update_sync_renamed")]
+    secrets: BTreeMap<String, Secret>,
+    #[update_sync(key = "0")]
+    history: Vec<Secret>,
+}
+
+#[test]
+fn update_sync_without_debug() {
+    use update_sync::{ordered::OrderedList, tombstone::TombstoneMap};
+
+    let vault = |pin: u32, secrets: &[(&str, u32)]| Vault {
+        pin: Some(Secret(pin)),
+        secrets: secrets
+            .iter()
+            .map(|(name, secret)| (name.to_string(), Secret(*secret)))
+            .collect(),
+    };
+    let last_base = vault(1, &[("a", 1)]);
+    let new_base = vault(1, &[("a", 1), ("b", 2)]);
+    let set = vault(2, &[("a", 3)]);
+    let is = UpdateSync::update_sync(last_base, new_base, set);
+    assert!(is == vault(2, &[("a", 3), ("b", 2)]));
+
+    let list = |secrets: &[(char, u32)]| -> OrderedList<char, Secret> {
+        secrets
+            .iter()
+            .map(|&(key, secret)| (key, Secret(secret)))
+            .collect()
+    };
+    let is = UpdateSync::update_sync(
+        list(&[('a', 1), ('b', 2)]),
+        list(&[('a', 1), ('b', 2), ('c', 3)]),
+        list(&[('a', 4), ('b', 2)]),
+    );
+    assert!(is.iter().map(|(_, secret)| secret.0).eq(vec![4, 2, 3]));
+
+    // The server renames `a` while the client changes it
+    let is = update_sync::rename::update_sync_renamed_by(
+        vault(1, &[("a", 1)]).secrets,
+        vault(1, &[("z", 1)]).secrets,
+        vault(1, &[("a", 11)]).secrets,
+        |secret| secret.0 % 10,
+    );
+    assert!(is == vault(1, &[("z", 11)]).secrets);
+
+    let mut last_base = TombstoneMap::new();
+    last_base.insert("a", Secret(1));
+    last_base.insert("b", Secret(2));
+    let mut new_base = last_base.clone();
+    new_base.remove(&"b");
+    let mut set = last_base.clone();
+    *set.get_mut(&"a").unwrap() = Secret(3);
+    let is = UpdateSync::update_sync(last_base, new_base, set);
+    assert!(is
+        .iter()
+        .map(|(key, secret)| (*key, secret.0))
+        .eq(vec![("a", 3)]));
+
+    let mut history = update_sync::undo::History::new();
+    let changed = history.apply(vault(1, &[]), vault(1, &[]), vault(2, &[]));
+    assert!(history.undo(changed) == Some(vault(1, &[])));
+
+    let archive = |secrets: &[(&str, u32)], history: Vec<u32>| Archive {
+        secrets: vault(0, secrets).secrets,
+        history: history.into_iter().map(Secret).collect(),
+    };
+    let is = UpdateSync::update_sync(
+        archive(&[("a", 1)], vec![1]),
+        archive(&[("b", 1)], vec![1, 2]),
+        archive(&[("a", 1), ("c", 3)], vec![1, 3]),
+    );
+    assert!(is == archive(&[("b", 1), ("c", 3)], vec![1, 3, 2]));
+}
+
+#[derive(update_sync::derive::ChangeEq, Clone, Debug)]
+struct Cached {
+    value: u32,
+    #[update_sync(skip_change_eq)]
+    fetched_at: u64,
+}
+
+#[test]
+fn change_eq_ignores_skipped_fields() {
+    let base = Some(Cached {
+        value: 1,
+        fetched_at: 0,
+    });
+    let one = Some(Cached {
+        value: 2,
+        fetched_at: 0,
+    });
+    let two = Some(Cached {
+        value: 1,
+        fetched_at: 5,
+    });
+    let is = UpdateSync::update_sync(base, one, two).unwrap();
+    assert_eq!(is.value, 2);
+}
+
+#[derive(update_sync::derive::UpdateSync, Clone, Debug, PartialEq)]
+struct CachedRecord {
+    value: u32,
+    #[update_sync(skip_change_eq)]
+    fetched_at: u64,
+}
+
+#[test]
+fn update_sync_keeps_new_base_skipped_fields() {
+    let base = CachedRecord {
+        value: 1,
+        fetched_at: 0,
+    };
+    let new_base = CachedRecord {
+        value: 1,
+        fetched_at: 3,
+    };
+    let set = CachedRecord {
+        value: 2,
+        fetched_at: 5,
+    };
+    let is = UpdateSync::update_sync(base, new_base, set);
+    assert_eq!(
+        is,
+        CachedRecord {
+            value: 2,
+            fetched_at: 3,
+        }
+    );
+}
+
+fn same_ignoring_case(a: &str, b: &str) -> bool {
+    a.eq_ignore_ascii_case(b)
+}
+
+#[derive(update_sync::derive::UpdateSync, update_sync::derive::ChangeEq, PartialEq, Debug)]
+enum Tag {
+    Named(#[update_sync(change_eq_with = "same_ignoring_case")] String),
+    Anonymous,
+}
+
+#[test]
+fn change_eq_with() {
+    use update_sync::ChangeEq;
+
+    assert!(Tag::Named("Foo".into()).change_eq(&Tag::Named("FOO".into())));
+    assert!(!Tag::Named("Foo".into()).change_eq(&Tag::Anonymous));
+
+    let base = Tag::Named("foo".into());
+    let one = Tag::Named("bar".into());
+    let two = Tag::Named("FOO".into());
+    let is = UpdateSync::update_sync(base, one, two);
+    assert_eq!(is, Tag::Named("bar".into()));
+}
+
+#[test]
+fn many_submissions() {
+    use update_sync::{
+        multi::{update_sync_many, Author, Conflict, Submission},
+        path::Path,
+    };
+
+    let base = WithFields {
+        foo: 0,
+        bar: 'a',
+        bat: 0,
+    };
+    let new_base = WithFields { foo: 1, ..base };
+    let submissions = vec![
+        Submission::new(3, WithFields { bar: 'c', ..base }),
+        Submission::new(1, WithFields { foo: 2, ..base }),
+        Submission::new(
+            2,
+            WithFields {
+                bar: 'b',
+                bat: 2,
+                ..base
+            },
+        ),
+    ];
+
+    let mut reversed = submissions.clone();
+    reversed.reverse();
+    let one = update_sync_many(base, new_base, submissions);
+    let two = update_sync_many(base, new_base, reversed);
+    assert_eq!(one, two);
+
+    assert_eq!(
+        one.merged,
+        WithFields {
+            foo: 2,
+            bar: 'c',
+            bat: 2,
+        }
+    );
+    assert_eq!(
+        one.conflicts,
+        vec![
+            Conflict {
+                location: Path::from_pointer("/bar").unwrap(),
+                winner: 3,
+                overwritten: vec![Author::Client(2)],
+            },
+            Conflict {
+                location: Path::from_pointer("/foo").unwrap(),
+                winner: 1,
+                overwritten: vec![Author::Server],
+            },
+        ]
+    );
+}
+
+#[test]
+fn hybrid_logical_clock() {
+    use update_sync::clock::{Clock, ManualTimeSource, ReplicaId, Timestamp};
+
+    let time = ManualTimeSource::new(100);
+    let mut clock = Clock::with_source(ReplicaId(1), time.clone());
+    let first = clock.now();
+    assert_eq!(
+        first,
+        Timestamp {
+            wall: 100,
+            logical: 0
+        }
+    );
+
+    // The wall clock going backwards doesn't move the clock backwards
+    time.set(50);
+    let second = clock.now();
+    assert!(second > first);
+
+    // Observing a timestamp from a replica whose clock is ahead moves this clock past it
+    let remote = Timestamp {
+        wall: 200,
+        logical: 3,
+    };
+    assert!(clock.observe(remote) > remote);
+    assert!(clock.now() > remote);
+
+    // Running out of logical time moves on to the next millisecond
+    let remote = Timestamp {
+        wall: 300,
+        logical: u32::MAX,
+    };
+    assert_eq!(
+        clock.observe(remote),
+        Timestamp {
+            wall: 301,
+            logical: 0
+        }
+    );
+    assert_eq!(
+        clock.now(),
+        Timestamp {
+            wall: 301,
+            logical: 1
+        }
+    );
+}
+
+#[test]
+fn last_writer_wins() {
+    use update_sync::{
+        clock::{Clock, ManualTimeSource, ReplicaId},
+        lww::Lww,
+    };
+
+    let time = ManualTimeSource::new(0);
+    let mut server = Clock::with_source(ReplicaId(0), time.clone());
+    let mut client = Clock::with_source(ReplicaId(1), time.clone());
+
+    let base = Lww::new("base", &mut server);
+    let mut early = base.clone();
+    let mut late = base.clone();
+    time.advance(10);
+    early.set("early", &mut client);
+    time.advance(10);
+    late.set("late", &mut server);
+
+    // The latest write is kept no matter which arrives first
+    let is = UpdateSync::update_sync(base.clone(), late.clone(), early.clone());
+    assert_eq!(is.get(), &"late");
+    let is = UpdateSync::update_sync(base, early, late);
+    assert_eq!(is.get(), &"late");
+}
+
+#[derive(Debug, PartialEq)]
+struct Edited {
+    text: String,
+    at: update_sync::clock::Timestamp,
+}
+
+impl update_sync::lww::Stamped for Edited {
+    fn stamp(&self) -> (update_sync::clock::Timestamp, update_sync::clock::ReplicaId) {
+        (self.at, Default::default())
+    }
+}
+
+#[derive(update_sync::derive::UpdateSync, PartialEq, Debug)]
+struct WithLww {
+    #[update_sync(lww)]
+    note: Edited,
+}
+
+#[test]
+fn lww_field() {
+    use update_sync::clock::Timestamp;
+
+    let edited = |text: &str, wall| Edited {
+        text: text.into(),
+        at: Timestamp { wall, logical: 0 },
+    };
+    let base = WithLww {
+        note: edited("base", 0),
+    };
+    let one = WithLww {
+        note: edited("newer", 2),
+    };
+    let two = WithLww {
+        note: edited("older", 1),
+    };
+    let is = UpdateSync::update_sync(base, one, two);
+    assert_eq!(is.note, edited("newer", 2));
+}
+
+#[test]
+fn version_vectors() {
+    use update_sync::{
+        clock::ReplicaId,
+        version::{Causality, Versioned},
+    };
+
+    let (server, one, two) = (ReplicaId(0), ReplicaId(1), ReplicaId(2));
+    let base = Versioned::new(WithFields {
+        foo: 0,
+        bar: 'a',
+        bat: 0,
+    });
+
+    // Client one sets `foo`, which the server applies
+    let mut from_one = base.clone();
+    from_one.edit(one, |value| value.foo = 1);
+    let applied = UpdateSync::update_sync(base.clone(), base.clone(), from_one.clone());
+    assert_eq!(applied, from_one);
+
+    // Client two syncs, and sets `foo` again, having seen client one's change
+    let mut from_two = applied.clone();
+    from_two.edit(two, |value| value.foo = 2);
+    assert_eq!(from_two.version.compare(&applied.version), Causality::After);
+    let applied = UpdateSync::update_sync(applied.clone(), applied, from_two);
+    assert_eq!(applied.value.foo, 2);
+
+    // Client one's request is delivered a second time, and is recognised as stale
+    assert_eq!(
+        from_one.version.compare(&applied.version),
+        Causality::Before
+    );
+    let applied = UpdateSync::update_sync(base.clone(), applied, from_one);
+    assert_eq!(applied.value.foo, 2);
+
+    // Concurrent edits are merged field by field
+    let mut from_server = applied.clone();
+    from_server.edit(server, |value| value.bar = 's');
+    let mut from_one = applied.clone();
+    from_one.edit(one, |value| value.bat = 1);
+    assert_eq!(
+        from_one.version.compare(&from_server.version),
+        Causality::Concurrent
+    );
+    let merged = UpdateSync::update_sync(applied, from_server, from_one);
+    assert_eq!(
+        merged.value,
+        WithFields {
+            foo: 2,
+            bar: 's',
+            bat: 1,
+        }
+    );
+}
+
+#[test]
+fn peer_to_peer() {
+    use update_sync::{
+        clock::ReplicaId,
+        version::{Replica, Versioned},
+    };
+
+    let origin = Versioned::new(WithFields {
+        foo: 0,
+        bar: 'a',
+        bat: 0,
+    });
+    let mut a = Replica::new(ReplicaId(1), origin.clone());
+    let mut b = Replica::new(ReplicaId(2), origin.clone());
+    let mut c = Replica::new(ReplicaId(3), origin);
+
+    a.edit(|value| value.foo = 1);
+    b.edit(|value| value.bar = 'b');
+    c.edit(|value| value.bat = 3);
+    a.sync_with(&mut b);
+    b.sync_with(&mut c);
+    c.sync_with(&mut a);
+    a.sync_with(&mut b);
+
+    let expected = WithFields {
+        foo: 1,
+        bar: 'b',
+        bat: 3,
+    };
+    assert_eq!(a.value(), &expected);
+    assert_eq!(b.value(), &expected);
+    assert_eq!(c.value(), &expected);
+
+    // Later edits continue to sync
+    b.edit(|value| value.foo = 4);
+    a.edit(|value| value.bar = 'a');
+    a.sync_with(&mut b);
+    assert_eq!(a.value(), b.value());
+    assert_eq!(a.value().foo, 4);
+    assert_eq!(a.value().bar, 'a');
+}
+
+/// Checks that joining is commutative and idempotent, returning the joined state
+fn check_join<T: update_sync::crdt::Join + Clone + PartialEq + std::fmt::Debug>(a: T, b: T) -> T {
+    let mut ab = a.clone();
+    ab.join(b.clone());
+    let mut ba = b;
+    ba.join(a);
+    assert_eq!(ab, ba);
+    let mut twice = ab.clone();
+    twice.join(ab.clone());
+    assert_eq!(twice, ab);
+    ab
+}
+
+#[test]
+fn counters() {
+    use update_sync::{
+        clock::ReplicaId,
+        crdt::{GCounter, PnCounter},
+    };
+
+    let mut a = GCounter::new();
+    let mut b = GCounter::new();
+    a.increment(ReplicaId(1), 2);
+    b.increment(ReplicaId(2), 3);
+    assert_eq!(check_join(a, b).value(), 5);
+
+    let mut a = PnCounter::new();
+    let mut b = PnCounter::new();
+    a.increment(ReplicaId(1), 2);
+    b.decrement(ReplicaId(2), 5);
+    assert_eq!(check_join(a, b).value(), -3);
+}
+
+#[test]
+fn or_set_add_wins() {
+    use update_sync::{clock::ReplicaId, crdt::OrSet};
+
+    let mut base = OrSet::new();
+    base.insert(ReplicaId(1), "apple");
+    base.insert(ReplicaId(1), "pear");
+
+    let mut a = base.clone();
+    let mut b = base.clone();
+    a.remove(&"apple");
+    a.remove(&"pear");
+    b.insert(ReplicaId(2), "apple");
+
+    let joined = check_join(a, b);
+    assert_eq!(joined.iter().collect::<Vec<_>>(), vec![&"apple"]);
+
+    // A stale replica doesn't bring back removed values
+    let joined = check_join(joined, base);
+    assert_eq!(joined.iter().collect::<Vec<_>>(), vec![&"apple"]);
+}
+
+#[test]
+fn lww_map() {
+    use update_sync::{
+        clock::{Clock, ManualTimeSource, ReplicaId},
+        crdt::LwwMap,
+    };
+
+    let time = ManualTimeSource::new(0);
+    let mut one = Clock::with_source(ReplicaId(1), time.clone());
+    let mut two = Clock::with_source(ReplicaId(2), time.clone());
+
+    let mut a = LwwMap::new();
+    a.insert("x", 1, &mut one);
+    a.insert("y", 1, &mut one);
+    let mut b = a.clone();
+    time.advance(1);
+    a.remove("x", &mut one);
+    b.insert("y", 2, &mut two);
+    time.advance(1);
+    b.insert("z", 3, &mut two);
+
+    let joined = check_join(a, b);
+    assert_eq!(
+        joined.iter().collect::<Vec<_>>(),
+        vec![(&"y", &2), (&"z", &3)]
+    );
+}
+
+#[test]
+fn mv_register() {
+    use update_sync::{clock::ReplicaId, crdt::MvRegister};
+
+    let mut base = MvRegister::new();
+    base.set(ReplicaId(1), 'a');
+    let mut a = base.clone();
+    let mut b = base;
+    a.set(ReplicaId(1), 'b');
+    b.set(ReplicaId(2), 'c');
+
+    let mut joined = check_join(a, b);
+    assert!(joined.is_conflicted());
+    let mut values: Vec<_> = joined.values().collect();
+    values.sort();
+    assert_eq!(values, vec![&'b', &'c']);
+
+    joined.set(ReplicaId(2), 'd');
+    assert_eq!(joined.values().collect::<Vec<_>>(), vec![&'d']);
+}
+
+#[derive(update_sync::derive::UpdateSync, Clone, PartialEq, Debug)]
+struct WithCrdts {
+    name: String,
+    views: update_sync::crdt::GCounter,
+    tags: update_sync::crdt::OrSet<&'static str>,
+}
+
+#[test]
+fn crdt_fields() {
+    use update_sync::{
+        clock::ReplicaId,
+        crdt::{GCounter, OrSet},
+        visit::Resolution,
+    };
+
+    let base = WithCrdts {
+        name: "base".into(),
+        views: GCounter::new(),
+        tags: OrSet::new(),
+    };
+    let mut one = base.clone();
+    one.views.increment(ReplicaId(1), 1);
+    one.tags.insert(ReplicaId(1), "red");
+    let mut two = base.clone();
+    two.name = "two".into();
+    two.views.increment(ReplicaId(2), 1);
+    two.tags.insert(ReplicaId(2), "blue");
+
+    let preview = update_sync::preview::preview(base.clone(), one.clone(), two.clone());
+    let resolutions: Vec<_> = preview
+        .summary
+        .changes
+        .iter()
+        .map(|change| (change.location.to_string(), change.resolution))
+        .collect();
+    assert_eq!(
+        resolutions,
+        vec![
+            ("name".to_owned(), Resolution::Set),
+            ("views".to_owned(), Resolution::Merged),
+            ("tags".to_owned(), Resolution::Merged),
+        ]
+    );
+
+    let is = UpdateSync::update_sync(base, one, two);
+    assert_eq!(preview.merged, is);
+    assert_eq!(is.name, "two");
+    assert_eq!(is.views.value(), 2);
+    assert_eq!(is.tags.iter().collect::<Vec<_>>(), vec![&"blue", &"red"]);
+}
+
+#[test]
+fn sequence_concurrent_edits() {
+    use update_sync::{
+        clock::ReplicaId,
+        crdt::{Sequence, Text},
+    };
+
+    let (one, two) = (ReplicaId(1), ReplicaId(2));
+    let base = Text::from_text(one, "hello world");
+
+    let mut a = base.clone();
+    let mut b = base.clone();
+    a.set_from(one, "hello, world".chars());
+    b.set_from(two, "hello world!".chars());
+    b.insert(two, 0, '>');
+    a.remove(0);
+
+    let joined = check_join(a, b);
+    assert_eq!(joined.to_string(), ">ello, world!");
+
+    // Concurrent inserts at the same place are never interleaved differently on each replica
+    let base = Sequence::from_vec(one, vec![1, 2]);
+    let mut a = base.clone();
+    let mut b = base;
+    a.insert(one, 1, 10);
+    a.insert(one, 2, 11);
+    b.insert(two, 1, 20);
+    let joined = check_join(a, b);
+    assert_eq!(joined.to_vec(), vec![1, 20, 10, 11, 2]);
+}
+
+#[derive(update_sync::derive::UpdateSync, Clone, PartialEq, Debug)]
+struct Document {
+    title: String,
+    body: update_sync::crdt::Text,
+}
+
+#[test]
+fn sequence_field() {
+    use update_sync::{clock::ReplicaId, crdt::Text};
+
+    let base = Document {
+        title: "Draft".into(),
+        body: Text::from_text(ReplicaId(0), "The quick fox"),
+    };
+    let mut one = base.clone();
+    one.body
+        .set_from(ReplicaId(1), "The quick brown fox".chars());
+    let mut two = base.clone();
+    two.title = "Final".into();
+    two.body
+        .set_from(ReplicaId(2), "The quick fox jumps".chars());
+
+    let is = UpdateSync::update_sync(base, one, two);
+    assert_eq!(is.title, "Final");
+    assert_eq!(is.body.to_string(), "The quick brown fox jumps");
+}
+
+#[test]
+fn ot_operations() {
+    use update_sync::ot::{compose, transform, Operation, OtError};
+
+    let document = "the cat sat";
+    let a = Operation::new().retain(4).delete(3).insert("dog").retain(4);
+    let b = Operation::new().retain(11).insert(" down");
+    assert_eq!(a.apply(document).unwrap(), "the dog sat");
+    assert_eq!(Operation::diff(document, "the dog sat"), a);
+
+    let (a_prime, b_prime) = transform(&a, &b).unwrap();
+    let ab = b_prime.apply(&a.apply(document).unwrap()).unwrap();
+    let ba = a_prime.apply(&b.apply(document).unwrap()).unwrap();
+    assert_eq!(ab, "the dog sat down");
+    assert_eq!(ab, ba);
+    assert_eq!(compose(&a, &b_prime).unwrap().apply(document).unwrap(), ab);
+
+    // Inserts at the same position are ordered the same way on both sides
+    let x = Operation::new().insert("x").retain(11);
+    let y = Operation::new().insert("y").retain(11);
+    let (x_prime, y_prime) = transform(&x, &y).unwrap();
+    assert_eq!(
+        y_prime.apply(&x.apply(document).unwrap()).unwrap(),
+        "xythe cat sat"
+    );
+    assert_eq!(
+        x_prime.apply(&y.apply(document).unwrap()).unwrap(),
+        "xythe cat sat"
+    );
+
+    assert_eq!(
+        a.apply("short"),
+        Err(OtError::LengthMismatch {
+            expected: 11,
+            found: 5
+        })
+    );
+}
+
+#[test]
+fn ot_server() {
+    use update_sync::ot::{transform, Operation, OtServer};
+
+    let mut server = OtServer::new("hello".into());
+    // Both clients edit revision 0
+    let one = Operation::new().retain(5).insert(" world");
+    let two = Operation::new().delete(1).insert("j").retain(4);
+
+    let broadcast_one = server.receive(0, one.clone()).unwrap();
+    let broadcast_two = server.receive(0, two.clone()).unwrap();
+    assert_eq!(server.document(), "jello world");
+    assert_eq!(server.revision(), 2);
+
+    // Client one receives two's rebased operation directly
+    let client_one = broadcast_two.apply(&one.apply("hello").unwrap()).unwrap();
+    assert_eq!(client_one, server.document());
+
+    // Client two rebases the operation it missed over its own pending one
+    let (_, missed) = transform(&two, &broadcast_one).unwrap();
+    let client_two = missed.apply(&two.apply("hello").unwrap()).unwrap();
+    assert_eq!(client_two, server.document());
+
+    assert!(server.history_since(3).is_err());
+}
+
+#[derive(update_sync::derive::UpdateSync, Clone, PartialEq, Debug)]
+struct Note {
+    #[update_sync(with = "update_sync::ot::update_sync_text")]
+    body: String,
+    author: String,
+}
+
+#[test]
+fn ot_text_field() {
+    use update_sync::visit::{Leaf, Resolution, Segment, Visitor};
+
+    let base = Note {
+        body: "Meet at noon".into(),
+        author: "amy".into(),
+    };
+    let mut server = base.clone();
+    server.body = "Meet at noon on Friday".into();
+    let mut client = base.clone();
+    client.body = "Let's meet at noon".into();
+    client.author = "bo".into();
+
+    let is = UpdateSync::update_sync(base.clone(), server.clone(), client.clone());
+    assert_eq!(is.body, "Let's meet at noon on Friday");
+    assert_eq!(is.author, "bo");
+
+    #[derive(Default)]
+    struct Resolutions(Vec<Resolution>);
+    impl Visitor for Resolutions {
+        fn enter(&mut self, _: Segment<'_>) {}
+        fn leave(&mut self) {}
+        fn leaf(&mut self, leaf: Leaf<'_>) {
+            self.0.push(leaf.resolution);
+        }
+    }
+    let mut resolutions = Resolutions::default();
+    let visited = UpdateSyncVisit::update_sync_visit(base, server, client, &mut resolutions);
+    assert_eq!(visited, is);
+    assert_eq!(resolutions.0, vec![Resolution::Merged, Resolution::Set]);
+}
+
+#[derive(
+    update_sync::derive::UpdateSync, update_sync::derive::ChangeEq, Clone, PartialEq, Debug,
+)]
+struct LineItem {
+    id: u32,
+    name: String,
+    quantity: u32,
+}
+
+impl update_sync::keyed::Keyed for LineItem {
+    type Key = u32;
+
+    fn key(&self) -> u32 {
+        self.id
+    }
+}
+
+fn line_item(id: u32, name: &str, quantity: u32) -> LineItem {
+    LineItem {
+        id,
+        name: name.into(),
+        quantity,
+    }
+}
+
+#[test]
+fn keyed_vec() {
+    use update_sync::keyed::KeyedVec;
+
+    let base = KeyedVec(vec![
+        line_item(1, "apples", 2),
+        line_item(2, "pears", 1),
+        line_item(3, "plums", 6),
+    ]);
+    // The server reorders the list, renames an item, and adds one at the end
+    let server = KeyedVec(vec![
+        line_item(3, "plums", 6),
+        line_item(1, "green apples", 2),
+        line_item(2, "pears", 1),
+        line_item(4, "figs", 3),
+    ]);
+    // The client changes a quantity, removes an item, and adds one after the apples
+    let client = KeyedVec(vec![
+        line_item(1, "apples", 5),
+        line_item(5, "grapes", 1),
+        line_item(3, "plums", 6),
+    ]);
+
+    let is = UpdateSync::update_sync(base, server, client);
+    assert_eq!(
+        is.0,
+        vec![
+            line_item(3, "plums", 6),
+            line_item(1, "green apples", 5),
+            line_item(5, "grapes", 1),
+            line_item(4, "figs", 3),
+        ]
+    );
+}
+
+#[test]
+fn keyed_records_sharing_keys() {
+    use update_sync::keyed::update_sync_keyed;
+
+    // Records without `Debug` can be updated, and records sharing a key are matched up in order
+    let tens = |secret: &Secret| secret.0 / 10;
+    let is = update_sync_keyed(
+        vec![Secret(11), Secret(12)],
+        vec![Secret(11), Secret(12), Secret(20)],
+        vec![Secret(11), Secret(13)],
+        tens,
+    );
+    assert!(is == vec![Secret(11), Secret(13), Secret(20)]);
+}
+
+#[derive(update_sync::derive::UpdateSync, Clone, PartialEq, Debug)]
+struct Order {
+    #[update_sync(key = "id")]
+    items: Vec<LineItem>,
+}
+
+#[test]
+fn keyed_field() {
+    let base = Order {
+        items: vec![line_item(1, "apples", 2), line_item(2, "pears", 1)],
+    };
+    // The client reorders, the server edits
+    let server = Order {
+        items: vec![line_item(1, "apples", 2), line_item(2, "pears", 4)],
+    };
+    let client = Order {
+        items: vec![line_item(2, "pears", 1), line_item(1, "apples", 2)],
+    };
+
+    let is = UpdateSync::update_sync(base.clone(), server.clone(), client);
+    assert_eq!(
+        is.items,
+        vec![line_item(2, "pears", 4), line_item(1, "apples", 2)]
+    );
+
+    // Removing an item the other side changed keeps the change, as maps do
+    let client = Order {
+        items: vec![line_item(1, "apples", 2)],
+    };
+    let merged = update_sync::multi::update_sync_many(
+        base.clone(),
+        server,
+        vec![update_sync::multi::Submission::new(1, client.clone())],
+    );
+    assert_eq!(
+        merged.merged.items,
+        vec![line_item(1, "apples", 2), line_item(2, "pears", 4)]
+    );
+    assert!(merged.conflicts.is_empty());
+    let map = |order: &Order| -> BTreeMap<u32, LineItem> {
+        order
+            .items
+            .iter()
+            .map(|item| (item.id, item.clone()))
+            .collect()
+    };
+    let server = Order {
+        items: vec![line_item(1, "apples", 2), line_item(2, "pears", 4)],
+    };
+    assert_eq!(
+        UpdateSync::update_sync(map(&base), map(&server), map(&client)),
+        map(&merged.merged)
+    );
+
+    // While removing an unchanged item removes it
+    let is = UpdateSync::update_sync(base.clone(), base, client.clone());
+    assert_eq!(is, client);
+}
+
+#[test]
+fn fractional_positions() {
+    use update_sync::ordered::Position;
+
+    let mut positions = vec![Position::between(None, None)];
+    // Repeatedly inserting at the same place still finds a position between its neighbours
+    for _ in 0..100 {
+        let first = Position::between(None, Some(&positions[0]));
+        let second = Position::between(Some(&first), Some(&positions[0]));
+        positions.insert(0, second);
+        positions.insert(0, first);
+        let last = Position::between(positions.last(), None);
+        positions.push(last);
+    }
+    assert!(positions.windows(2).all(|pair| pair[0] < pair[1]));
+    assert!(positions.iter().all(|p| !p.as_str().ends_with('0')));
+}
+
+#[test]
+fn ordered_list() {
+    use update_sync::ordered::OrderedList;
+
+    let base: OrderedList<char, u32> = vec![('a', 1), ('b', 2), ('c', 3), ('d', 4)]
+        .into_iter()
+        .collect();
+
+    // The server drags `d` to the front and edits `b`
+    let mut server = base.clone();
+    server.move_to(&'d', 0);
+    *server.get_mut(&'b').unwrap() = 20;
+    // The client drags `a` to the end, inserts `e` after `b`, and removes `c`
+    let mut client = base.clone();
+    client.move_to(&'a', 3);
+    client.insert(1, 'e', 5);
+    client.remove(&'c');
+
+    let is = UpdateSync::update_sync(base, server, client);
+    assert_eq!(
+        is.iter().map(|(k, v)| (*k, *v)).collect::<Vec<_>>(),
+        vec![('d', 4), ('b', 20), ('e', 5), ('a', 1)]
+    );
+    assert_eq!(is.index_of(&'a'), Some(3));
+    assert_eq!(is.get(&'c'), None);
+}
+
+#[test]
+fn ordered_list_move_of_removed() {
+    use update_sync::ordered::OrderedList;
+
+    let base: OrderedList<char, u32> = vec![('a', 1), ('b', 2), ('c', 3)].into_iter().collect();
+    let mut removed = base.clone();
+    removed.remove(&'b');
+    let mut moved = base.clone();
+    moved.move_to(&'b', 0);
+
+    // Whichever side moved `b`, the other removed it, so it is left out of the list
+    for (server, client) in [(removed.clone(), moved.clone()), (moved, removed)] {
+        let is = UpdateSync::update_sync(base.clone(), server, client);
+        assert_eq!(
+            is.iter().map(|(k, v)| (*k, *v)).collect::<Vec<_>>(),
+            vec![('a', 1), ('c', 3)]
+        );
+        assert!(!is.contains_key(&'b'));
+        assert_eq!(is.len(), 2);
+    }
+}
+
+#[derive(
+    update_sync::derive::UpdateSync, update_sync::derive::ChangeEq, Clone, PartialEq, Debug,
+)]
+struct Config {
+    id: u32,
+    port: u16,
+}
+
+#[test]
+fn renamed_map_keys() {
+    use update_sync::rename::{update_sync_renamed, update_sync_renamed_by};
+
+    let config = |id, port| Config { id, port };
+    let base: BTreeMap<String, Config> = vec![("db".into(), config(1, 5432))].into_iter().collect();
+
+    // The server changes the port while the client renames the key
+    let mut server = base.clone();
+    server.get_mut("db").unwrap().port = 5433;
+    let mut client = base.clone();
+    let renamed = client.remove("db").unwrap();
+    client.insert("database".into(), renamed);
+
+    let plain = UpdateSync::update_sync(base.clone(), server.clone(), client.clone());
+    assert_eq!(plain.len(), 2);
+    let is = update_sync_renamed(base.clone(), server.clone(), client);
+    assert_eq!(
+        is.into_iter().collect::<Vec<_>>(),
+        vec![("database".into(), config(1, 5433))]
+    );
+
+    // With an identity function, values changed as they are renamed are found too
+    let mut client = base.clone();
+    client.remove("db");
+    client.insert("database".into(), config(1, 6000));
+    let is = update_sync_renamed_by(base, server, client, |config| config.id);
+    assert_eq!(
+        is.into_iter().collect::<Vec<_>>(),
+        vec![("database".into(), config(1, 6000))]
+    );
+}
+
+#[test]
+fn tombstone_map() {
+    use update_sync::tombstone::TombstoneMap;
+
+    let mut base = TombstoneMap::new();
+    base.insert("a", 1);
+    base.insert("b", 2);
+
+    // One client removes `a`
+    let mut remover = base.clone();
+    remover.remove(&"a");
+    let mut server = UpdateSync::update_sync(base.clone(), base.clone(), remover);
+    assert_eq!(server.revision(), 1);
+    assert_eq!(server.tombstones().collect::<Vec<_>>(), vec![(&"a", 1)]);
+
+    // A client which edited its copy from before the removal can't bring `a` back
+    let mut stale = base.clone();
+    *stale.get_mut(&"a").unwrap() = 10;
+    *stale.get_mut(&"b").unwrap() = 20;
+    let is = UpdateSync::update_sync(base.clone(), server.clone(), stale.clone());
+    assert_eq!(is.get(&"a"), None);
+    assert_eq!(is.get(&"b"), Some(&20));
+
+    // A client which saw the removal can add it again
+    let mut fresh = server.clone();
+    fresh.insert("a", 100);
+    let is = UpdateSync::update_sync(server.clone(), server.clone(), fresh);
+    assert_eq!(is.get(&"a"), Some(&100));
+
+    // Once its tombstone is collected, entries from before the horizon are refused outright
+    server.collect_garbage(1);
+    assert_eq!(server.tombstones().count(), 0);
+    let is = UpdateSync::update_sync(base, server.clone(), stale);
+    assert_eq!(is.get(&"a"), None);
+    let mut fresh = server.clone();
+    fresh.insert("c", 3);
+    let is = UpdateSync::update_sync(server.clone(), server, fresh);
+    assert_eq!(is.get(&"c"), Some(&3));
+
+    // Maps with a retention collect their own tombstones as they are updated
+    let mut base = TombstoneMap::with_retention(1);
+    base.insert("a", 1);
+    let mut removed = base.clone();
+    removed.remove(&"a");
+    let server = UpdateSync::update_sync(base.clone(), base, removed);
+    let mut edit = server.clone();
+    edit.insert("b", 2);
+    let server = UpdateSync::update_sync(server.clone(), server, edit);
+    assert_eq!(server.revision(), 2);
+    assert_eq!(server.horizon(), 1);
+    assert_eq!(server.tombstones().count(), 0);
+}
+
+#[test]
+fn undo_redo() {
+    use update_sync::undo::History;
+
+    let base = WithFields {
+        foo: 1,
+        bar: 'a',
+        bat: 1,
+    };
+    let mut history = History::new();
+
+    // This user changes `foo`, while another user changes `bar`
+    let mut set = base;
+    set.foo = 2;
+    let mut other = base;
+    other.bar = 'b';
+    let current = history.apply(base, other, set);
+    assert_eq!((current.foo, current.bar), (2, 'b'));
+
+    // Then the other user changes `bat`
+    let mut current = current;
+    current.bat = 3;
+
+    // Undoing only puts back the value this user overwrote
+    let undone = history.undo(current).unwrap();
+    assert_eq!(
+        undone,
+        WithFields {
+            foo: 1,
+            bar: 'b',
+            bat: 3,
+        }
+    );
+    assert!(!history.can_undo());
+
+    let redone = history.redo(undone).unwrap();
+    assert_eq!(redone, current);
+    assert!(history.can_undo());
+    assert!(history.redo(redone).is_none());
+}
+
+#[derive(update_sync::derive::UpdateSync, Clone, PartialEq, Debug)]
+struct Team {
+    name: String,
+    scores: BTreeMap<String, u32>,
+}
+
+#[test]
+fn merge_preview() {
+    use update_sync::{
+        path::Path,
+        preview::{preview, FieldChange},
+        visit::Resolution,
+    };
+
+    let base = Team {
+        name: "Reds".into(),
+        scores: vec![("amy".into(), 1), ("bo".into(), 2)]
+            .into_iter()
+            .collect(),
+    };
+    let mut server = base.clone();
+    server.name = "Blues".into();
+    server.scores.insert("bo".into(), 3);
+    let mut client = base.clone();
+    client.name = "Greens".into();
+    client.scores.insert("amy".into(), 5);
+    client.scores.insert("cy".into(), 1);
+
+    let preview = preview(base.clone(), server.clone(), client.clone());
+    assert_eq!(
+        preview.merged,
+        UpdateSync::update_sync(base, server, client)
+    );
+
+    let resolutions: Vec<_> = preview
+        .summary
+        .changes
+        .iter()
+        .map(|change| (change.location.to_string(), change.resolution))
+        .collect();
+    assert_eq!(
+        resolutions,
+        vec![
+            ("name".to_owned(), Resolution::Conflict),
+            ("scores[\"amy\"]".to_owned(), Resolution::Set),
+            ("scores[\"bo\"]".to_owned(), Resolution::NewBase),
+            ("scores[\"cy\"]".to_owned(), Resolution::Set),
+        ]
+    );
+    assert_eq!(preview.summary.changes_to_new_base().count(), 3);
+    assert_eq!(
+        preview.summary.to_string(),
+        "name: \"Blues\" -> \"Greens\" (overwriting its change from \"Reds\")\n\
+         scores[\"amy\"]: 1 -> 5\n\
+         scores[\"bo\"]: 3 (already changed from 2)\n\
+         scores[\"cy\"]: (none) -> 1\n"
+    );
+    assert!(preview.summary.to_json().starts_with(
+        "{\"changes\":[{\"location\":\"/name\",\"resolution\":\"Conflict\",\"winner\":\"set\",\
+         \"last_base\":\"\\\"Reds\\\"\","
+    ));
+
+    // Summaries only hold changes, but a change built by hand can still be unchanged
+    let unchanged = FieldChange {
+        location: Path::from_pointer("/name").unwrap(),
+        resolution: Resolution::Unchanged,
+        last_base: Some("\"Reds\"".into()),
+        new_base: Some("\"Reds\"".into()),
+        set: Some("\"Reds\"".into()),
+    };
+    assert_eq!(unchanged.to_string(), "name: \"Reds\" (unchanged)");
+}
+
+#[test]
+fn paths() {
+    use update_sync::{
+        path::{Path, PathSegment, PointerError},
+        visit::Segment,
+    };
+
+    let mut path = Path::new();
+    path.push(Segment::Field("users"));
+    path.push(Segment::Key(&"a/b~c"));
+    path.push(Segment::Index(2));
+    path.push(Segment::Variant("Named"));
+    assert_eq!(path.to_string(), "users[\"a/b~c\"].2.Named");
+    assert_eq!(path.to_pointer(), "/users/a~1b~0c/2/Named");
+
+    let parsed = Path::from_pointer("/users/a~1b~0c/2/Named").unwrap();
+    assert_eq!(parsed, path);
+    assert_eq!(parsed.segments()[2], PathSegment::Index(2));
+    assert!(path.starts_with(&Path::from_pointer("/users").unwrap()));
+    assert!(!path.starts_with(&Path::from_pointer("/user").unwrap()));
+
+    assert_eq!(Path::from_pointer(""), Ok(Path::new()));
+    assert_eq!(Path::from_pointer("users"), Err(PointerError::MissingSlash));
+    assert_eq!(Path::from_pointer("/a~2"), Err(PointerError::InvalidEscape));
+    // Indices are ordered numerically
+    assert!(Path::from_pointer("/2").unwrap() < Path::from_pointer("/10").unwrap());
+}
+
+#[test]
+fn json_values() {
+    use serde_json::json;
+    use update_sync::json::{merge, update_sync_json, ArrayStrategy, JsonOptions};
+
+    let base = json!({
+        "title": "Draft",
+        "meta": { "tags": ["a"], "views": 1 },
+        "items": [{ "id": 1, "qty": 1 }, { "id": 2, "qty": 1 }],
+    });
+    let server = json!({
+        "title": "Final",
+        "meta": { "tags": ["a"], "views": 2 },
+        "items": [{ "id": 1, "qty": 5 }, { "id": 2, "qty": 1 }],
+    });
+    let client = json!({
+        "title": "Published",
+        "meta": { "tags": ["a", "b"], "views": 1, "pinned": true },
+        "items": [{ "id": 2, "qty": 3 }, { "id": 1, "qty": 1 }],
+    });
+
+    // Arrays are replaced as a whole by default
+    let is = UpdateSync::update_sync(base.clone(), server.clone(), client.clone());
+    assert_eq!(
+        is,
+        json!({
+            "title": "Published",
+            "meta": { "tags": ["a", "b"], "views": 2, "pinned": true },
+            "items": [{ "id": 2, "qty": 3 }, { "id": 1, "qty": 1 }],
+        })
+    );
+
+    let keyed = JsonOptions {
+        arrays: ArrayStrategy::Keyed("id".into()),
+    };
+    let merged = merge(base.clone(), server.clone(), client.clone(), &keyed);
+    assert_eq!(
+        merged.merged["items"],
+        json!([{ "id": 2, "qty": 3 }, { "id": 1, "qty": 5 }])
+    );
+    assert_eq!(merged.conflicts, vec!["/title"]);
+
+    // Conflicts inside elements are located by the element's key
+    let merged = merge(
+        json!({ "t": [{ "id": "a", "v": 1 }, { "id": 2, "v": 1 }] }),
+        json!({ "t": [{ "id": "a", "v": 2 }, { "id": 2, "v": 2 }] }),
+        json!({ "t": [{ "id": "a", "v": 3 }, { "id": 2, "v": 3 }] }),
+        &keyed,
+    );
+    assert_eq!(merged.conflicts, vec!["/t/a/v", "/t/2/v"]);
+
+    // Elements sharing a key, or without one, are matched up in order rather than collapsed
+    let is = update_sync_json(
+        json!([{ "id": 1, "qty": 1 }, { "id": 1, "qty": 2 }, "x", "x"]),
+        json!([{ "id": 1, "qty": 1 }, { "id": 1, "qty": 2 }, "x", "x", "x"]),
+        json!([{ "id": 1, "qty": 1 }, { "id": 1, "qty": 4 }, "x", "x"]),
+        &keyed,
+    );
+    assert_eq!(
+        is,
+        json!([{ "id": 1, "qty": 1 }, { "id": 1, "qty": 4 }, "x", "x", "x"])
+    );
+
+    let by_index = JsonOptions {
+        arrays: ArrayStrategy::ByIndex,
+    };
+    let is = update_sync_json(
+        json!([1, { "a": 1 }, 3]),
+        json!([2, { "a": 1 }, 3]),
+        json!([1, { "a": 2 }, 4]),
+        &by_index,
+    );
+    assert_eq!(is, json!([2, { "a": 2 }, 4]));
+}
+
+#[derive(
+    update_sync::derive::UpdateSync, serde::Serialize, serde::Deserialize, Clone, PartialEq, Debug,
+)]
+struct Profile {
+    name: String,
+    email: Option<String>,
+    roles: BTreeMap<String, String>,
+}
+
+#[test]
+fn json_patches() {
+    use serde_json::json;
+    use update_sync::json_patch::{
+        apply_merge_patch, json_patch, merge_patch, update_sync_json_patch, JsonPatch, PatchError,
+    };
+
+    let new_base = Profile {
+        name: "Bob".into(),
+        email: Some("bob@example.com".into()),
+        roles: vec![("admin".into(), "all".into())].into_iter().collect(),
+    };
+    let merged = Profile {
+        name: "Robert".into(),
+        email: None,
+        roles: vec![
+            ("admin".into(), "all".into()),
+            ("ops".into(), "deploys".into()),
+        ]
+        .into_iter()
+        .collect(),
+    };
+
+    let patch = json_patch(&new_base, &merged).unwrap();
+    assert_eq!(
+        patch.to_value(),
+        json!([
+            { "op": "replace", "path": "/email", "value": null },
+            { "op": "replace", "path": "/name", "value": "Robert" },
+            { "op": "add", "path": "/roles/ops", "value": "deploys" },
+        ])
+    );
+    let mut patched = serde_json::to_value(&new_base).unwrap();
+    JsonPatch::from_value(&patch.to_value())
+        .unwrap()
+        .apply(&mut patched)
+        .unwrap();
+    assert_eq!(patched, serde_json::to_value(&merged).unwrap());
+
+    let patch = merge_patch(&new_base, &merged).unwrap();
+    assert_eq!(
+        patch,
+        json!({ "email": null, "name": "Robert", "roles": { "ops": "deploys" } })
+    );
+    let mut patched = serde_json::to_value(&new_base).unwrap();
+    apply_merge_patch(&mut patched, &patch);
+    assert_eq!(
+        patched,
+        json!({ "name": "Robert", "roles": { "admin": "all", "ops": "deploys" } })
+    );
+
+    // The client sends its changes since `last_base` as a patch
+    let last_base = Profile {
+        name: "Bob".into(),
+        email: None,
+        roles: BTreeMap::new(),
+    };
+    let client = JsonPatch::from_value(&json!([
+        { "op": "test", "path": "/name", "value": "Bob" },
+        { "op": "add", "path": "/roles/support", "value": "tickets" },
+    ]))
+    .unwrap();
+    let is = update_sync_json_patch(last_base.clone(), new_base.clone(), &client).unwrap();
+    assert_eq!(
+        is,
+        Profile {
+            name: "Bob".into(),
+            email: Some("bob@example.com".into()),
+            roles: vec![
+                ("admin".into(), "all".into()),
+                ("support".into(), "tickets".into()),
+            ]
+            .into_iter()
+            .collect(),
+        }
+    );
+
+    let failing = JsonPatch::from_value(&json!([
+        { "op": "test", "path": "/name", "value": "Alice" },
+    ]))
+    .unwrap();
+    assert!(std::matches!(
+        update_sync_json_patch(last_base.clone(), new_base.clone(), &failing),
+        Err(PatchError::TestFailed(pointer)) if pointer == "/name"
+    ));
+    assert!(std::matches!(
+        JsonPatch::from_value(&json!([{ "op": "frobnicate", "path": "" }])),
+        Err(PatchError::InvalidOperation(0))
+    ));
+
+    // Array indices have no signs or leading zeros
+    for pointer in &["/1", "/01", "/+1"] {
+        let mut array = json!(["a", "b"]);
+        let patch = JsonPatch::from_value(&json!([{ "op": "remove", "path": pointer }])).unwrap();
+        let applied = patch.apply(&mut array);
+        if *pointer == "/1" {
+            assert!(applied.is_ok());
+            assert_eq!(array, json!(["a"]));
+        } else {
+            assert!(std::matches!(applied, Err(PatchError::NotFound(_))));
+            assert_eq!(array, json!(["a", "b"]));
+        }
+        let patch = JsonPatch::from_value(&json!([{ "op": "add", "path": pointer, "value": "c" }]))
+            .unwrap();
+        assert_eq!(
+            patch.apply(&mut json!(["a", "b"])).is_ok(),
+            *pointer == "/1"
+        );
+    }
+}
+
+#[test]
+fn toml_documents() {
+    use toml_edit::DocumentMut;
+    use update_sync::toml::merge;
+
+    let base = r#"# Service configuration
+name = "api"
+port = 80
+
+[database]
+host = "db.internal" # primary
+pool = 4
+"#;
+    let server = r#"# Service configuration
+name = "api"
+port = 8080
+
+[database]
+host = "db.internal" # primary
+pool = 4
+"#;
+    let client = r#"name = "api"
+port = 80
+
+[database]
+host =   "db2.internal"
+pool = 8
+
+[logging]
+level = "debug"
+"#;
+    let parse = |text: &str| text.parse::<DocumentMut>().unwrap();
+
+    let merged = merge(parse(base), parse(server), parse(client));
+    assert_eq!(
+        merged.merged.to_string(),
+        r#"# Service configuration
+name = "api"
+port = 8080
+
+[database]
+host = "db2.internal" # primary
+pool = 8
+
+[logging]
+level = "debug"
+"#
+    );
+    assert!(merged.conflicts.is_empty());
+
+    // Reformatting isn't a change, so the server's formatting is kept
+    let reformatted = "name = 'api'\nport = 80\ndatabase = { host = \"db.internal\", pool = 4 }\n";
+    let is = UpdateSync::update_sync(parse(base), parse(server), parse(reformatted));
+    assert_eq!(is.to_string(), server);
+
+    let other = server.replace("8080", "9090");
+    let merged = merge(parse(base), parse(server), parse(&other));
+    assert_eq!(merged.conflicts, vec!["/port"]);
+    assert_eq!(merged.merged.to_string(), other);
+}
+
+#[derive(
+    update_sync::derive::UpdateSync,
+    update_sync::derive::ChangeEq,
+    update_sync::derive::SyncHash,
+    Clone,
+    PartialEq,
+    Debug,
+)]
+enum Shape {
+    Circle { radius: u32 },
+    Square(u32),
+    Empty,
+}
+
+#[derive(
+    update_sync::derive::UpdateSync, update_sync::derive::SyncHash, Clone, PartialEq, Debug,
+)]
+struct Drawing {
+    title: String,
+    shapes: BTreeMap<String, Shape>,
+    layers: Vec<u8>,
+}
+
+#[test]
+fn sync_hashes() {
+    use update_sync::hash::{update_sync_hashed, SyncHash};
+    use update_sync::path::Path;
+
+    let drawing = Drawing {
+        title: "Plan".into(),
+        shapes: vec![
+            ("a".into(), Shape::Circle { radius: 1 }),
+            ("b".into(), Shape::Square(2)),
+            ("c".into(), Shape::Empty),
+        ]
+        .into_iter()
+        .collect(),
+        layers: vec![1, 2],
+    };
+    assert_eq!(drawing.sync_hash(), drawing.clone().sync_hash());
+    assert_eq!(drawing.sync_hash(), drawing.hash_tree().hash());
+    // Hashes are the same on every platform and in every process
+    assert_eq!(1u32.sync_hash(), 0xad2a_ca77_4798_5764);
+
+    let mut edited = drawing.clone();
+    edited
+        .shapes
+        .insert("a".into(), Shape::Circle { radius: 5 });
+    edited.shapes.remove("c");
+    edited.layers.push(3);
+    assert_ne!(drawing.sync_hash(), edited.sync_hash());
+    assert_eq!(edited.sync_hash(), edited.hash_tree().hash());
+
+    let paths = |pointers: &[&str]| -> Vec<Path> {
+        pointers
+            .iter()
+            .map(|pointer| Path::from_pointer(pointer).unwrap())
+            .collect()
+    };
+    let (tree, edited_tree) = (drawing.hash_tree(), edited.hash_tree());
+    assert_eq!(
+        tree.changed(&edited_tree),
+        paths(&["/shapes/a/Circle/radius", "/shapes/c", "/layers/2"])
+    );
+    // Exchanging only the top levels finds the changed fields
+    assert_eq!(
+        tree.truncated(1).changed(&edited_tree),
+        paths(&["/shapes", "/layers"])
+    );
+    assert_eq!(
+        tree.get(&Path::from_pointer("/shapes/b").unwrap()),
+        edited_tree.get(&Path::from_pointer("/shapes/b").unwrap())
+    );
+
+    let is = update_sync_hashed(drawing.clone(), edited.clone(), drawing.clone());
+    assert_eq!(is, edited);
+    let mut retitled = drawing.clone();
+    retitled.title = "Final".into();
+    let is = update_sync_hashed(drawing, edited.clone(), retitled);
+    assert_eq!(is.title, "Final");
+    assert_eq!(is.shapes, edited.shapes);
+}
+
+#[test]
+fn sync_hashes_of_std_types() {
+    use std::{
+        net::{IpAddr, Ipv4Addr},
+        num::Wrapping,
+        path::PathBuf,
+        time::Duration,
+    };
+    use update_sync::{
+        hash::{update_sync_hashed, SyncHash},
+        path::Path,
+    };
+
+    type Settings = (Duration, IpAddr, PathBuf, Result<Wrapping<u8>, String>);
+    let base: Settings = (
+        Duration::from_secs(1),
+        Ipv4Addr::LOCALHOST.into(),
+        "a/b".into(),
+        Ok(Wrapping(1)),
+    );
+    assert_eq!(base.sync_hash(), base.hash_tree().hash());
+    assert_ne!(Ok::<u8, u8>(1).sync_hash(), Err::<u8, u8>(1).sync_hash());
+
+    let mut new_base = base.clone();
+    new_base.0 = Duration::from_millis(1500);
+    let mut set = base.clone();
+    set.3 = Err("gone".into());
+    // Changing variant changes the child each side has
+    assert_eq!(
+        base.hash_tree().changed(&set.hash_tree()),
+        vec![
+            Path::from_pointer("/3/Ok").unwrap(),
+            Path::from_pointer("/3/Err").unwrap()
+        ]
+    );
+    let is = update_sync_hashed(base, new_base.clone(), set);
+    assert_eq!(is.0, new_base.0);
+    assert_eq!(is.3, Err("gone".into()));
+}
+
+#[derive(
+    update_sync::derive::UpdateSync, update_sync::derive::SyncHash, Clone, PartialEq, Debug,
+)]
+struct Reading {
+    value: f64,
+    scale: f32,
+}
+
+#[test]
+fn sync_hashes_of_floats() {
+    use update_sync::hash::{update_sync_hashed, SyncHash};
+
+    // Floats which are `ChangeEq` hash the same
+    assert_eq!(0.0f64.sync_hash(), (-0.0f64).sync_hash());
+    assert_eq!(f64::NAN.sync_hash(), (-f64::NAN).sync_hash());
+    assert_eq!(
+        f32::NAN.sync_hash(),
+        f32::from_bits(f32::NAN.to_bits() | 1).sync_hash()
+    );
+    assert_ne!(1.0f64.sync_hash(), (-1.0f64).sync_hash());
+
+    // So a `set` which only flipped a zero's sign or a `NaN`'s bits counts as unchanged
+    let last_base = Reading {
+        value: 0.0,
+        scale: f32::NAN,
+    };
+    let set = Reading {
+        value: -0.0,
+        scale: -f32::NAN,
+    };
+    let mut new_base = last_base.clone();
+    new_base.value = 2.0;
+    let is = update_sync_hashed(last_base.clone(), new_base.clone(), set.clone());
+    // `NaN` isn't `PartialEq` to itself, so the results are compared by how they print
+    assert_eq!(
+        format!("{:?}", is),
+        format!(
+            "{:?}",
+            UpdateSync::update_sync(last_base.clone(), new_base, set.clone())
+        )
+    );
+    assert_eq!(is.value, 2.0);
+    let is = update_sync_hashed(last_base.clone(), last_base.clone(), set);
+    assert!(is.value.is_sign_positive());
+    assert_eq!(is.scale.to_bits(), f32::NAN.to_bits());
+}
+
+#[test]
+fn range_reconciliation() {
+    use update_sync::reconcile::{
+        apply_entries, changed_entries, update_sync_entries, InProcess, Reconciler,
+    };
+
+    let last_base: BTreeMap<u32, String> = (0..100_000).map(|i| (i, i.to_string())).collect();
+    let mut new_base = last_base.clone();
+    new_base.insert(10, "server".into());
+    new_base.insert(50_000, "server".into());
+    new_base.remove(&70_000);
+    let mut set = last_base.clone();
+    set.insert(20, "client".into());
+    set.insert(50_000, "client".into());
+    set.insert(200_000, "added".into());
+    set.remove(&90_000);
+
+    // Find the differing keys without sending either map
+    let mut client = Reconciler::new(&set);
+    let mut server = InProcess::new(Reconciler::new(&new_base));
+    let round_trips = client.run(&mut server);
+    assert!(round_trips <= 6, "took {} round trips", round_trips);
+    let keys = client.into_differing();
+    assert_eq!(&keys, server.remote.differing());
+    assert_eq!(
+        keys.iter().copied().collect::<Vec<_>>(),
+        vec![10, 20, 50_000, 70_000, 90_000, 200_000]
+    );
+
+    // Update only those entries, as updating the whole map would
+    let expected = BTreeMap::update_sync(last_base.clone(), new_base.clone(), set.clone());
+    let changes = changed_entries(&last_base, &set, &keys);
+    let (merged, reply) = update_sync_entries(new_base, changes);
+    assert_eq!(merged, expected);
+    apply_entries(&mut set, reply);
+    assert_eq!(set, expected);
+
+    // Identical maps are reconciled in a single round trip
+    let mut client = Reconciler::new(&last_base)
+        .with_threshold(4)
+        .with_branching(4);
+    let mut server = InProcess::new(Reconciler::new(&last_base));
+    assert_eq!(client.run(&mut server), 1);
+    assert!(client.differing().is_empty());
+}
+
+#[derive(
+    update_sync::derive::UpdateSync,
+    update_sync::derive::Track,
+    update_sync::derive::Patch,
+    Clone,
+    PartialEq,
+    Debug,
+)]
+struct Account {
+    name: String,
+    email: String,
+    tags: BTreeMap<String, bool>,
+}
+
+#[test]
+fn tracked_fields() {
+    use update_sync::track::Tracked;
+
+    let synced = Account {
+        name: "Bob".into(),
+        email: "bob@example.com".into(),
+        tags: BTreeMap::new(),
+    };
+    let mut account = Tracked::new(synced.clone());
+    assert!(!account.is_changed());
+
+    account.set_name("Robert".into());
+    account.set_name("Rob".into());
+    account.tags_mut().insert("admin".into(), true);
+    assert_eq!(account.name, "Rob");
+    assert_eq!(account.changed_fields(), vec!["name", "tags"]);
+
+    // The original values are kept, so `last_base` is the value as it was synced
+    assert_eq!(account.last_base(), synced);
+    // Only the changed fields are sent
+    let changes = account.changes().unwrap();
+    assert_eq!(
+        changes,
+        AccountPatch {
+            name: Some("Rob".into()),
+            email: None,
+            tags: Some(vec![("admin".into(), true)].into_iter().collect()),
+        }
+    );
+
+    let mut new_base = synced.clone();
+    new_base.email = "robert@example.com".into();
+    let merged = changes.apply_to(new_base.clone());
+    assert_eq!(
+        merged,
+        UpdateSync::update_sync(account.last_base(), new_base, account.get().clone())
+    );
+    assert_eq!(
+        merged,
+        Account {
+            name: "Rob".into(),
+            email: "robert@example.com".into(),
+            tags: vec![("admin".into(), true)].into_iter().collect(),
+        }
+    );
+    account.synced(merged.clone());
+    assert!(!account.is_changed());
+    assert_eq!(account.changes(), None);
+    assert_eq!(account.last_base(), merged);
+
+    account.set_email("rob@example.com".into());
+    account.discard();
+    assert_eq!(account.get(), &merged);
+}
+
+#[derive(update_sync::derive::UpdateSync, update_sync::derive::Patch, Clone, PartialEq, Debug)]
+struct Address {
+    city: String,
+    street: String,
+}
+
+#[derive(update_sync::derive::UpdateSync, update_sync::derive::Patch, Clone, PartialEq, Debug)]
+struct Customer {
+    name: String,
+    points: u32,
+    address: Address,
+}
+
+#[test]
+fn typed_patches() {
+    use serde_json::json;
+
+    let last_base = Customer {
+        name: "Ada".into(),
+        points: 10,
+        address: Address {
+            city: "London".into(),
+            street: "Baker Street".into(),
+        },
+    };
+    let mut set = last_base.clone();
+    set.points = 20;
+    set.address.street = "Fleet Street".into();
+
+    let patch = Customer::diff(&last_base, &set);
+    assert_eq!(
+        patch,
+        CustomerPatch {
+            name: None,
+            points: Some(20),
+            address: Some(AddressPatch {
+                city: None,
+                street: Some("Fleet Street".into()),
+            }),
+        }
+    );
+    assert!(Customer::diff(&set, &set).is_empty());
+
+    // Only changed fields are sent
+    let json = serde_json::to_value(&patch).unwrap();
+    assert_eq!(
+        json,
+        json!({ "points": 20, "address": { "street": "Fleet Street" } })
+    );
+    let patch: CustomerPatch = serde_json::from_value(json).unwrap();
+
+    let mut new_base = last_base.clone();
+    new_base.name = "Ada Lovelace".into();
+    new_base.address.city = "Marylebone".into();
+    let is = patch.apply_to(new_base.clone());
+    assert_eq!(is, UpdateSync::update_sync(last_base, new_base, set));
+    assert_eq!(
+        is,
+        Customer {
+            name: "Ada Lovelace".into(),
+            points: 20,
+            address: Address {
+                city: "Marylebone".into(),
+                street: "Fleet Street".into(),
+            },
+        }
+    );
+}
+
+#[derive(update_sync::derive::UpdateSync, update_sync::derive::Patch, Clone, PartialEq, Debug)]
+struct Member {
+    nickname: Option<String>,
+    tags: BTreeMap<String, u32>,
+    #[update_sync(epsilon = 0.01)]
+    balance: f64,
+    #[update_sync(with = "update_sync::ot::update_sync_text")]
+    bio: String,
+    #[update_sync(skip_change_eq)]
+    visits: u32,
+}
+
+#[test]
+fn typed_patches_match_update_sync() {
+    let last_base = Member {
+        nickname: Some("ada".into()),
+        tags: vec![("a".into(), 1), ("b".into(), 2)].into_iter().collect(),
+        balance: 1.0,
+        bio: "hello".into(),
+        visits: 1,
+    };
+    let set = Member {
+        nickname: None,
+        tags: vec![("b".into(), 3), ("c".into(), 4)].into_iter().collect(),
+        balance: 1.001,
+        bio: "hello world".into(),
+        visits: 5,
+    };
+    let new_base = Member {
+        tags: vec![("a".into(), 1), ("b".into(), 2), ("d".into(), 5)]
+            .into_iter()
+            .collect(),
+        balance: 2.0,
+        bio: "oh hello".into(),
+        visits: 2,
+        ..last_base.clone()
+    };
+
+    // Clearing the nickname survives a round trip, while fields which didn't change are left out
+    let patch = Member::diff(&last_base, &set);
+    let json = serde_json::to_value(&patch).unwrap();
+    assert_eq!(json["nickname"], serde_json::Value::Null);
+    assert_eq!(json["tags"], serde_json::json!({ "b": 3, "c": 4 }));
+    assert!(json.get("balance").is_none());
+    assert!(json.get("visits").is_none());
+    let patch: MemberPatch = serde_json::from_value(json).unwrap();
+    assert_eq!(patch, Member::diff(&last_base, &set));
+
+    let is = patch.apply_to(new_base.clone());
+    assert_eq!(is, UpdateSync::update_sync(last_base, new_base, set));
+    assert_eq!(
+        is,
+        Member {
+            nickname: None,
+            tags: vec![
+                ("a".into(), 1),
+                ("b".into(), 3),
+                ("c".into(), 4),
+                ("d".into(), 5)
+            ]
+            .into_iter()
+            .collect(),
+            balance: 2.0,
+            bio: "oh hello world".into(),
+            visits: 2,
+        }
+    );
+}
+
+#[derive(
+    update_sync::derive::UpdateSync,
+    update_sync::derive::ChangeEq,
+    update_sync::derive::Patch,
+    serde::Serialize,
+    serde::Deserialize,
+    Clone,
+    PartialEq,
+    Debug,
+)]
+enum Plan {
+    Free,
+    Paid { seats: u32 },
+}
+
+#[derive(update_sync::derive::UpdateSync, update_sync::derive::Patch, Clone, PartialEq, Debug)]
+struct Subscription {
+    plan: Plan,
+    limits: (u32, String),
+    timeout: std::time::Duration,
+    last_payment: Result<u32, String>,
+}
+
+#[test]
+fn typed_patches_of_other_types() {
+    use std::time::Duration;
+    use update_sync::{clock::ReplicaId, crdt::GCounter, patch::Patch};
+
+    let last_base = Subscription {
+        plan: Plan::Paid { seats: 1 },
+        limits: (10, "daily".into()),
+        timeout: Duration::from_secs(30),
+        last_payment: Ok(5),
+    };
+    let set = Subscription {
+        plan: Plan::Free,
+        limits: (20, "daily".into()),
+        last_payment: Err("declined".into()),
+        ..last_base.clone()
+    };
+    let new_base = Subscription {
+        limits: (10, "weekly".into()),
+        timeout: Duration::from_secs(60),
+        ..last_base.clone()
+    };
+
+    let patch = Subscription::diff(&last_base, &set);
+    assert_eq!(patch.plan, Some(Plan::Free));
+    assert_eq!(patch.limits, Some((Some(20), None)));
+    assert_eq!(patch.timeout, None);
+    let json = serde_json::to_value(&patch).unwrap();
+    let patch: SubscriptionPatch = serde_json::from_value(json).unwrap();
+    let is = patch.apply_to(new_base.clone());
+    assert_eq!(is, UpdateSync::update_sync(last_base, new_base, set));
+    assert_eq!(is.limits, (20, "weekly".into()));
+
+    // CRDTs are joined into the value the patch is applied to
+    let mut counter = GCounter::new();
+    let mut other = counter.clone();
+    other.increment(ReplicaId(2), 3);
+    let patch = GCounter::diff_patch(&counter, &other).unwrap();
+    counter.increment(ReplicaId(1), 1);
+    counter.apply_patch(patch);
+    assert_eq!(counter.value(), 4);
+}
+
+#[test]
+fn change_observers() {
+    use std::{cell::RefCell, rc::Rc};
+    use update_sync::{observe::Observers, path::Path};
+
+    let drawing = |title: &str, a: Shape, b: Shape| Drawing {
+        title: title.into(),
+        shapes: vec![("a".into(), a), ("b".into(), b)].into_iter().collect(),
+        layers: vec![1],
+    };
+    let last_base = drawing("Plan", Shape::Circle { radius: 1 }, Shape::Square(2));
+    let new_base = drawing("Plan", Shape::Circle { radius: 1 }, Shape::Square(3));
+    let mut set = drawing("Final", Shape::Circle { radius: 4 }, Shape::Square(2));
+    set.shapes.insert("c".into(), Shape::Empty);
+
+    let mut observers = Observers::new();
+    let all = Rc::new(RefCell::new(Vec::new()));
+    let shapes = Rc::new(RefCell::new(Vec::new()));
+    {
+        let all = all.clone();
+        observers.subscribe_all(move |event| {
+            all.borrow_mut().push(format!(
+                "{}: {:?} -> {:?}",
+                event.path, event.old, event.new
+            ))
+        });
+    }
+    let shapes_id = {
+        let shapes = shapes.clone();
+        observers.subscribe(Path::from_pointer("/shapes").unwrap(), move |event| {
+            assert!(!event.contains_prefix);
+            shapes.borrow_mut().push(event.path.to_pointer())
+        })
+    };
+
+    let radius = Rc::new(RefCell::new(Vec::new()));
+    {
+        let radius = radius.clone();
+        observers.subscribe(
+            Path::from_pointer("/shapes/a/radius").unwrap(),
+            move |event| {
+                assert!(event.contains_prefix);
+                radius.borrow_mut().push(event.path.to_pointer())
+            },
+        );
+    }
+
+    let merged = observers.update(last_base.clone(), new_base.clone(), set.clone());
+    assert_eq!(merged, UpdateSync::update_sync(last_base, new_base, set));
+    // The square was only changed by `new_base`, so it isn't reported
+    assert_eq!(
+        *all.borrow(),
+        vec![
+            "title: Some(\"Plan\") -> Some(\"Final\")",
+            "shapes[\"a\"]: Some(Circle { radius: 1 }) -> Some(Circle { radius: 4 })",
+            "shapes[\"c\"]: None -> Some(Empty)",
+        ]
+    );
+
+    assert!(observers.unsubscribe(shapes_id));
+    assert!(!observers.unsubscribe(shapes_id));
+    assert_eq!(*shapes.borrow(), vec!["/shapes/a", "/shapes/c"]);
+    // Map entries are replaced whole, so subscribers inside them hear of the whole entry
+    assert_eq!(*radius.borrow(), vec!["/shapes/a"]);
+}
+
+#[test]
+fn merged_change_observers() {
+    use std::{cell::RefCell, rc::Rc};
+    use update_sync::{observe::Observers, visit::Resolution};
+
+    let note = |body: &str| Note {
+        body: body.into(),
+        author: "Ada".into(),
+    };
+    let mut observers = Observers::new();
+    let events = Rc::new(RefCell::new(Vec::new()));
+    {
+        let events = events.clone();
+        observers.subscribe_all(move |event| {
+            events.borrow_mut().push((
+                event.path.to_pointer(),
+                event.resolution,
+                format!("{:?} -> {:?}", event.old, event.new),
+            ))
+        });
+    }
+
+    // Both sides edited the body, and the merged text is neither side's
+    let merged = observers.update(note("hello"), note("oh hello"), note("hello world"));
+    assert_eq!(merged.body, "oh hello world");
+    assert_eq!(
+        *events.borrow(),
+        vec![(
+            "/body".to_string(),
+            Resolution::Merged,
+            "Some(\"oh hello\") -> None".to_string()
+        )]
+    );
+}
+
 mod demo;